@@ -0,0 +1,5 @@
+ALTER TABLE tx
+  DROP COLUMN input_token_program,
+  DROP COLUMN output_token_program,
+  DROP COLUMN input_transfer_fee,
+  DROP COLUMN output_transfer_fee;
\ No newline at end of file
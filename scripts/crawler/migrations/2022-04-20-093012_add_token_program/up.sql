@@ -0,0 +1,5 @@
+ALTER TABLE tx
+  ADD COLUMN input_token_program VARCHAR NOT NULL DEFAULT 'TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA',
+  ADD COLUMN output_token_program VARCHAR NOT NULL DEFAULT 'TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA',
+  ADD COLUMN input_transfer_fee BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN output_transfer_fee BIGINT NOT NULL DEFAULT 0;
\ No newline at end of file
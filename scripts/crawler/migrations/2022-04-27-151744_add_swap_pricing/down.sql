@@ -0,0 +1,7 @@
+ALTER TABLE tx
+  DROP COLUMN price,
+  DROP COLUMN inverse_price,
+  DROP COLUMN peg_deviation,
+  DROP COLUMN quoted_out_amount,
+  DROP COLUMN minimum_out_amount,
+  DROP COLUMN price_impact_bps;
\ No newline at end of file
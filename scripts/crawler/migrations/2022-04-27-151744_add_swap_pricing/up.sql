@@ -0,0 +1,7 @@
+ALTER TABLE tx
+  ADD COLUMN price DOUBLE PRECISION,
+  ADD COLUMN inverse_price DOUBLE PRECISION,
+  ADD COLUMN peg_deviation DOUBLE PRECISION,
+  ADD COLUMN quoted_out_amount BIGINT,
+  ADD COLUMN minimum_out_amount BIGINT,
+  ADD COLUMN price_impact_bps DOUBLE PRECISION;
\ No newline at end of file
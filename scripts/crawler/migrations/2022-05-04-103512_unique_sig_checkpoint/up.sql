@@ -0,0 +1,11 @@
+-- the websocket and historical crawlers can both have inserted the same transaction
+DELETE FROM tx a USING tx b
+  WHERE a.sig = b.sig AND a.id > b.id;
+
+CREATE UNIQUE INDEX tx_sig_idx ON tx (sig);
+
+CREATE TABLE checkpoint (
+  name VARCHAR PRIMARY KEY,
+  sig VARCHAR NOT NULL,
+  slot BIGINT NOT NULL
+);
@@ -0,0 +1,13 @@
+DROP TABLE swap_legs;
+
+DROP INDEX tx_trader_idx;
+DROP INDEX tx_slot_idx;
+
+ALTER TABLE tx
+  DROP COLUMN slot,
+  DROP COLUMN trader,
+  DROP COLUMN fee,
+  DROP COLUMN success,
+  DROP COLUMN input_decimals,
+  DROP COLUMN output_decimals,
+  DROP COLUMN program_version;
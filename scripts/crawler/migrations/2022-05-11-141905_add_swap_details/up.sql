@@ -0,0 +1,24 @@
+-- swaps stored before this migration have no details, so the columns are nullable
+ALTER TABLE tx
+  ADD COLUMN slot BIGINT,
+  ADD COLUMN trader VARCHAR,
+  ADD COLUMN fee BIGINT,
+  ADD COLUMN success BOOLEAN NOT NULL DEFAULT TRUE,
+  ADD COLUMN input_decimals SMALLINT,
+  ADD COLUMN output_decimals SMALLINT,
+  ADD COLUMN program_version SMALLINT;
+
+CREATE INDEX tx_slot_idx ON tx (slot);
+CREATE INDEX tx_trader_idx ON tx (trader);
+
+CREATE TABLE swap_legs (
+  id SERIAL PRIMARY KEY,
+  sig VARCHAR NOT NULL REFERENCES tx (sig) ON DELETE CASCADE,
+  leg_index SMALLINT NOT NULL,
+  amm_program VARCHAR NOT NULL,
+  input_token VARCHAR NOT NULL,
+  output_token VARCHAR NOT NULL,
+  input_amount BIGINT NOT NULL,
+  output_amount BIGINT NOT NULL,
+  UNIQUE (sig, leg_index)
+);
@@ -0,0 +1,17 @@
+-- ohlcv per token pair, maintained as swaps are stored and rebuilt with rebuild-candles
+CREATE TABLE candles (
+  base_token VARCHAR NOT NULL,
+  quote_token VARCHAR NOT NULL,
+  interval_secs BIGINT NOT NULL,
+  start_time BIGINT NOT NULL,
+  open DOUBLE PRECISION NOT NULL,
+  high DOUBLE PRECISION NOT NULL,
+  low DOUBLE PRECISION NOT NULL,
+  close DOUBLE PRECISION NOT NULL,
+  open_time BIGINT NOT NULL,
+  close_time BIGINT NOT NULL,
+  base_volume DOUBLE PRECISION NOT NULL,
+  quote_volume DOUBLE PRECISION NOT NULL,
+  trades BIGINT NOT NULL,
+  PRIMARY KEY (base_token, quote_token, interval_secs, start_time)
+);
@@ -0,0 +1,4 @@
+-- swaps stored before this migration were all crawled at finalized commitment
+ALTER TABLE tx ADD COLUMN commitment VARCHAR NOT NULL DEFAULT 'finalized';
+
+CREATE INDEX tx_unfinalized_idx ON tx (slot) WHERE commitment <> 'finalized';
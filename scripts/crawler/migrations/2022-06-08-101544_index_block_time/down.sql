@@ -0,0 +1 @@
+DROP INDEX tx_block_time_idx;
@@ -0,0 +1,2 @@
+-- /swaps pages newest first by block time
+CREATE INDEX tx_block_time_idx ON tx (block_time, id);
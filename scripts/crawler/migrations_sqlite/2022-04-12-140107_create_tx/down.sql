@@ -0,0 +1 @@
+DROP TABLE tx;
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE tx (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  sig TEXT NOT NULL,
+  input_token TEXT NOT NULL,
+  output_token TEXT NOT NULL,
+  input_amount BIGINT NOT NULL,
+  output_amount BIGINT NOT NULL,
+  block_time BIGINT NOT NULL
+);
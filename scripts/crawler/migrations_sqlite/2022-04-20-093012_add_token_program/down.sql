@@ -0,0 +1,4 @@
+ALTER TABLE tx DROP COLUMN input_token_program;
+ALTER TABLE tx DROP COLUMN output_token_program;
+ALTER TABLE tx DROP COLUMN input_transfer_fee;
+ALTER TABLE tx DROP COLUMN output_transfer_fee;
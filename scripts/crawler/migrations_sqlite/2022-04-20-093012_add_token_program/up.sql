@@ -0,0 +1,4 @@
+ALTER TABLE tx ADD COLUMN input_token_program TEXT NOT NULL DEFAULT 'TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA';
+ALTER TABLE tx ADD COLUMN output_token_program TEXT NOT NULL DEFAULT 'TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA';
+ALTER TABLE tx ADD COLUMN input_transfer_fee BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE tx ADD COLUMN output_transfer_fee BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+ALTER TABLE tx DROP COLUMN price;
+ALTER TABLE tx DROP COLUMN inverse_price;
+ALTER TABLE tx DROP COLUMN peg_deviation;
+ALTER TABLE tx DROP COLUMN quoted_out_amount;
+ALTER TABLE tx DROP COLUMN minimum_out_amount;
+ALTER TABLE tx DROP COLUMN price_impact_bps;
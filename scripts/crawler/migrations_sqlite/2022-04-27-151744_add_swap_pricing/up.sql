@@ -0,0 +1,6 @@
+ALTER TABLE tx ADD COLUMN price DOUBLE;
+ALTER TABLE tx ADD COLUMN inverse_price DOUBLE;
+ALTER TABLE tx ADD COLUMN peg_deviation DOUBLE;
+ALTER TABLE tx ADD COLUMN quoted_out_amount BIGINT;
+ALTER TABLE tx ADD COLUMN minimum_out_amount BIGINT;
+ALTER TABLE tx ADD COLUMN price_impact_bps DOUBLE;
@@ -0,0 +1,3 @@
+DROP TABLE checkpoint;
+
+DROP INDEX tx_sig_idx;
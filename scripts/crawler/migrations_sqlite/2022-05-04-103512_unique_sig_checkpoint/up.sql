@@ -0,0 +1,10 @@
+-- the websocket and historical crawlers can both have inserted the same transaction
+DELETE FROM tx WHERE id NOT IN (SELECT MIN(id) FROM tx GROUP BY sig);
+
+CREATE UNIQUE INDEX tx_sig_idx ON tx (sig);
+
+CREATE TABLE checkpoint (
+  name TEXT PRIMARY KEY NOT NULL,
+  sig TEXT NOT NULL,
+  slot BIGINT NOT NULL
+);
@@ -0,0 +1,12 @@
+DROP TABLE swap_legs;
+
+DROP INDEX tx_trader_idx;
+DROP INDEX tx_slot_idx;
+
+ALTER TABLE tx DROP COLUMN slot;
+ALTER TABLE tx DROP COLUMN trader;
+ALTER TABLE tx DROP COLUMN fee;
+ALTER TABLE tx DROP COLUMN success;
+ALTER TABLE tx DROP COLUMN input_decimals;
+ALTER TABLE tx DROP COLUMN output_decimals;
+ALTER TABLE tx DROP COLUMN program_version;
@@ -0,0 +1,23 @@
+-- swaps stored before this migration have no details, so the columns are nullable
+ALTER TABLE tx ADD COLUMN slot BIGINT;
+ALTER TABLE tx ADD COLUMN trader TEXT;
+ALTER TABLE tx ADD COLUMN fee BIGINT;
+ALTER TABLE tx ADD COLUMN success BOOLEAN NOT NULL DEFAULT 1;
+ALTER TABLE tx ADD COLUMN input_decimals SMALLINT;
+ALTER TABLE tx ADD COLUMN output_decimals SMALLINT;
+ALTER TABLE tx ADD COLUMN program_version SMALLINT;
+
+CREATE INDEX tx_slot_idx ON tx (slot);
+CREATE INDEX tx_trader_idx ON tx (trader);
+
+CREATE TABLE swap_legs (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  sig TEXT NOT NULL REFERENCES tx (sig) ON DELETE CASCADE,
+  leg_index SMALLINT NOT NULL,
+  amm_program TEXT NOT NULL,
+  input_token TEXT NOT NULL,
+  output_token TEXT NOT NULL,
+  input_amount BIGINT NOT NULL,
+  output_amount BIGINT NOT NULL,
+  UNIQUE (sig, leg_index)
+);
@@ -0,0 +1,17 @@
+-- ohlcv per token pair, maintained as swaps are stored and rebuilt with rebuild-candles
+CREATE TABLE candles (
+  base_token TEXT NOT NULL,
+  quote_token TEXT NOT NULL,
+  interval_secs BIGINT NOT NULL,
+  start_time BIGINT NOT NULL,
+  open DOUBLE NOT NULL,
+  high DOUBLE NOT NULL,
+  low DOUBLE NOT NULL,
+  close DOUBLE NOT NULL,
+  open_time BIGINT NOT NULL,
+  close_time BIGINT NOT NULL,
+  base_volume DOUBLE NOT NULL,
+  quote_volume DOUBLE NOT NULL,
+  trades BIGINT NOT NULL,
+  PRIMARY KEY (base_token, quote_token, interval_secs, start_time)
+);
@@ -0,0 +1,3 @@
+DROP INDEX tx_unfinalized_idx;
+
+ALTER TABLE tx DROP COLUMN commitment;
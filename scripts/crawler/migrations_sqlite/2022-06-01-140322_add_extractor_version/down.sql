@@ -0,0 +1 @@
+ALTER TABLE tx DROP COLUMN extractor_version;
@@ -0,0 +1,2 @@
+-- swaps stored before this migration have no extractor version
+ALTER TABLE tx ADD COLUMN extractor_version SMALLINT;
@@ -0,0 +1,409 @@
+use clap::Args;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Nullable, Text};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use solcrawl::extractors::jupiter_swap::SwapEvent;
+
+use crate::export::parse_pair;
+use crate::storage::conn::DbConnection;
+use crate::storage::models::tx::Tx;
+use crate::storage::schema::tx;
+use crate::storage::swaps::load_swap_events;
+use crate::with_conn;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Address the api listens on
+    #[clap(long, env = "API_LISTEN", default_value = "127.0.0.1:8080")]
+    pub listen: String,
+
+    /// Threads answering requests, each with its own database connection
+    #[clap(long, default_value_t = 4)]
+    pub workers: usize,
+}
+
+// ApiError is turned into a json error body with the status code
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: 400,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: 404,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Box<dyn Error>> for ApiError {
+    fn from(e: Box<dyn Error>) -> Self {
+        Self {
+            status: 500,
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        Self {
+            status: 500,
+            message: e.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SwapPage {
+    swaps: Vec<SwapEvent>,
+    // pass as cursor to get the next page, missing on the last page
+    next_cursor: Option<String>,
+}
+
+#[derive(QueryableByName, Serialize)]
+struct PairVolume {
+    #[sql_type = "Text"]
+    input_token: String,
+    #[sql_type = "Text"]
+    output_token: String,
+    #[sql_type = "BigInt"]
+    swaps: i64,
+    #[sql_type = "Double"]
+    input_volume: f64,
+    #[sql_type = "Double"]
+    output_volume: f64,
+    #[sql_type = "Nullable<BigInt>"]
+    first_block_time: Option<i64>,
+    #[sql_type = "Nullable<BigInt>"]
+    last_block_time: Option<i64>,
+}
+
+// serves the read only json api until the process is stopped:
+//   GET /swaps                newest first, filtered by pair, trader, from, to, start_slot,
+//                             end_slot and min_amount (raw input amount), paged with limit and cursor
+//   GET /swaps/<sig>          one swap with its route legs
+//   GET /pairs/volume         swaps and raw volume per swap direction, filtered by from and to
+// TODO dont print to std out - use a logger
+pub fn serve(database_url: &str, args: ServeArgs) -> Result<(), Box<dyn Error>> {
+    if args.workers == 0 {
+        return Err("workers must be at least 1".into());
+    }
+    let server = Arc::new(
+        Server::http(args.listen.as_str())
+            .map_err(|e| format!("could not listen on {}: {}", args.listen, e))?,
+    );
+    println!("serving the api on http://{}", args.listen);
+
+    let mut handles = Vec::new();
+    for _ in 0..args.workers {
+        // diesel connections can not be shared between threads
+        let conn = DbConnection::establish(database_url)?;
+        let server = server.clone();
+        handles.push(std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle_request(&conn, request);
+            }
+        }));
+    }
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| "api worker panicked".to_string())?;
+    }
+    Ok(())
+}
+
+fn handle_request(conn: &DbConnection, request: Request) {
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+    let params = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect::<HashMap<String, String>>();
+
+    let result = if request.method() != &Method::Get {
+        Err(ApiError {
+            status: 405,
+            message: "only GET is supported".to_string(),
+        })
+    } else {
+        route(conn, &path, &params)
+    };
+
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(e) => {
+            if e.status == 500 {
+                println!("api err - {} - {}", path, e.message);
+            }
+            let body = serde_json::json!({ "error": e.message }).to_string();
+            (e.status, body)
+        }
+    };
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        println!("api respond err - {}", e);
+    }
+}
+
+fn route(
+    conn: &DbConnection,
+    path: &str,
+    params: &HashMap<String, String>,
+) -> Result<String, ApiError> {
+    let path = path.trim_end_matches('/');
+    let body = match path {
+        "/swaps" => serde_json::to_string(&list_swaps(conn, params)?),
+        "/pairs/volume" => serde_json::to_string(&pair_volumes(conn, params)?),
+        _ => match path.strip_prefix("/swaps/") {
+            Some(sig) => serde_json::to_string(&get_swap(conn, sig)?),
+            None => return Err(ApiError::not_found(format!("no route {}", path))),
+        },
+    };
+    body.map_err(|e| ApiError::from(Box::new(e) as Box<dyn Error>))
+}
+
+fn list_swaps(conn: &DbConnection, params: &HashMap<String, String>) -> Result<SwapPage, ApiError> {
+    let limit = int_param(params, "limit")?.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let cursor = match params.get("cursor") {
+        Some(cursor) => Some(parse_cursor(cursor).map_err(ApiError::bad_request)?),
+        None => None,
+    };
+    let pair = match params.get("pair") {
+        Some(pair) => Some(parse_pair(pair).map_err(ApiError::bad_request)?),
+        None => None,
+    };
+    let from = int_param(params, "from")?;
+    let to = int_param(params, "to")?;
+    let start_slot = int_param(params, "start_slot")?;
+    let end_slot = int_param(params, "end_slot")?;
+    let min_amount = int_param(params, "min_amount")?;
+    let trader = params.get("trader");
+
+    // one extra row tells whether there is a next page
+    let mut txs = with_conn!(conn, c => {
+        let mut query = tx::table
+            .order((tx::block_time.desc(), tx::id.desc()))
+            .limit(limit + 1)
+            .into_boxed();
+        if let Some((block_time, id)) = cursor {
+            query = query.filter(
+                tx::block_time
+                    .lt(block_time)
+                    .or(tx::block_time.eq(block_time).and(tx::id.lt(id))),
+            );
+        }
+        if let Some((a, b)) = &pair {
+            query = query.filter(
+                (tx::input_token.eq(a).and(tx::output_token.eq(b)))
+                    .or(tx::input_token.eq(b).and(tx::output_token.eq(a))),
+            );
+        }
+        if let Some(trader) = trader {
+            query = query.filter(tx::trader.eq(trader));
+        }
+        if let Some(from) = from {
+            query = query.filter(tx::block_time.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(tx::block_time.lt(to));
+        }
+        if let Some(start_slot) = start_slot {
+            query = query.filter(tx::slot.ge(start_slot));
+        }
+        if let Some(end_slot) = end_slot {
+            query = query.filter(tx::slot.le(end_slot));
+        }
+        if let Some(min_amount) = min_amount {
+            query = query.filter(tx::input_amount.ge(min_amount));
+        }
+        query.load::<Tx>(c)?
+    });
+
+    let next_cursor = if txs.len() as i64 > limit {
+        txs.truncate(limit as usize);
+        txs.last().map(|t| format!("{}_{}", t.block_time, t.id))
+    } else {
+        None
+    };
+    let swaps = load_swap_events(conn, txs)?;
+    Ok(SwapPage { swaps, next_cursor })
+}
+
+fn get_swap(conn: &DbConnection, sig: &str) -> Result<SwapEvent, ApiError> {
+    let found = with_conn!(conn, c => tx::table
+        .filter(tx::sig.eq(sig))
+        .first::<Tx>(c)
+        .optional()?);
+    let found = found.ok_or_else(|| ApiError::not_found(format!("no swap {}", sig)))?;
+    let mut swaps = load_swap_events(conn, vec![found])?;
+    Ok(swaps.remove(0))
+}
+
+fn pair_volumes(
+    conn: &DbConnection,
+    params: &HashMap<String, String>,
+) -> Result<Vec<PairVolume>, ApiError> {
+    let from = int_param(params, "from")?.unwrap_or(i64::MIN);
+    let to = int_param(params, "to")?.unwrap_or(i64::MAX);
+    // the bounds are parsed integers, so formatting them into the sql is safe and keeps
+    // the query the same for both backends, which use different placeholders
+    let query = format!(
+        "SELECT input_token, output_token, COUNT(*) AS swaps, \
+         SUM(CAST(input_amount AS DOUBLE PRECISION)) AS input_volume, \
+         SUM(CAST(output_amount AS DOUBLE PRECISION)) AS output_volume, \
+         MIN(block_time) AS first_block_time, MAX(block_time) AS last_block_time \
+         FROM tx WHERE block_time >= {} AND block_time < {} \
+         GROUP BY input_token, output_token ORDER BY swaps DESC",
+        from, to
+    );
+    let volumes = with_conn!(conn, c => diesel::sql_query(query).load::<PairVolume>(c)?);
+    Ok(volumes)
+}
+
+// the block time and id of the last swap of a page, swaps with the same block time are
+// ordered by id
+fn parse_cursor(cursor: &str) -> Result<(i64, i32), String> {
+    let invalid = || format!("invalid cursor {}", cursor);
+    let (block_time, id) = cursor.split_once('_').ok_or_else(invalid)?;
+    Ok((
+        block_time.parse().map_err(|_| invalid())?,
+        id.parse().map_err(|_| invalid())?,
+    ))
+}
+
+fn int_param(params: &HashMap<String, String>, name: &str) -> Result<Option<i64>, ApiError> {
+    match params.get(name) {
+        Some(value) => value
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| ApiError::bad_request(format!("{} must be an integer", name))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_txs::DatabaseSink;
+    use crate::storage::test_util::{swap, temp_db};
+    use serde_json::Value;
+    use solcrawl::sinks::{Checkpoint, Sink};
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn get(conn: &DbConnection, path: &str, pairs: &[(&str, &str)]) -> Result<Value, u16> {
+        route(conn, path, &params(pairs))
+            .map(|body| serde_json::from_str(&body).unwrap())
+            .map_err(|e| e.status)
+    }
+
+    #[test]
+    fn parses_cursors() {
+        assert_eq!(parse_cursor("1650000000_42"), Ok((1650000000, 42)));
+        assert_eq!(parse_cursor("-5_1"), Ok((-5, 1)));
+        for cursor in ["", "1650000000", "a_1", "1_b", "1_2_3"] {
+            assert_eq!(
+                parse_cursor(cursor),
+                Err(format!("invalid cursor {}", cursor))
+            );
+        }
+    }
+
+    #[test]
+    fn integer_params_are_validated() {
+        let values = params(&[("from", "100"), ("to", "soon")]);
+        assert_eq!(int_param(&values, "from").ok(), Some(Some(100)));
+        assert_eq!(int_param(&values, "missing").ok(), Some(None));
+        let err = int_param(&values, "to").err().unwrap();
+        assert_eq!(
+            (err.status, err.message.as_str()),
+            (400, "to must be an integer")
+        );
+    }
+
+    #[test]
+    fn pages_through_swaps_with_the_cursor() {
+        let (_dir, conn) = temp_db();
+        let mut sink = DatabaseSink::new(vec![], conn, false);
+        let swaps = [("a", 100), ("b", 200), ("c", 200), ("d", 200), ("e", 300)]
+            .iter()
+            .map(|(sig, block_time)| swap(sig, *block_time, 1_000_000, 999_000))
+            .collect::<Vec<SwapEvent>>();
+        sink.write_batch(&swaps).unwrap();
+        sink.checkpoint(&Checkpoint {
+            name: "test".to_string(),
+            sig: "e".to_string(),
+            slot: 300,
+        })
+        .unwrap();
+        let conn = sink.into_conn();
+
+        let mut pages = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut pairs = vec![("limit", "2")];
+            if let Some(cursor) = &cursor {
+                pairs.push(("cursor", cursor.as_str()));
+            }
+            let page = get(&conn, "/swaps", &pairs).unwrap();
+            pages.push(
+                page["swaps"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|swap| swap["sig"].as_str().unwrap().to_string())
+                    .collect::<Vec<String>>(),
+            );
+            match page["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+        // newest first, swaps of one block time by id
+        assert_eq!(pages, vec![vec!["e", "d"], vec!["c", "b"], vec!["a"]]);
+
+        let page = get(&conn, "/swaps", &[("from", "150"), ("to", "300")]).unwrap();
+        assert_eq!(page["swaps"].as_array().unwrap().len(), 3);
+        assert_eq!(page["next_cursor"], Value::Null);
+        assert_eq!(get(&conn, "/swaps/c", &[]).unwrap()["sig"], "c");
+
+        assert_eq!(get(&conn, "/swaps", &[("limit", "0")]), Err(400));
+        assert_eq!(get(&conn, "/swaps", &[("limit", "1001")]), Err(400));
+        assert_eq!(get(&conn, "/swaps", &[("cursor", "300")]), Err(400));
+        assert_eq!(get(&conn, "/swaps/missing", &[]), Err(404));
+        assert_eq!(get(&conn, "/candles", &[]), Err(404));
+    }
+}
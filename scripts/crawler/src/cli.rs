@@ -0,0 +1,232 @@
+use clap::{Args, Parser, Subcommand};
+use solana_transaction_status::UiTransactionEncoding;
+use solcrawl::candles::CandleInterval;
+use solcrawl::monitors::depeg::{DEFAULT_MIN_VOLUME, DEFAULT_SUSTAIN_SECS, DEFAULT_WINDOW_SECS};
+use std::path::PathBuf;
+
+use crate::api::ServeArgs;
+use crate::export::ExportArgs;
+use crate::verify::VerifyArgs;
+
+const RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+const WS_URL: &str = "wss://api.mainnet-beta.solana.com";
+
+const JUPITER_PROGRAM: &str = "JUP2jxvXaqu7NQY1GmNF4m1vodw12LVXYxbFL2uJvfo";
+
+const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const USDT_MINT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+const UST_MINT: &str = "9vMJfxuKxXBoEa7rM12mYLMwTacLMLDJqHozw96WQL8i";
+
+pub const ENCODINGS: [&str; 4] = ["json", "json-parsed", "base58", "base64"];
+
+// crawling jupiter for stable swaps
+#[derive(Parser)]
+#[clap(
+    name = "crawler",
+    about = "Crawls jupiter swaps between approved tokens"
+)]
+pub struct Cli {
+    #[clap(flatten)]
+    pub options: CrawlOptions,
+
+    // run is the default so the crawler can be started without arguments
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Crawl new swaps live and backfill older swaps at the same time
+    Run,
+    /// Crawl swaps older than the oldest stored swap, or a slot range
+    Backfill(BackfillArgs),
+    /// Only crawl new swaps as they happen
+    Live,
+    /// Write stored swaps to parquet or csv files
+    Export(ExportArgs),
+    /// Print counts of the stored swaps
+    Stats,
+    /// Run pending database migrations and exit
+    Migrate,
+    /// Run every pipeline described in a toml or yaml config file
+    Pipelines(PipelinesArgs),
+    /// Serve a read only http json api over the stored swaps
+    Serve(ServeArgs),
+    /// Recompute the candles of every stored swap
+    RebuildCandles,
+    /// Finalize or remove the swaps stored below finalized commitment
+    Reconcile,
+    /// Compare the stored swaps of a slot or time range with the cluster
+    ///
+    /// Failed extractions are not recorded in a dead letter store, so every listed
+    /// transaction is fetched and extracted again to find the missing swaps.
+    Verify(VerifyArgs),
+}
+
+// options shared by every subcommand, the environment variables keep older setups working
+#[derive(Args)]
+pub struct CrawlOptions {
+    /// postgres:// url, or the path of a sqlite database
+    #[clap(long, env = "DATABASE_URL", global = true)]
+    pub database_url: Option<String>,
+
+    #[clap(long, env = "RPC_URL", default_value = RPC_URL, global = true)]
+    pub rpc_url: String,
+
+    #[clap(long, env = "WS_URL", default_value = WS_URL, global = true)]
+    pub ws_url: String,
+
+    /// Program whose transactions are crawled, can be repeated
+    #[clap(long = "program", default_value = JUPITER_PROGRAM, global = true)]
+    pub programs: Vec<String>,
+
+    /// Mint swaps are stored for, both sides of a swap have to be listed, can be repeated
+    #[clap(long = "token", default_values = &[USDC_MINT, USDT_MINT, UST_MINT], global = true)]
+    pub tokens: Vec<String>,
+
+    /// Pause before every transaction fetch, for rate limited rpc nodes
+    #[clap(long, global = true)]
+    pub sleep_ms: Option<u64>,
+
+    /// Encoding transactions are fetched in, extraction reads all of them
+    #[clap(long, env = "ENCODING", default_value = "json", possible_values = &ENCODINGS, global = true)]
+    pub encoding: String,
+
+    /// Swaps written to the database per transaction
+    #[clap(long, env = "BATCH_SIZE", default_value_t = 100, global = true)]
+    pub batch_size: usize,
+
+    /// Longest a swap waits in a partial batch
+    #[clap(long, env = "FLUSH_INTERVAL_MS", default_value_t = 2000, global = true)]
+    pub flush_interval_ms: u64,
+
+    /// Overwrite stored swaps instead of keeping the first insert, for re-extracting
+    #[clap(long, global = true)]
+    pub update_existing: bool,
+
+    /// Keep fetched transactions so extraction can be re-run by replaying them
+    #[clap(long, env = "ARCHIVE_DIR", global = true)]
+    pub archive_dir: Option<PathBuf>,
+
+    /// Archive every fetched transaction (pre) or only swaps between approved tokens (post)
+    #[clap(long, env = "ARCHIVE_STAGE", default_value = "pre", possible_values = &["pre", "post"], global = true)]
+    pub archive_stage: String,
+
+    /// Keep fetched finalized transactions on disk, so restarts and re-extraction do not fetch them again
+    #[clap(long, env = "TX_CACHE_DIR", global = true)]
+    pub tx_cache_dir: Option<PathBuf>,
+
+    /// Size the transaction cache is kept under by evicting the least recently used transactions
+    #[clap(long, env = "TX_CACHE_MAX_MB", default_value_t = 4096, global = true)]
+    pub tx_cache_max_mb: u64,
+
+    /// OHLCV interval maintained as swaps are stored (1m, 5m, 1h or 1d), can be repeated
+    #[clap(long = "candle-interval", default_values = &["1m", "5m", "1h", "1d"], global = true)]
+    pub candle_intervals: Vec<CandleInterval>,
+
+    /// Commitment live swaps are crawled at, below finalized they are stored as confirmed and
+    /// corrected once the cluster finalizes or drops them
+    #[clap(long, env = "COMMITMENT", default_value = "finalized", possible_values = &["processed", "confirmed", "finalized"], global = true)]
+    pub commitment: String,
+
+    /// Seconds between checks of the swaps stored below finalized
+    #[clap(long, default_value_t = 30, global = true)]
+    pub finality_interval_secs: u64,
+
+    /// Do not maintain candles while crawling
+    #[clap(long, global = true)]
+    pub no_candles: bool,
+
+    /// Watch the stable pairs of the approved tokens for depegs while crawling
+    #[clap(long, env = "DEPEG_MONITOR", global = true)]
+    pub depeg_monitor: bool,
+
+    /// Deviation from 1.0 of the rolling price that raises a depeg alert, can be repeated for severities
+    #[clap(long = "depeg-threshold", default_values = &["0.005", "0.02"], global = true)]
+    pub depeg_thresholds: Vec<f64>,
+
+    /// Seconds of swaps the rolling volume weighted price covers
+    #[clap(long, default_value_t = DEFAULT_WINDOW_SECS, global = true)]
+    pub depeg_window_secs: i64,
+
+    /// Seconds a pair has to stay past a threshold before it is alerted
+    #[clap(long, default_value_t = DEFAULT_SUSTAIN_SECS, global = true)]
+    pub depeg_sustain_secs: i64,
+
+    /// Smallest base token volume in the window for the price to count
+    #[clap(long, default_value_t = DEFAULT_MIN_VOLUME, global = true)]
+    pub depeg_min_volume: f64,
+
+    /// Post depeg alerts as json to this url
+    #[clap(long, env = "ALERT_WEBHOOK_URL", global = true)]
+    pub alert_webhook: Option<String>,
+
+    /// Append depeg alerts as json lines to this file
+    #[clap(long, env = "ALERT_FILE", global = true)]
+    pub alert_file: Option<PathBuf>,
+
+    /// Serve stored swaps live as server-sent events on this address
+    #[clap(long, env = "STREAM_LISTEN", global = true)]
+    pub stream_listen: Option<String>,
+
+    /// Recent swaps kept to replay to stream clients when they connect
+    #[clap(long, default_value_t = 100, global = true)]
+    pub stream_replay: usize,
+
+    /// Solana token list json used for token symbols
+    #[clap(long, env = "TOKEN_LIST_PATH", global = true)]
+    pub token_list: Option<String>,
+}
+
+#[derive(Args)]
+pub struct BackfillArgs {
+    /// Crawl signatures older than this one instead of the oldest stored swap
+    #[clap(long, conflicts_with_all = &["start-slot", "end-slot"])]
+    pub before_sig: Option<String>,
+
+    /// Walk every block from this slot instead of listing signatures
+    #[clap(long)]
+    pub start_slot: Option<u64>,
+
+    /// Last slot of the block walk, follows the cluster when left out
+    #[clap(long, requires = "start-slot")]
+    pub end_slot: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct PipelinesArgs {
+    /// toml or yaml file describing the pipelines
+    #[clap(long)]
+    pub config: PathBuf,
+
+    /// Only validate the config and exit
+    #[clap(long)]
+    pub check: bool,
+}
+
+impl CrawlOptions {
+    pub fn candle_intervals(&self) -> Vec<CandleInterval> {
+        if self.no_candles {
+            return vec![];
+        }
+        self.candle_intervals.clone()
+    }
+
+    pub fn encoding(&self) -> UiTransactionEncoding {
+        parse_encoding(&self.encoding).unwrap_or(UiTransactionEncoding::Json)
+    }
+}
+
+pub fn parse_encoding(encoding: &str) -> Result<UiTransactionEncoding, String> {
+    match encoding {
+        "json" => Ok(UiTransactionEncoding::Json),
+        "json-parsed" => Ok(UiTransactionEncoding::JsonParsed),
+        "base58" => Ok(UiTransactionEncoding::Base58),
+        "base64" => Ok(UiTransactionEncoding::Base64),
+        _ => Err(format!(
+            "encoding {} is not one of {}",
+            encoding,
+            ENCODINGS.join(", ")
+        )),
+    }
+}
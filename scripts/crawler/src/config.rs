@@ -0,0 +1,782 @@
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use solcrawl::archive::ArchiveFormat;
+use solcrawl::candles::{CandleInterval, ALL_INTERVALS};
+use solcrawl::monitors::depeg::{
+    DEFAULT_MIN_VOLUME, DEFAULT_SUSTAIN_SECS, DEFAULT_THRESHOLDS, DEFAULT_WINDOW_SECS,
+};
+use solcrawl::sinks::export::{ExportFormat, DEFAULT_ROW_GROUP_SIZE};
+use solcrawl::sinks::webhook::WebhookMode;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cli::parse_encoding;
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 2000;
+const DEFAULT_ARCHIVE_SEGMENT_BYTES: u64 = 256 * 1024 * 1024;
+const DEFAULT_WEBHOOK_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_WEBHOOK_MAX_RETRIES: u32 = 5;
+const DEFAULT_WEBHOOK_BACKOFF_MS: u64 = 1000;
+const DEFAULT_WEBHOOK_MAX_RETRY_SECS: u64 = 60;
+const DEFAULT_STREAM_REPLAY: usize = 100;
+const DEFAULT_FINALITY_INTERVAL_SECS: u64 = 30;
+
+const COMMITMENTS: [&str; 3] = ["processed", "confirmed", "finalized"];
+
+// Config describes every pipeline run by the pipelines subcommand, urls left out of a
+// pipeline fall back to the ones given here and then to the command line
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub database_url: Option<String>,
+    pub rpc_url: Option<String>,
+    pub ws_url: Option<String>,
+    // one transaction cache is shared by the crawlers of every pipeline
+    pub tx_cache_dir: Option<PathBuf>,
+    pub tx_cache_max_mb: Option<u64>,
+    // encoding every crawler fetches transactions in, see --encoding
+    pub encoding: Option<String>,
+    pub pipelines: Vec<PipelineConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    // also the name of the pipelines checkpoint, so it has to be unique
+    pub name: String,
+    pub sources: Vec<SourceConfig>,
+    #[serde(default)]
+    pub filters: Vec<FilterConfig>,
+    pub extractor: ExtractorConfig,
+    pub sinks: Vec<SinkConfig>,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+}
+
+// sleep_ms pauses before every transaction fetch, for rate limited rpc nodes
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SourceConfig {
+    // below finalized commitment swaps are stored as confirmed, and the pipelines
+    // databases are corrected once the cluster finalizes or drops them
+    Live {
+        programs: Vec<String>,
+        rpc_url: Option<String>,
+        ws_url: Option<String>,
+        sleep_ms: Option<u64>,
+        #[serde(default = "default_commitment")]
+        commitment: String,
+        #[serde(default = "default_finality_interval_secs")]
+        finality_interval_secs: u64,
+    },
+    Historical {
+        programs: Vec<String>,
+        rpc_url: Option<String>,
+        // where the first run starts, later runs continue every program below its checkpoint
+        before_sig: Option<String>,
+        sleep_ms: Option<u64>,
+    },
+    Blocks {
+        programs: Vec<String>,
+        rpc_url: Option<String>,
+        start_slot: u64,
+        end_slot: Option<u64>,
+        sleep_ms: Option<u64>,
+    },
+    Replay {
+        paths: Vec<PathBuf>,
+        #[serde(default)]
+        programs: Vec<String>,
+        speed: Option<f64>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FilterConfig {
+    // keeps jupiter swaps where both sides are one of the tokens
+    SwapTokens { tokens: Vec<String> },
+    // drops transactions that failed on chain
+    Failed,
+    // keeps transactions referencing one of the addresses
+    Mentions { addresses: Vec<String> },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ExtractorConfig {
+    JupiterSwap {
+        #[serde(default)]
+        stable_tokens: Vec<String>,
+        token_list: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SinkConfig {
+    // stores swaps between the approved tokens, every swap when none are listed,
+    // and maintains their candles unless candle_intervals is empty
+    Database {
+        database_url: Option<String>,
+        #[serde(default)]
+        approved_tokens: Vec<String>,
+        #[serde(default)]
+        update_existing: bool,
+        #[serde(default = "default_candle_intervals")]
+        candle_intervals: Vec<CandleInterval>,
+    },
+    Jsonl {
+        path: PathBuf,
+    },
+    Stdout,
+    Export {
+        dir: PathBuf,
+        format: ExportFormat,
+        #[serde(default = "default_row_group_size")]
+        row_group_size: usize,
+    },
+    // posts swaps, or the raw transactions that passed the filters, see WebhookSink
+    Webhook {
+        url: String,
+        #[serde(default = "default_webhook_items")]
+        items: WebhookItems,
+        #[serde(default = "default_webhook_mode")]
+        mode: WebhookMode,
+        // the hmac secret, or the environment variable holding it
+        secret: Option<String>,
+        secret_env: Option<String>,
+        #[serde(default = "default_webhook_timeout_ms")]
+        timeout_ms: u64,
+        #[serde(default = "default_webhook_max_retries")]
+        max_retries: u32,
+        #[serde(default = "default_webhook_backoff_ms")]
+        backoff_ms: u64,
+        // retries stop once a batch has waited this long on them
+        #[serde(default = "default_webhook_max_retry_secs")]
+        max_retry_secs: u64,
+        spool_dir: Option<PathBuf>,
+    },
+    // serves the swaps as server-sent events once the sinks before it stored them
+    Stream {
+        listen: String,
+        #[serde(default = "default_stream_replay")]
+        replay: usize,
+    },
+    // alerts when a stable pair trades away from peg, see DepegMonitor
+    DepegMonitor {
+        stable_tokens: Vec<String>,
+        #[serde(default = "default_depeg_thresholds")]
+        thresholds: Vec<f64>,
+        #[serde(default = "default_depeg_window_secs")]
+        window_secs: i64,
+        #[serde(default = "default_depeg_sustain_secs")]
+        sustain_secs: i64,
+        #[serde(default = "default_depeg_min_volume")]
+        min_volume: f64,
+        #[serde(default = "default_notifiers")]
+        notifiers: Vec<NotifierConfig>,
+    },
+    // keeps the raw transactions that passed the filters, for replaying
+    Archive {
+        dir: PathBuf,
+        #[serde(default = "default_archive_format")]
+        format: ArchiveFormat,
+        #[serde(default = "default_true")]
+        compress: bool,
+        #[serde(default = "default_archive_segment_bytes")]
+        max_segment_bytes: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookItems {
+    Swaps,
+    Transactions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum NotifierConfig {
+    Log,
+    Webhook {
+        url: String,
+        #[serde(default = "default_webhook_timeout_ms")]
+        timeout_ms: u64,
+    },
+    File {
+        path: PathBuf,
+    },
+}
+
+fn default_batch_size() -> usize {
+    DEFAULT_BATCH_SIZE
+}
+
+fn default_flush_interval_ms() -> u64 {
+    DEFAULT_FLUSH_INTERVAL_MS
+}
+
+fn default_candle_intervals() -> Vec<CandleInterval> {
+    ALL_INTERVALS.to_vec()
+}
+
+fn default_depeg_thresholds() -> Vec<f64> {
+    DEFAULT_THRESHOLDS.to_vec()
+}
+
+fn default_depeg_window_secs() -> i64 {
+    DEFAULT_WINDOW_SECS
+}
+
+fn default_depeg_sustain_secs() -> i64 {
+    DEFAULT_SUSTAIN_SECS
+}
+
+fn default_depeg_min_volume() -> f64 {
+    DEFAULT_MIN_VOLUME
+}
+
+fn default_notifiers() -> Vec<NotifierConfig> {
+    vec![NotifierConfig::Log]
+}
+
+fn default_webhook_timeout_ms() -> u64 {
+    DEFAULT_WEBHOOK_TIMEOUT_MS
+}
+
+fn default_commitment() -> String {
+    "finalized".to_string()
+}
+
+fn default_finality_interval_secs() -> u64 {
+    DEFAULT_FINALITY_INTERVAL_SECS
+}
+
+fn default_stream_replay() -> usize {
+    DEFAULT_STREAM_REPLAY
+}
+
+fn default_webhook_items() -> WebhookItems {
+    WebhookItems::Swaps
+}
+
+fn default_webhook_mode() -> WebhookMode {
+    WebhookMode::Batch
+}
+
+fn default_webhook_max_retries() -> u32 {
+    DEFAULT_WEBHOOK_MAX_RETRIES
+}
+
+fn default_webhook_backoff_ms() -> u64 {
+    DEFAULT_WEBHOOK_BACKOFF_MS
+}
+
+fn default_webhook_max_retry_secs() -> u64 {
+    DEFAULT_WEBHOOK_MAX_RETRY_SECS
+}
+
+fn default_row_group_size() -> usize {
+    DEFAULT_ROW_GROUP_SIZE
+}
+
+fn default_archive_format() -> ArchiveFormat {
+    ArchiveFormat::Jsonl
+}
+
+fn default_archive_segment_bytes() -> u64 {
+    DEFAULT_ARCHIVE_SEGMENT_BYTES
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Config {
+    // the format is picked by the extension, .toml or .yaml / .yml
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let config = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&contents).map_err(|e| e.to_string())
+            }
+            _ => Err("expected a .toml, .yaml or .yml file".to_string()),
+        };
+        config.map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    // checks everything that can be checked before connecting anywhere, so a typo in
+    // the last pipeline does not surface after the others already started
+    pub fn validate(&self, fallback_database_url: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if self.pipelines.is_empty() {
+            return Err("config has no pipelines".into());
+        }
+        if self.tx_cache_max_mb == Some(0) {
+            return Err("tx_cache_max_mb must be at least 1".into());
+        }
+        if let Some(encoding) = &self.encoding {
+            parse_encoding(encoding)?;
+        }
+
+        // two stream sinks can not share an address
+        let mut listens = HashSet::new();
+        for pipeline in &self.pipelines {
+            for sink in &pipeline.sinks {
+                if let SinkConfig::Stream { listen, .. } = sink {
+                    if !listens.insert(listen.as_str()) {
+                        return Err(format!(
+                            "pipeline {}: stream listen address {} is used more than once",
+                            pipeline.name, listen
+                        )
+                        .into());
+                    }
+                }
+            }
+        }
+
+        let mut names = HashSet::new();
+        for (i, pipeline) in self.pipelines.iter().enumerate() {
+            if pipeline.name.trim().is_empty() {
+                return Err(format!("pipelines[{}]: name is empty", i).into());
+            }
+            if !names.insert(pipeline.name.as_str()) {
+                return Err(format!(
+                    "pipeline {}: name is used more than once, names are checkpoint names",
+                    pipeline.name
+                )
+                .into());
+            }
+            let database_url = self.database_url.as_deref().or(fallback_database_url);
+            pipeline
+                .validate(database_url)
+                .map_err(|e| format!("pipeline {}: {}", pipeline.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl PipelineConfig {
+    fn validate(&self, database_url: Option<&str>) -> Result<(), String> {
+        if self.batch_size == 0 {
+            return Err("batch_size must be at least 1".to_string());
+        }
+        if self.sources.is_empty() {
+            return Err("no sources".to_string());
+        }
+        if self.sinks.is_empty() {
+            return Err("no sinks".to_string());
+        }
+
+        for (i, source) in self.sources.iter().enumerate() {
+            source
+                .validate()
+                .map_err(|e| format!("sources[{}]: {}", i, e))?;
+        }
+        for (i, filter) in self.filters.iter().enumerate() {
+            filter
+                .validate()
+                .map_err(|e| format!("filters[{}]: {}", i, e))?;
+        }
+        match &self.extractor {
+            ExtractorConfig::JupiterSwap {
+                stable_tokens,
+                token_list,
+            } => {
+                check_pubkeys("stable_tokens", stable_tokens)
+                    .map_err(|e| format!("extractor: {}", e))?;
+                if let Some(path) = token_list {
+                    if !Path::new(path).is_file() {
+                        return Err(format!("extractor: token_list {} does not exist", path));
+                    }
+                }
+            }
+        }
+        for (i, sink) in self.sinks.iter().enumerate() {
+            sink.validate(database_url)
+                .map_err(|e| format!("sinks[{}]: {}", i, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl SourceConfig {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            SourceConfig::Live {
+                programs,
+                commitment,
+                finality_interval_secs,
+                ..
+            } => {
+                if programs.is_empty() {
+                    return Err("programs is empty".to_string());
+                }
+                if !COMMITMENTS.contains(&commitment.as_str()) {
+                    return Err(format!(
+                        "commitment {} is not one of {}",
+                        commitment,
+                        COMMITMENTS.join(", ")
+                    ));
+                }
+                if *finality_interval_secs == 0 {
+                    return Err("finality_interval_secs must be at least 1".to_string());
+                }
+                check_pubkeys("programs", programs)
+            }
+            SourceConfig::Historical { programs, .. } => {
+                if programs.is_empty() {
+                    return Err("programs is empty".to_string());
+                }
+                check_pubkeys("programs", programs)
+            }
+            SourceConfig::Blocks {
+                programs,
+                start_slot,
+                end_slot,
+                ..
+            } => {
+                if programs.is_empty() {
+                    return Err("programs is empty".to_string());
+                }
+                if let Some(end_slot) = end_slot {
+                    if end_slot < start_slot {
+                        return Err(format!(
+                            "end_slot {} is before start_slot {}",
+                            end_slot, start_slot
+                        ));
+                    }
+                }
+                check_pubkeys("programs", programs)
+            }
+            SourceConfig::Replay {
+                paths,
+                programs,
+                speed,
+            } => {
+                if paths.is_empty() {
+                    return Err("paths is empty".to_string());
+                }
+                for path in paths {
+                    if !path.exists() {
+                        return Err(format!("{} does not exist", path.display()));
+                    }
+                }
+                if let Some(speed) = speed {
+                    if *speed <= 0.0 {
+                        return Err("speed must be positive".to_string());
+                    }
+                }
+                check_pubkeys("programs", programs)
+            }
+        }
+    }
+}
+
+impl FilterConfig {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            FilterConfig::SwapTokens { tokens } => {
+                if tokens.is_empty() {
+                    return Err("tokens is empty".to_string());
+                }
+                check_pubkeys("tokens", tokens)
+            }
+            FilterConfig::Failed => Ok(()),
+            FilterConfig::Mentions { addresses } => {
+                if addresses.is_empty() {
+                    return Err("addresses is empty".to_string());
+                }
+                check_pubkeys("addresses", addresses)
+            }
+        }
+    }
+}
+
+impl SinkConfig {
+    fn validate(&self, fallback_database_url: Option<&str>) -> Result<(), String> {
+        match self {
+            SinkConfig::Database {
+                database_url,
+                approved_tokens,
+                ..
+            } => {
+                if database_url.is_none() && fallback_database_url.is_none() {
+                    return Err(
+                        "database sink needs database_url, in the sink, the config or DATABASE_URL"
+                            .to_string(),
+                    );
+                }
+                check_pubkeys("approved_tokens", approved_tokens)
+            }
+            SinkConfig::Export { row_group_size, .. } => {
+                if *row_group_size == 0 {
+                    return Err("row_group_size must be at least 1".to_string());
+                }
+                Ok(())
+            }
+            SinkConfig::Archive {
+                max_segment_bytes, ..
+            } => {
+                if *max_segment_bytes == 0 {
+                    return Err("max_segment_bytes must be at least 1".to_string());
+                }
+                Ok(())
+            }
+            SinkConfig::Webhook {
+                url,
+                secret,
+                secret_env,
+                ..
+            } => {
+                check_url(url)?;
+                if secret.is_some() && secret_env.is_some() {
+                    return Err("set secret or secret_env, not both".to_string());
+                }
+                if let Some(name) = secret_env {
+                    if std::env::var(name).is_err() {
+                        return Err(format!("secret_env {} is not set", name));
+                    }
+                }
+                Ok(())
+            }
+            SinkConfig::DepegMonitor {
+                stable_tokens,
+                thresholds,
+                window_secs,
+                sustain_secs,
+                notifiers,
+                ..
+            } => {
+                if stable_tokens.len() < 2 {
+                    return Err("stable_tokens needs at least two mints".to_string());
+                }
+                check_pubkeys("stable_tokens", stable_tokens)?;
+                if thresholds.is_empty() {
+                    return Err("thresholds is empty".to_string());
+                }
+                if thresholds.iter().any(|t| t.is_nan() || *t <= 0.0) {
+                    return Err("thresholds must be positive".to_string());
+                }
+                if *window_secs <= 0 {
+                    return Err("window_secs must be positive".to_string());
+                }
+                if *sustain_secs < 0 {
+                    return Err("sustain_secs can not be negative".to_string());
+                }
+                if notifiers.is_empty() {
+                    return Err("notifiers is empty".to_string());
+                }
+                for notifier in notifiers {
+                    if let NotifierConfig::Webhook { url, .. } = notifier {
+                        check_url(url)?;
+                    }
+                }
+                Ok(())
+            }
+            SinkConfig::Jsonl { .. } | SinkConfig::Stdout | SinkConfig::Stream { .. } => Ok(()),
+        }
+    }
+}
+
+fn check_pubkeys(field: &str, keys: &[String]) -> Result<(), String> {
+    for key in keys {
+        Pubkey::from_str(key).map_err(|_| format!("{}: {} is not a valid address", field, key))?;
+    }
+    Ok(())
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("url {} is not http(s)", url));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_util::{USDC, USDT};
+
+    const JUPITER: &str = "JUP2jxvXaqu7NQY1GmNF4m1vodw12LVXYxbFL2uJvfo";
+
+    // a pipeline named name with the given sources and sinks tables
+    fn pipeline(name: &str, sources: &str, sinks: &str) -> String {
+        format!(
+            r#"
+[[pipelines]]
+name = "{}"
+{}
+[pipelines.extractor]
+type = "jupiter_swap"
+{}
+"#,
+            name, sources, sinks
+        )
+    }
+
+    fn historical() -> String {
+        format!(
+            "[[pipelines.sources]]\ntype = \"historical\"\nprograms = [\"{}\"]\n",
+            JUPITER
+        )
+    }
+
+    fn stdout() -> &'static str {
+        "[[pipelines.sinks]]\ntype = \"stdout\"\n"
+    }
+
+    fn validate(toml: &str) -> Result<(), String> {
+        let config: Config = toml::from_str(toml).map_err(|e| e.to_string())?;
+        config.validate(None).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn accepts_a_valid_config() {
+        validate(&pipeline("p", &historical(), stdout())).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_config_without_pipelines() {
+        assert_eq!(
+            validate("pipelines = []"),
+            Err("config has no pipelines".into())
+        );
+    }
+
+    #[test]
+    fn rejects_an_unknown_encoding() {
+        let toml = format!(
+            "encoding = \"binary\"\n{}",
+            pipeline("p", &historical(), stdout())
+        );
+        assert_eq!(
+            validate(&toml),
+            Err("encoding binary is not one of json, json-parsed, base58, base64".into())
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_pipeline_names() {
+        let toml = pipeline("p", &historical(), stdout()) + &pipeline("p", &historical(), stdout());
+        assert_eq!(
+            validate(&toml),
+            Err("pipeline p: name is used more than once, names are checkpoint names".into())
+        );
+    }
+
+    #[test]
+    fn rejects_a_stream_address_used_twice() {
+        let stream = "[[pipelines.sinks]]\ntype = \"stream\"\nlisten = \"127.0.0.1:8080\"\n";
+        let toml = pipeline("a", &historical(), stream) + &pipeline("b", &historical(), stream);
+        assert_eq!(
+            validate(&toml),
+            Err("pipeline b: stream listen address 127.0.0.1:8080 is used more than once".into())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_sources() {
+        let bad_program =
+            "[[pipelines.sources]]\ntype = \"historical\"\nprograms = [\"jupiter\"]\n";
+        assert_eq!(
+            validate(&pipeline("p", bad_program, stdout())),
+            Err("pipeline p: sources[0]: programs: jupiter is not a valid address".into())
+        );
+
+        let blocks = format!(
+            "[[pipelines.sources]]\ntype = \"blocks\"\nprograms = [\"{}\"]\nstart_slot = 10\nend_slot = 5\n",
+            JUPITER
+        );
+        assert_eq!(
+            validate(&pipeline("p", &(historical() + &blocks), stdout())),
+            Err("pipeline p: sources[1]: end_slot 5 is before start_slot 10".into())
+        );
+
+        let live = format!(
+            "[[pipelines.sources]]\ntype = \"live\"\nprograms = [\"{}\"]\ncommitment = \"rooted\"\n",
+            JUPITER
+        );
+        assert_eq!(
+            validate(&pipeline("p", &live, stdout())),
+            Err(
+                "pipeline p: sources[0]: commitment rooted is not one of processed, confirmed, finalized"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_sinks() {
+        assert_eq!(
+            validate(&pipeline("p", &historical(), "[[pipelines.sinks]]\ntype = \"database\"\n")),
+            Err("pipeline p: sinks[0]: database sink needs database_url, in the sink, the config or DATABASE_URL".into())
+        );
+
+        let webhook = "[[pipelines.sinks]]\ntype = \"webhook\"\nurl = \"https://hook\"\nsecret = \"s\"\nsecret_env = \"S\"\n";
+        assert_eq!(
+            validate(&pipeline("p", &historical(), webhook)),
+            Err("pipeline p: sinks[0]: set secret or secret_env, not both".into())
+        );
+
+        let depeg = format!(
+            "[[pipelines.sinks]]\ntype = \"depeg_monitor\"\nstable_tokens = [\"{}\"]\n",
+            USDC
+        );
+        assert_eq!(
+            validate(&pipeline("p", &historical(), &depeg)),
+            Err("pipeline p: sinks[0]: stable_tokens needs at least two mints".into())
+        );
+
+        let depeg = format!(
+            "[[pipelines.sinks]]\ntype = \"depeg_monitor\"\nstable_tokens = [\"{}\", \"{}\"]\nthresholds = [0.01, -0.02]\n",
+            USDC, USDT
+        );
+        assert_eq!(
+            validate(&pipeline("p", &historical(), &depeg)),
+            Err("pipeline p: sinks[0]: thresholds must be positive".into())
+        );
+    }
+
+    #[test]
+    fn rejects_an_empty_batch() {
+        let toml = pipeline("p", &historical(), stdout())
+            .replace("name = \"p\"\n", "name = \"p\"\nbatch_size = 0\n");
+        assert_eq!(
+            validate(&toml),
+            Err("pipeline p: batch_size must be at least 1".into())
+        );
+    }
+
+    #[test]
+    fn load_rejects_unknown_fields_and_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pipelines.toml");
+        std::fs::write(
+            &path,
+            pipeline("p", &historical(), stdout())
+                .replace("name = \"p\"\n", "name = \"p\"\nbatch = 5\n"),
+        )
+        .unwrap();
+        let err = Config::load(&path).unwrap_err().to_string();
+        assert!(err.contains("unknown field `batch`"), "{}", err);
+
+        let path = dir.path().join("pipelines.json");
+        std::fs::write(&path, "{}").unwrap();
+        let err = Config::load(&path).unwrap_err().to_string();
+        assert!(
+            err.ends_with("expected a .toml, .yaml or .yml file"),
+            "{}",
+            err
+        );
+    }
+}
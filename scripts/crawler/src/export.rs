@@ -0,0 +1,104 @@
+use clap::Args;
+use diesel::prelude::*;
+
+use solcrawl::sinks::export::{ExportFilter, ExportFormat, ExportSink, DEFAULT_ROW_GROUP_SIZE};
+use solcrawl::sinks::Sink;
+
+use crate::storage::conn::DbConnection;
+use crate::storage::models::tx::Tx;
+use crate::storage::schema::tx;
+use crate::storage::swaps::load_swap_events;
+use crate::with_conn;
+use std::error::Error;
+use std::path::PathBuf;
+
+// swaps loaded from the database per query
+const EXPORT_PAGE_SIZE: i64 = 10_000;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Directory the day partitions are written to
+    #[clap(long)]
+    pub out: PathBuf,
+
+    /// parquet or csv
+    #[clap(long, default_value = "parquet")]
+    pub format: ExportFormat,
+
+    /// Only swaps at or after this unix time
+    #[clap(long)]
+    pub from: Option<i64>,
+
+    /// Only swaps before this unix time
+    #[clap(long)]
+    pub to: Option<i64>,
+
+    /// Only swaps between two mints, in either direction, as <mint>,<mint>
+    #[clap(long, parse(try_from_str = parse_pair))]
+    pub pair: Option<(String, String)>,
+
+    /// Rows per parquet row group
+    #[clap(long, default_value_t = DEFAULT_ROW_GROUP_SIZE)]
+    pub row_group_size: usize,
+}
+
+impl ExportArgs {
+    pub fn filter(&self) -> ExportFilter {
+        ExportFilter {
+            start_time: self.from,
+            end_time: self.to,
+            pair: self.pair.clone(),
+        }
+    }
+}
+
+pub fn parse_pair(pair: &str) -> Result<(String, String), String> {
+    let (a, b) = pair
+        .split_once(',')
+        .ok_or("pair must be two mints separated by a comma")?;
+    Ok((a.to_string(), b.to_string()))
+}
+
+// writes the stored swaps matching the filter to the export sink, returns how many
+pub fn export(conn: &DbConnection, args: ExportArgs) -> Result<usize, Box<dyn Error>> {
+    let filter = args.filter();
+    let mut sink = ExportSink::new(args.out, args.format, args.row_group_size, filter.clone())?;
+
+    let mut exported = 0;
+    let mut last_id = 0;
+    loop {
+        let txs = with_conn!(conn, c => {
+            let mut query = tx::table
+                .filter(tx::id.gt(last_id))
+                .order(tx::id.asc())
+                .limit(EXPORT_PAGE_SIZE)
+                .into_boxed();
+            if let Some(start) = filter.start_time {
+                query = query.filter(tx::block_time.ge(start));
+            }
+            if let Some(end) = filter.end_time {
+                query = query.filter(tx::block_time.lt(end));
+            }
+            if let Some((a, b)) = &filter.pair {
+                query = query.filter(
+                    (tx::input_token.eq(a).and(tx::output_token.eq(b)))
+                        .or(tx::input_token.eq(b).and(tx::output_token.eq(a))),
+                );
+            }
+            query.load::<Tx>(c)?
+        });
+        let last = match txs.last() {
+            Some(last) => last.id,
+            None => break,
+        };
+
+        let swaps = load_swap_events(conn, txs)?;
+        sink.write_batch(&swaps)?;
+        exported += swaps.len();
+        last_id = last;
+        println!("exported {} swaps", exported);
+    }
+
+    sink.close()?;
+    Ok(exported)
+}
@@ -0,0 +1,214 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+
+use solcrawl::candles::CandleInterval;
+use solcrawl::crawlers::live_crawler::{LiveEvent, LiveEventReceiver, LiveEventSender};
+
+use crate::storage::conn::DbConnection;
+use crate::storage::finality::{mark_finalized, remove_swaps, unfinalized_swaps};
+use crossbeam::channel::RecvTimeoutError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// getSignatureStatuses accepts at most 256 signatures per request
+const STATUS_BATCH_SIZE: usize = 256;
+
+// stored swaps resolved per sweep, the rest wait for the next one
+const SWEEP_LIMIT: i64 = 10_000;
+
+// FinalityTracker keeps the commitment of stored swaps up to date. It applies the live
+// crawlers finalized and rolled back notices as they arrive, and every sweep interval
+// resolves the swaps still stored below finalized against the cluster, which catches
+// notices that arrived before their swap was committed or were lost with a restart.
+pub struct FinalityTracker {
+    conn: DbConnection,
+    client: RpcClient,
+    // candles the removed swaps were merged into are recomputed
+    candle_intervals: Vec<CandleInterval>,
+    sweep_interval: Duration,
+}
+
+// TODO dont print to std out - use a logger
+impl FinalityTracker {
+    pub fn new(
+        conn: DbConnection,
+        rpc_url: String,
+        candle_intervals: Vec<CandleInterval>,
+        sweep_interval: Duration,
+    ) -> Self {
+        Self {
+            conn,
+            client: RpcClient::new(rpc_url),
+            candle_intervals,
+            sweep_interval,
+        }
+    }
+
+    // runs until the live crawler is gone
+    pub fn run(&self, events: LiveEventReceiver) {
+        let mut last_sweep = Instant::now();
+        loop {
+            let timeout = self.sweep_interval.saturating_sub(last_sweep.elapsed());
+            match events.recv_timeout(timeout) {
+                Ok(event) => {
+                    if let Err(e) = self.apply(event) {
+                        println!("finality err - {}", e);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if last_sweep.elapsed() >= self.sweep_interval {
+                if let Err(e) = self.sweep() {
+                    println!("finality sweep err - {}", e);
+                }
+                last_sweep = Instant::now();
+            }
+        }
+    }
+
+    fn apply(&self, event: LiveEvent) -> Result<(), Box<dyn Error>> {
+        match event {
+            LiveEvent::Finalized { sig, slot } => {
+                mark_finalized(&self.conn, &[(sig, slot)])?;
+            }
+            // the crawler only saw the signature disappear, the swap is removed once
+            // the cluster confirms its slot was skipped and left to the sweep otherwise
+            LiveEvent::RolledBack { sig, slot } => {
+                if !self.is_skipped(slot) {
+                    return Ok(());
+                }
+                if remove_swaps(
+                    &self.conn,
+                    std::slice::from_ref(&sig),
+                    &self.candle_intervals,
+                )? > 0
+                {
+                    println!("removed rolled back swap {} from slot {}", sig, slot);
+                }
+            }
+            // confirmed transactions go through the pipeline
+            LiveEvent::Confirmed(_) => {}
+        }
+        Ok(())
+    }
+
+    // resolves the stored swaps below finalized, swaps the cluster does not know are only
+    // removed once their slot is at or below the finalized slot and has no block. nodes
+    // without transaction history also answer unknown, so anything else is left alone.
+    // returns how many were finalized and how many removed
+    pub fn sweep(&self) -> Result<(usize, usize), Box<dyn Error>> {
+        let pending = unfinalized_swaps(&self.conn, SWEEP_LIMIT)?;
+        if pending.is_empty() {
+            return Ok((0, 0));
+        }
+        let root = self
+            .client
+            .get_slot_with_commitment(CommitmentConfig::finalized())?;
+
+        let mut finalized = Vec::new();
+        let mut rolled_back = Vec::new();
+        let mut skipped_slots = HashMap::new();
+        for batch in pending.chunks(STATUS_BATCH_SIZE) {
+            let sigs = batch
+                .iter()
+                .map(|(sig, _)| Signature::from_str(sig))
+                .collect::<Result<Vec<Signature>, _>>()?;
+            let statuses = self
+                .client
+                .get_signature_statuses_with_history(&sigs)?
+                .value;
+
+            for ((sig, slot), status) in batch.iter().zip(statuses) {
+                match status {
+                    Some(status) if status.satisfies_commitment(CommitmentConfig::finalized()) => {
+                        finalized.push((sig.clone(), status.slot));
+                    }
+                    None => {
+                        let slot = match slot {
+                            Some(slot) if (*slot as u64) <= root => *slot as u64,
+                            _ => continue,
+                        };
+                        let skipped = *skipped_slots
+                            .entry(slot)
+                            .or_insert_with(|| self.is_skipped(slot));
+                        if skipped {
+                            rolled_back.push(sig.clone());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let finalized = mark_finalized(&self.conn, &finalized)?;
+        let removed = remove_swaps(&self.conn, &rolled_back, &self.candle_intervals)?;
+        if finalized > 0 || removed > 0 {
+            println!(
+                "finalized {} swaps, removed {} rolled back swaps",
+                finalized, removed
+            );
+        }
+        Ok((finalized, removed))
+    }
+
+    // true only if the cluster finalized past the slot without producing a block in it,
+    // errors count as not skipped
+    fn is_skipped(&self, slot: u64) -> bool {
+        match self.client.get_blocks_with_commitment(
+            slot,
+            Some(slot),
+            CommitmentConfig::finalized(),
+        ) {
+            Ok(blocks) => blocks.is_empty(),
+            Err(e) => {
+                println!("finality blocks err - {}", e);
+                false
+            }
+        }
+    }
+}
+
+// starts a tracker for every database, each one gets all of the notices
+pub fn start_trackers(
+    rpc_url: &str,
+    databases: &[(String, Vec<CandleInterval>)],
+    events: LiveEventReceiver,
+    sweep_interval: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let mut senders: Vec<LiveEventSender> = Vec::new();
+    for (database_url, candle_intervals) in databases {
+        let conn = DbConnection::establish(database_url)?;
+        let tracker = FinalityTracker::new(
+            conn,
+            rpc_url.to_string(),
+            candle_intervals.clone(),
+            sweep_interval,
+        );
+        let (sender, recv) = crossbeam::channel::unbounded();
+        std::thread::spawn(move || tracker.run(recv));
+        senders.push(sender);
+    }
+    std::thread::spawn(move || {
+        for event in events {
+            for sender in &senders {
+                let copy = match &event {
+                    LiveEvent::Finalized { sig, slot } => LiveEvent::Finalized {
+                        sig: sig.clone(),
+                        slot: *slot,
+                    },
+                    LiveEvent::RolledBack { sig, slot } => LiveEvent::RolledBack {
+                        sig: sig.clone(),
+                        slot: *slot,
+                    },
+                    LiveEvent::Confirmed(_) => continue,
+                };
+                let _ = sender.send(copy);
+            }
+        }
+    });
+    Ok(())
+}
@@ -1,187 +1,562 @@
-use crossbeam::channel::Receiver;
 use diesel::prelude::*;
 
-use solana_transaction_status::{
-    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiMessage,
-    UiTransactionTokenBalance,
-};
+use solcrawl::candles::{aggregate, CandleInterval};
+use solcrawl::extractors::jupiter_swap::{SwapEvent, EXTRACTOR_VERSION};
+use solcrawl::sinks::{Checkpoint, Sink};
 
 use crate::storage::models::create_tx::CreateTx;
+use crate::storage::models::swap_leg::CreateSwapLeg;
 
-use crate::storage::models::tx::Tx;
+use crate::storage::candles::merge_candles;
+use crate::storage::conn::DbConnection;
+use crate::storage::models::checkpoint::Checkpoint as CheckpointRow;
+use crate::storage::schema::{checkpoint, swap_legs, tx};
+use crate::with_conn;
+use diesel::pg::upsert::excluded;
+use diesel::sql_types::{BigInt, Bool, Double, Nullable, SmallInt, Text};
+use diesel::sqlite::SqliteConnection;
+use std::collections::HashSet;
 use std::error::Error;
-use std::ops::Index;
 
-pub fn handle_txs(
-    approved_tokens: &[String],
-    conn: PgConnection,
-    recvs: Vec<Receiver<(String, EncodedConfirmedTransactionWithStatusMeta)>>,
-) {
-    loop {
-        let res = handle_tx(approved_tokens, &conn, recvs.clone());
-        if let Err(err) = res {
-            println!("handle tx err - {}", err)
+// rows per INSERT statement, postgres allows at most 65535 bind parameters per statement
+const INSERT_CHUNK_SIZE: usize = 1000;
+
+// DatabaseSink stores swaps between approved tokens (any tokens when none are approved)
+// in the tx table and their route in
+// the swap_legs table. Rows are buffered and committed in one transaction together with
+// the pipelines checkpoint, so after a restart the checkpoint never points past rows
+// that were lost. Rows of a failed commit are kept and committed with the next batch.
+// With candle intervals set, swaps that were not stored yet are also merged into the
+// candles table in the same transaction.
+pub struct DatabaseSink {
+    approved_tokens: Vec<String>,
+    conn: DbConnection,
+    // overwrite rows that already exist instead of keeping the first insert, for re-extracting
+    update_existing: bool,
+    candle_intervals: Vec<CandleInterval>,
+    pending: Vec<CreateTx>,
+    pending_legs: Vec<CreateSwapLeg>,
+    pending_swaps: Vec<SwapEvent>,
+}
+
+impl DatabaseSink {
+    pub fn new(approved_tokens: Vec<String>, conn: DbConnection, update_existing: bool) -> Self {
+        Self {
+            approved_tokens,
+            conn,
+            update_existing,
+            candle_intervals: Vec::new(),
+            pending: Vec::new(),
+            pending_legs: Vec::new(),
+            pending_swaps: Vec::new(),
         }
     }
+
+    // swaps re-extracted with update_existing are not merged again, rebuild the candles instead
+    pub fn with_candles(mut self, candle_intervals: Vec<CandleInterval>) -> Self {
+        self.candle_intervals = candle_intervals;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn into_conn(self) -> DbConnection {
+        self.conn
+    }
 }
 
-pub fn handle_tx(
-    approved_tokens: &[String],
-    conn: &PgConnection,
-    tx_recvs: Vec<Receiver<(String, EncodedConfirmedTransactionWithStatusMeta)>>,
-) -> Result<(), Box<dyn Error>> {
-    let mut sel = crossbeam::channel::Select::new();
-    tx_recvs.iter().for_each(|recv| {
-        sel.recv(recv);
-    });
-    loop {
-        let opr = sel.select();
-        let i = opr.index();
-        let (sig, tx) = opr.recv(tx_recvs.index(i))?;
-        let create_tx = build_create_tx_obj(approved_tokens, sig.clone(), tx)?;
-
-        let txs = crate::storage::schema::tx::table
-            .filter(crate::storage::schema::tx::sig.eq(sig.as_str()))
-            .load::<Tx>(conn)?;
-
-        if !txs.is_empty() {
-            println!("tx with sig already exists in database - {}", sig);
-            continue;
+// TODO dont print to std out - use a logger
+impl Sink<SwapEvent> for DatabaseSink {
+    fn write_batch(&mut self, items: &[SwapEvent]) -> Result<(), Box<dyn Error>> {
+        for swap in items {
+            match build_create_tx_obj(&self.approved_tokens, swap) {
+                Ok(create_tx) => {
+                    self.pending.push(create_tx);
+                    self.pending_legs.extend(build_create_swap_legs(swap));
+                    if !self.candle_intervals.is_empty() {
+                        self.pending_swaps.push(swap.clone());
+                    }
+                }
+                Err(e) => println!("handle tx err - {} - {}", swap.sig, e),
+            }
         }
+        Ok(())
+    }
 
-        println!("{:?}", create_tx);
+    // rows are only committed with the checkpoint
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
 
-        diesel::insert_into(crate::storage::schema::tx::table)
-            .values(create_tx)
-            .execute(conn)?;
+    fn checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+        let row = CheckpointRow {
+            name: checkpoint.name.clone(),
+            sig: checkpoint.sig.clone(),
+            slot: checkpoint.slot as i64,
+        };
+        let rows = &self.pending;
+        let legs = &self.pending_legs;
+        let swaps = &self.pending_swaps;
+        let update_existing = self.update_existing;
+        let intervals = &self.candle_intervals;
+        let db = &self.conn;
+        match &self.conn {
+            DbConnection::Postgres(conn) => {
+                conn.transaction::<_, diesel::result::Error, _>(|| {
+                    update_candles(db, swaps, intervals)?;
+                    insert_txs(conn, rows, update_existing)?;
+                    insert_swap_legs(conn, legs, update_existing)?;
+                    diesel::insert_into(checkpoint::table)
+                        .values(&row)
+                        .on_conflict(checkpoint::name)
+                        .do_update()
+                        .set((
+                            checkpoint::sig.eq(excluded(checkpoint::sig)),
+                            checkpoint::slot.eq(excluded(checkpoint::slot)),
+                        ))
+                        .execute(conn)?;
+                    Ok(())
+                })?
+            }
+            DbConnection::Sqlite(conn) => {
+                conn.transaction::<_, diesel::result::Error, _>(|| {
+                    update_candles(db, swaps, intervals)?;
+                    insert_txs_sqlite(conn, rows, update_existing)?;
+                    insert_swap_legs_sqlite(conn, legs, update_existing)?;
+                    diesel::replace_into(checkpoint::table)
+                        .values(&row)
+                        .execute(conn)?;
+                    Ok(())
+                })?
+            }
+        }
+        self.pending.clear();
+        self.pending_legs.clear();
+        self.pending_swaps.clear();
+        Ok(())
     }
 }
 
+// the token of the swap that is not approved, any token is when none are
+pub fn unapproved_token<'a>(approved_tokens: &[String], swap: &'a SwapEvent) -> Option<&'a str> {
+    [&swap.input_token, &swap.output_token]
+        .into_iter()
+        .find(|tok| !approved_tokens.is_empty() && !approved_tokens.contains(tok))
+        .map(|tok| tok.as_str())
+}
+
 pub fn build_create_tx_obj(
     approved_tokens: &[String],
-    sig: String,
-    confirmed_tx: EncodedConfirmedTransactionWithStatusMeta,
+    swap: &SwapEvent,
 ) -> Result<CreateTx, Box<dyn Error>> {
-    let tx = confirmed_tx.transaction;
-    let mut account_keys: Vec<String> = Vec::new();
-    if let EncodedTransaction::Json(tx) = tx.transaction {
-        if let UiMessage::Raw(msg) = tx.message {
-            account_keys = msg.account_keys
+    if let Some(tok) = unapproved_token(approved_tokens, swap) {
+        return Err(format!("unexpected tok type - {}", tok).into());
+    }
+
+    Ok(CreateTx {
+        sig: swap.sig.clone(),
+        input_token: swap.input_token.clone(),
+        output_token: swap.output_token.clone(),
+        input_amount: swap.input_amount as i64,
+        output_amount: swap.output_amount as i64,
+        block_time: swap
+            .block_time
+            .ok_or("tx does not contain blocktime")?
+            .abs(),
+        input_token_program: swap.input_token_program.clone(),
+        output_token_program: swap.output_token_program.clone(),
+        input_transfer_fee: swap.input_transfer_fee as i64,
+        output_transfer_fee: swap.output_transfer_fee as i64,
+        price: Some(swap.price),
+        inverse_price: Some(swap.inverse_price),
+        peg_deviation: swap.peg_deviation,
+        quoted_out_amount: swap.quoted_out_amount.map(|a| a as i64),
+        minimum_out_amount: swap.minimum_out_amount.map(|a| a as i64),
+        price_impact_bps: swap.price_impact_bps,
+        slot: Some(swap.slot as i64),
+        trader: Some(swap.trader.clone()),
+        fee: Some(swap.fee as i64),
+        success: swap.success,
+        input_decimals: Some(swap.input_decimals as i16),
+        output_decimals: Some(swap.output_decimals as i16),
+        program_version: swap.program_version.map(|v| v as i16),
+        commitment: swap.commitment.to_string(),
+        extractor_version: Some(EXTRACTOR_VERSION as i16),
+    })
+}
+
+pub fn build_create_swap_legs(swap: &SwapEvent) -> Vec<CreateSwapLeg> {
+    swap.legs
+        .iter()
+        .map(|leg| CreateSwapLeg {
+            sig: swap.sig.clone(),
+            leg_index: leg.index as i16,
+            amm_program: leg.amm_program.clone(),
+            input_token: leg.input_token.clone(),
+            output_token: leg.output_token.clone(),
+            input_amount: leg.input_amount as i64,
+            output_amount: leg.output_amount as i64,
+        })
+        .collect()
+}
+
+// runs before the swaps are inserted, so swaps already stored can be left out
+// and a replayed swap is never counted twice
+fn update_candles(
+    conn: &DbConnection,
+    swaps: &[SwapEvent],
+    intervals: &[CandleInterval],
+) -> Result<(), diesel::result::Error> {
+    if swaps.is_empty() {
+        return Ok(());
+    }
+    let mut seen = HashSet::new();
+    let mut swaps = swaps.to_vec();
+    swaps.retain(|swap| seen.insert(swap.sig.clone()));
+
+    let mut stored = HashSet::new();
+    for chunk in swaps.chunks(INSERT_CHUNK_SIZE) {
+        let sigs = chunk.iter().map(|s| s.sig.clone()).collect::<Vec<String>>();
+        let existing = with_conn!(conn, c => tx::table
+            .select(tx::sig)
+            .filter(tx::sig.eq_any(&sigs))
+            .load::<String>(c)?);
+        stored.extend(existing);
+    }
+    swaps.retain(|swap| !stored.contains(&swap.sig));
+
+    merge_candles(conn, aggregate(&swaps, intervals))
+}
+
+fn insert_txs(
+    conn: &PgConnection,
+    rows: &[CreateTx],
+    update_existing: bool,
+) -> Result<(), diesel::result::Error> {
+    // a statement may not touch the same row twice with ON CONFLICT DO UPDATE
+    let mut seen = HashSet::new();
+    let mut rows = rows.to_vec();
+    rows.retain(|row| seen.insert(row.sig.clone()));
+
+    for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+        let inserted = if update_existing {
+            diesel::insert_into(tx::table)
+                .values(chunk)
+                .on_conflict(tx::sig)
+                .do_update()
+                .set((
+                    tx::input_token.eq(excluded(tx::input_token)),
+                    tx::output_token.eq(excluded(tx::output_token)),
+                    tx::input_amount.eq(excluded(tx::input_amount)),
+                    tx::output_amount.eq(excluded(tx::output_amount)),
+                    tx::block_time.eq(excluded(tx::block_time)),
+                    tx::input_token_program.eq(excluded(tx::input_token_program)),
+                    tx::output_token_program.eq(excluded(tx::output_token_program)),
+                    tx::input_transfer_fee.eq(excluded(tx::input_transfer_fee)),
+                    tx::output_transfer_fee.eq(excluded(tx::output_transfer_fee)),
+                    tx::price.eq(excluded(tx::price)),
+                    tx::inverse_price.eq(excluded(tx::inverse_price)),
+                    tx::peg_deviation.eq(excluded(tx::peg_deviation)),
+                    tx::quoted_out_amount.eq(excluded(tx::quoted_out_amount)),
+                    tx::minimum_out_amount.eq(excluded(tx::minimum_out_amount)),
+                    tx::price_impact_bps.eq(excluded(tx::price_impact_bps)),
+                    tx::slot.eq(excluded(tx::slot)),
+                    tx::trader.eq(excluded(tx::trader)),
+                    tx::fee.eq(excluded(tx::fee)),
+                    tx::success.eq(excluded(tx::success)),
+                    tx::input_decimals.eq(excluded(tx::input_decimals)),
+                    tx::output_decimals.eq(excluded(tx::output_decimals)),
+                    tx::program_version.eq(excluded(tx::program_version)),
+                    tx::commitment.eq(excluded(tx::commitment)),
+                    tx::extractor_version.eq(excluded(tx::extractor_version)),
+                ))
+                .execute(conn)?
+        } else {
+            diesel::insert_into(tx::table)
+                .values(chunk)
+                .on_conflict(tx::sig)
+                .do_nothing()
+                .execute(conn)?
+        };
+        println!(
+            "stored {} txs, {} already existed",
+            inserted,
+            chunk.len() - inserted
+        );
+    }
+    Ok(())
+}
+
+fn insert_swap_legs(
+    conn: &PgConnection,
+    rows: &[CreateSwapLeg],
+    update_existing: bool,
+) -> Result<(), diesel::result::Error> {
+    let mut seen = HashSet::new();
+    let mut rows = rows.to_vec();
+    rows.retain(|row| seen.insert((row.sig.clone(), row.leg_index)));
+
+    for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+        if update_existing {
+            diesel::insert_into(swap_legs::table)
+                .values(chunk)
+                .on_conflict((swap_legs::sig, swap_legs::leg_index))
+                .do_update()
+                .set((
+                    swap_legs::amm_program.eq(excluded(swap_legs::amm_program)),
+                    swap_legs::input_token.eq(excluded(swap_legs::input_token)),
+                    swap_legs::output_token.eq(excluded(swap_legs::output_token)),
+                    swap_legs::input_amount.eq(excluded(swap_legs::input_amount)),
+                    swap_legs::output_amount.eq(excluded(swap_legs::output_amount)),
+                ))
+                .execute(conn)?;
+        } else {
+            diesel::insert_into(swap_legs::table)
+                .values(chunk)
+                .on_conflict((swap_legs::sig, swap_legs::leg_index))
+                .do_nothing()
+                .execute(conn)?;
         }
     }
+    Ok(())
+}
 
-    let tx_creator = account_keys
-        .first()
-        .ok_or("could not get tx creator")?
-        .clone();
+// columns written by the sqlite upserts, in the order their values are bound
+const TX_COLUMNS: [&str; 25] = [
+    "sig",
+    "input_token",
+    "output_token",
+    "input_amount",
+    "output_amount",
+    "block_time",
+    "input_token_program",
+    "output_token_program",
+    "input_transfer_fee",
+    "output_transfer_fee",
+    "price",
+    "inverse_price",
+    "peg_deviation",
+    "quoted_out_amount",
+    "minimum_out_amount",
+    "price_impact_bps",
+    "slot",
+    "trader",
+    "fee",
+    "success",
+    "input_decimals",
+    "output_decimals",
+    "program_version",
+    "commitment",
+    "extractor_version",
+];
+const SWAP_LEG_COLUMNS: [&str; 7] = [
+    "sig",
+    "leg_index",
+    "amm_program",
+    "input_token",
+    "output_token",
+    "input_amount",
+    "output_amount",
+];
 
-    let meta = tx.meta.ok_or("tx does not contain metadata")?;
-    let mut all_pre = meta
-        .pre_token_balances
-        .ok_or("does not have pre token balances")?
-        .into_iter()
-        .filter(|t| {
-            if let Some(owner) = t.owner.clone() {
-                owner == *tx_creator
-            } else {
-                false
+// diesel has no ON CONFLICT DO UPDATE for sqlite. REPLACE would delete the row, taking its
+// swap_legs with it through the cascade and giving it a new id, which id cursors skip or repeat
+fn upsert_sql(table: &str, columns: &[&str], conflict: &[&str]) -> String {
+    let placeholders = vec!["?"; columns.len()];
+    let updates = columns
+        .iter()
+        .filter(|column| !conflict.contains(column))
+        .map(|column| format!("{} = excluded.{}", column, column))
+        .collect::<Vec<String>>();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({}) DO UPDATE SET {}",
+        table,
+        columns.join(", "),
+        placeholders.join(", "),
+        conflict.join(", "),
+        updates.join(", ")
+    )
+}
+
+fn insert_txs_sqlite(
+    conn: &SqliteConnection,
+    rows: &[CreateTx],
+    update_existing: bool,
+) -> Result<(), diesel::result::Error> {
+    let mut seen = HashSet::new();
+    let mut rows = rows.to_vec();
+    rows.retain(|row| seen.insert(row.sig.clone()));
+
+    let upsert = upsert_sql("tx", &TX_COLUMNS, &["sig"]);
+    for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+        let inserted = if update_existing {
+            let mut upserted = 0;
+            for row in chunk {
+                upserted += diesel::sql_query(upsert.as_str())
+                    .bind::<Text, _>(&row.sig)
+                    .bind::<Text, _>(&row.input_token)
+                    .bind::<Text, _>(&row.output_token)
+                    .bind::<BigInt, _>(row.input_amount)
+                    .bind::<BigInt, _>(row.output_amount)
+                    .bind::<BigInt, _>(row.block_time)
+                    .bind::<Text, _>(&row.input_token_program)
+                    .bind::<Text, _>(&row.output_token_program)
+                    .bind::<BigInt, _>(row.input_transfer_fee)
+                    .bind::<BigInt, _>(row.output_transfer_fee)
+                    .bind::<Nullable<Double>, _>(row.price)
+                    .bind::<Nullable<Double>, _>(row.inverse_price)
+                    .bind::<Nullable<Double>, _>(row.peg_deviation)
+                    .bind::<Nullable<BigInt>, _>(row.quoted_out_amount)
+                    .bind::<Nullable<BigInt>, _>(row.minimum_out_amount)
+                    .bind::<Nullable<Double>, _>(row.price_impact_bps)
+                    .bind::<Nullable<BigInt>, _>(row.slot)
+                    .bind::<Nullable<Text>, _>(&row.trader)
+                    .bind::<Nullable<BigInt>, _>(row.fee)
+                    .bind::<Bool, _>(row.success)
+                    .bind::<Nullable<SmallInt>, _>(row.input_decimals)
+                    .bind::<Nullable<SmallInt>, _>(row.output_decimals)
+                    .bind::<Nullable<SmallInt>, _>(row.program_version)
+                    .bind::<Text, _>(&row.commitment)
+                    .bind::<Nullable<SmallInt>, _>(row.extractor_version)
+                    .execute(conn)?;
             }
-        })
-        .collect::<Vec<UiTransactionTokenBalance>>();
-    all_pre.sort_by(|t1, t2| Ord::cmp(&t1.mint, &t2.mint));
+            upserted
+        } else {
+            diesel::insert_or_ignore_into(tx::table)
+                .values(chunk)
+                .execute(conn)?
+        };
+        println!(
+            "stored {} txs, {} already existed",
+            inserted,
+            chunk.len() - inserted
+        );
+    }
+    Ok(())
+}
 
-    let mut all_post = meta
-        .post_token_balances
-        .ok_or("does not have post token balances")?
-        .into_iter()
-        .filter(|t| {
-            if let Some(owner) = t.owner.clone() {
-                owner == *tx_creator
-            } else {
-                false
+fn insert_swap_legs_sqlite(
+    conn: &SqliteConnection,
+    rows: &[CreateSwapLeg],
+    update_existing: bool,
+) -> Result<(), diesel::result::Error> {
+    let mut seen = HashSet::new();
+    let mut rows = rows.to_vec();
+    rows.retain(|row| seen.insert((row.sig.clone(), row.leg_index)));
+
+    let upsert = upsert_sql("swap_legs", &SWAP_LEG_COLUMNS, &["sig", "leg_index"]);
+    for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+        if update_existing {
+            for row in chunk {
+                diesel::sql_query(upsert.as_str())
+                    .bind::<Text, _>(&row.sig)
+                    .bind::<SmallInt, _>(row.leg_index)
+                    .bind::<Text, _>(&row.amm_program)
+                    .bind::<Text, _>(&row.input_token)
+                    .bind::<Text, _>(&row.output_token)
+                    .bind::<BigInt, _>(row.input_amount)
+                    .bind::<BigInt, _>(row.output_amount)
+                    .execute(conn)?;
             }
-        })
-        .collect::<Vec<UiTransactionTokenBalance>>();
-    all_post.sort_by(|t1, t2| Ord::cmp(&t1.mint, &t2.mint));
-
-    if all_pre.len() != all_post.len() {
-        return Err("unexpected token balances length".into());
-    }
-
-    let mut pre: Vec<UiTransactionTokenBalance> = Vec::new();
-    let mut post: Vec<UiTransactionTokenBalance> = Vec::new();
-    for i in 0..all_pre.len() {
-        let diff = all_pre
-            .index(i)
-            .ui_token_amount
-            .amount
-            .parse::<u64>()?
-            .abs_diff(all_post.index(i).ui_token_amount.amount.parse::<u64>()?);
-        if diff > 0 {
-            pre.push(all_pre.index(i).clone());
-            post.push(all_post.index(i).clone());
+        } else {
+            diesel::insert_or_ignore_into(swap_legs::table)
+                .values(chunk)
+                .execute(conn)?;
         }
     }
+    Ok(())
+}
 
-    if pre.len() != 2 {
-        return Err("unexpected token balances".into());
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_util::{swap, temp_db};
 
-    for tok in pre.iter() {
-        if !approved_tokens.contains(&tok.mint) {
-            return Err(format!("unexpected tok type - {}", tok.mint).into());
+    fn checkpoint(sig: &str) -> Checkpoint {
+        Checkpoint {
+            name: "test/historical".to_string(),
+            sig: sig.to_string(),
+            slot: 1,
         }
     }
 
-    let mut input_index: usize = 1;
-    let mut output_index: usize = 0;
-    if pre.index(0).ui_token_amount.amount.parse::<u64>()?
-        > post.index(0).ui_token_amount.amount.parse::<u64>()?
-    {
-        input_index = 0;
-        output_index = 1;
-    }
-
-    let input_token = pre.index(input_index).mint.clone();
-    let output_token = pre.index(output_index).mint.clone();
-    let input_amount = pre
-        .index(input_index)
-        .ui_token_amount
-        .amount
-        .parse::<u64>()?
-        .checked_sub(
-            post.index(input_index)
-                .ui_token_amount
-                .amount
-                .parse::<u64>()?,
-        )
-        .ok_or("unexpected input amount")?;
-    let output_amount = post
-        .index(output_index)
-        .ui_token_amount
-        .amount
-        .parse::<u64>()?
-        .checked_sub(
-            pre.index(output_index)
-                .ui_token_amount
-                .amount
-                .parse::<u64>()?,
-        )
-        .ok_or("unexpected output amount")?;
-
-    if input_amount == 0 || output_amount == 0 {
-        return Err("unexpected token amounts".into());
+    fn stored_sigs(conn: &DbConnection) -> Vec<String> {
+        with_conn!(conn, c => tx::table
+            .select(tx::sig)
+            .order(tx::sig.asc())
+            .load::<String>(c))
+        .unwrap()
     }
 
-    Ok(CreateTx {
-        sig,
-        input_token,
-        output_token,
-        input_amount: input_amount as i64,
-        output_amount: output_amount as i64,
-        block_time: confirmed_tx
-            .block_time
-            .ok_or("tx does not contain blocktime")?
-            .abs(),
-    })
+    #[test]
+    fn failed_commit_keeps_rows_for_next_batch() {
+        let (_dir, conn) = temp_db();
+        let mut sink = DatabaseSink::new(vec![], conn, false);
+        let fail = "CREATE TRIGGER fail BEFORE INSERT ON checkpoint BEGIN SELECT RAISE(ABORT, 'fail'); END";
+        with_conn!(&sink.conn, c => c.execute(fail)).unwrap();
+
+        sink.write_batch(&[swap("a", 100, 1_000_000, 999_000)])
+            .unwrap();
+        assert!(sink.checkpoint(&checkpoint("a")).is_err());
+        assert!(stored_sigs(&sink.conn).is_empty());
+
+        with_conn!(&sink.conn, c => c.execute("DROP TRIGGER fail")).unwrap();
+        sink.write_batch(&[swap("b", 101, 2_000_000, 1_999_000)])
+            .unwrap();
+        sink.checkpoint(&checkpoint("b")).unwrap();
+        assert_eq!(stored_sigs(&sink.conn), vec!["a", "b"]);
+
+        let row = crate::storage::checkpoints::load_checkpoint(&sink.conn, "test/historical")
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.sig, "b");
+    }
+    // (tx id, output amount, leg id, leg output amount) of the stored swap
+    fn stored_swap(conn: &DbConnection, sig: &str) -> (i32, i64, i32, i64) {
+        let (id, output_amount) = with_conn!(conn, c => tx::table
+            .select((tx::id, tx::output_amount))
+            .filter(tx::sig.eq(sig))
+            .first::<(i32, i64)>(c))
+        .unwrap();
+        let (leg_id, leg_output_amount) = with_conn!(conn, c => swap_legs::table
+            .select((swap_legs::id, swap_legs::output_amount))
+            .filter(swap_legs::sig.eq(sig))
+            .first::<(i32, i64)>(c))
+        .unwrap();
+        (id, output_amount, leg_id, leg_output_amount)
+    }
+
+    fn store(sink: &mut DatabaseSink, swaps: &[SwapEvent]) {
+        sink.write_batch(swaps).unwrap();
+        sink.checkpoint(&checkpoint(&swaps[swaps.len() - 1].sig))
+            .unwrap();
+    }
+
+    #[test]
+    fn existing_swaps_are_kept_without_update_existing() {
+        let (_dir, conn) = temp_db();
+        let mut sink = DatabaseSink::new(vec![], conn, false);
+        store(&mut sink, &[swap("a", 100, 1_000_000, 999_000)]);
+        let first = stored_swap(&sink.conn, "a");
+
+        store(&mut sink, &[swap("a", 100, 1_000_000, 998_000)]);
+        assert_eq!(stored_swap(&sink.conn, "a"), first);
+    }
+
+    #[test]
+    fn upserts_keep_ids_and_legs() {
+        let (_dir, conn) = temp_db();
+        let mut sink = DatabaseSink::new(vec![], conn, true);
+        store(
+            &mut sink,
+            &[
+                swap("a", 100, 1_000_000, 999_000),
+                swap("b", 101, 1_000_000, 999_000),
+            ],
+        );
+        let (id, _, leg_id, _) = stored_swap(&sink.conn, "a");
+
+        store(&mut sink, &[swap("a", 100, 1_000_000, 998_000)]);
+        assert_eq!(stored_swap(&sink.conn, "a"), (id, 998_000, leg_id, 998_000));
+        assert_eq!(stored_sigs(&sink.conn), vec!["a", "b"]);
+    }
 }
@@ -1,8 +1,38 @@
+// diesel 1.x table!/derive macros expand to impls the newer non_local_definitions lint rejects
+#![allow(non_local_definitions)]
+
+use solcrawl::archive::{ArchiveFormat, ArchiveWriter};
+use solcrawl::cache::TransactionCache;
+use solcrawl::crawlers::block_crawler::BlockCrawler;
+use solcrawl::crawlers::historical_crawler::HistoricalCrawler;
+use solcrawl::crawlers::live_crawler::{split_events, LiveCrawler};
+use solcrawl::crawlers::websocket_crawler::WebSocketCrawler;
+use solcrawl::crawlers::{CrawledTransaction, TransactionReceiver};
+use solcrawl::extractors::jupiter_swap::{JupiterSwapExtractor, SwapEvent};
 use solcrawl::filters::jupiter_swap_token::JupiterSwapToken;
+use solcrawl::filters::TransactionFilter;
+use solcrawl::monitors::depeg::{DepegAlert, DepegConfig, DepegMonitor, LogNotifier};
+use solcrawl::pipeline::Pipeline;
+use solcrawl::sinks::broadcast::{BroadcastHub, BroadcastSink};
+use solcrawl::sinks::jsonl_file::JsonlFileSink;
+use solcrawl::sinks::webhook::WebhookSink;
+use solcrawl::sinks::{checkpoint_name, cursor_checkpoint_name, Sink};
+use solcrawl::tokens::mint_registry::MintRegistry;
+use solcrawl::tokens::{TOKEN_2022_PROGRAM, TOKEN_PROGRAM};
 
+use crate::cli::{BackfillArgs, Cli, Command, CrawlOptions};
+use crate::finality::{start_trackers, FinalityTracker};
+use crate::handle_txs::{unapproved_token, DatabaseSink};
+use crate::storage::candles::rebuild_candles;
+use crate::storage::checkpoints::load_checkpoint;
+use crate::storage::conn::DbConnection;
 use crate::storage::models::tx::Tx;
+use clap::Parser;
 use diesel::prelude::*;
+use solana_sdk::commitment_config::CommitmentConfig;
 use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[macro_use]
@@ -11,67 +41,406 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
+pub mod api;
+pub mod cli;
+pub mod config;
+pub mod export;
+pub mod finality;
 pub mod handle_txs;
+pub mod pipelines;
+pub mod stats;
 pub mod storage;
+pub mod stream;
+pub mod verify;
 
-const RPC_URL: &str = "https://api.mainnet-beta.solana.com";
-const WS_URL: &str = "wss://api.mainnet-beta.solana.com";
+// pipeline and source names, together they name the checkpoints
+const RUN_PIPELINE: &str = "jupiter";
+const BACKFILL_PIPELINE: &str = "jupiter-backfill";
+const LIVE_PIPELINE: &str = "jupiter-live";
+const LIVE_SOURCE: &str = "live";
+const HISTORICAL_SOURCE: &str = "historical";
+const BLOCKS_SOURCE: &str = "blocks";
 
-const JUPITER_PROGRAM: &str = "JUP2jxvXaqu7NQY1GmNF4m1vodw12LVXYxbFL2uJvfo";
-const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const ARCHIVE_SEGMENT_BYTES: u64 = 256 * 1024 * 1024;
+const ALERT_TIMEOUT: Duration = Duration::from_secs(10);
 
-const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
-const USDT_MINT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
-const UST_MINT: &str = "9vMJfxuKxXBoEa7rM12mYLMwTacLMLDJqHozw96WQL8i";
+// where the archive sits in the pipeline, see --archive-stage
+enum ArchiveStage {
+    // every transaction the crawlers fetched
+    PreFilter,
+    // only the swaps between approved tokens
+    PostFilter,
+}
 
-diesel_migrations::embed_migrations!();
+type Archive = (ArchiveWriter, ArchiveStage);
 
-// crawling jupiter for stable swaps
 fn main() -> Result<(), Box<dyn Error>> {
-    let conn = storage::conn::establish_connection()?;
-    embedded_migrations::run(&conn)?;
+    // .env may hold DATABASE_URL and the other settings read by the cli
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    let options = cli.options;
+    let command = cli.command.unwrap_or(Command::Run);
 
-    let mut curr_sig: Option<String> = None;
-    let res = crate::storage::schema::tx::table
-        .order(crate::storage::schema::tx::block_time.asc())
-        .first::<Tx>(&conn);
-    if let Ok(tx) = res {
-        curr_sig = Some(tx.sig)
+    // pipelines open their own database connections, and may not need one at all
+    if let Command::Pipelines(args) = command {
+        return pipelines::run_pipelines(&options, args);
     }
 
+    let database_url = options
+        .database_url
+        .clone()
+        .ok_or("DATABASE_URL or --database-url is required")?;
+    let conn = DbConnection::establish(&database_url)?;
+    conn.run_migrations()?;
+
+    match command {
+        Command::Run => {
+            let archive = open_archive(&options)?;
+            let cache = open_tx_cache(&options)?;
+            let curr_sigs = resume_sigs(&conn, RUN_PIPELINE, &options.programs);
+            let ws_recv = start_live(&options, &cache)?;
+            let h_recv = start_historical(&options, &cache, curr_sigs)?;
+            let sources = vec![(LIVE_SOURCE, ws_recv), (HISTORICAL_SOURCE, h_recv)];
+            run_pipeline(RUN_PIPELINE, &options, conn, sources, archive)?;
+        }
+        Command::Backfill(args) => {
+            let archive = open_archive(&options)?;
+            let cache = open_tx_cache(&options)?;
+            let source = start_backfill(&options, &cache, &conn, args)?;
+            run_pipeline(BACKFILL_PIPELINE, &options, conn, vec![source], archive)?;
+        }
+        Command::Live => {
+            let archive = open_archive(&options)?;
+            let cache = open_tx_cache(&options)?;
+            let recv = start_live(&options, &cache)?;
+            let sources = vec![(LIVE_SOURCE, recv)];
+            run_pipeline(LIVE_PIPELINE, &options, conn, sources, archive)?;
+        }
+        Command::Export(args) => {
+            let count = export::export(&conn, args)?;
+            println!("exported {} swaps", count);
+        }
+        Command::Stats => stats::print_stats(&conn)?,
+        Command::Serve(args) => api::serve(&database_url, args)?,
+        Command::Reconcile => {
+            let tracker = FinalityTracker::new(
+                conn,
+                options.rpc_url.clone(),
+                options.candle_intervals(),
+                finality_interval(&options),
+            );
+            let (finalized, removed) = tracker.sweep()?;
+            println!(
+                "finalized {} swaps, removed {} rolled back swaps",
+                finalized, removed
+            );
+        }
+        Command::Verify(args) => {
+            let cache = open_tx_cache(&options)?;
+            verify::verify(conn, &options, args, cache.clone())?.print();
+            if let Some(cache) = cache {
+                println!(
+                    "tx cache - {} hits, {} misses",
+                    cache.hits(),
+                    cache.misses()
+                );
+            }
+        }
+        Command::RebuildCandles => {
+            let count = rebuild_candles(&conn, &options.candle_intervals())?;
+            println!("rebuilt candles from {} swaps", count);
+        }
+        // migrations have already been run
+        Command::Migrate => println!("database is up to date"),
+        Command::Pipelines(_) => unreachable!("pipelines are run before connecting"),
+    }
+
+    Ok(())
+}
+
+// the historical crawler continues every program below the last transaction it committed
+// for it, or below the oldest stored swap when the program has no checkpoint yet
+fn resume_sigs(conn: &DbConnection, pipeline: &str, programs: &[String]) -> Vec<Option<String>> {
+    let mut oldest = None;
+    programs
+        .iter()
+        .map(|program| {
+            let name = cursor_checkpoint_name(pipeline, HISTORICAL_SOURCE, program);
+            match load_checkpoint(conn, &name) {
+                Ok(Some(checkpoint)) => {
+                    println!("curr_sig - {} from checkpoint {}", checkpoint.sig, name);
+                    return Some(checkpoint.sig);
+                }
+                Ok(None) => {}
+                Err(e) => println!("checkpoint err - {}", e),
+            }
+            oldest.get_or_insert_with(|| oldest_sig(conn)).clone()
+        })
+        .collect()
+}
+
+// the block walk continues from its checkpoint when that lies within the walked range,
+// after the last transaction it committed in that slot
+fn resume_slot(
+    conn: &DbConnection,
+    start_slot: u64,
+    end_slot: Option<u64>,
+) -> (u64, Option<String>) {
+    let name = checkpoint_name(BACKFILL_PIPELINE, BLOCKS_SOURCE);
+    match load_checkpoint(conn, &name) {
+        Ok(Some(checkpoint))
+            if checkpoint.slot as u64 >= start_slot
+                && end_slot.is_none_or(|end| checkpoint.slot as u64 <= end) =>
+        {
+            println!("curr_slot - {} from checkpoint {}", checkpoint.slot, name);
+            (checkpoint.slot as u64, Some(checkpoint.sig))
+        }
+        Ok(_) => (start_slot, None),
+        Err(e) => {
+            println!("checkpoint err - {}", e);
+            (start_slot, None)
+        }
+    }
+}
+
+fn oldest_sig(conn: &DbConnection) -> Option<String> {
+    let res = with_conn!(conn, c => crate::storage::schema::tx::table
+        .order(crate::storage::schema::tx::block_time.asc())
+        .first::<Tx>(c));
+    let curr_sig = res.ok().map(|tx| tx.sig);
     println!("curr_sig - {:?}", curr_sig);
+    curr_sig
+}
+
+// crawlers publish every transaction they fetch, the pipeline filters them
+fn start_live(
+    options: &CrawlOptions,
+    cache: &Option<Arc<TransactionCache>>,
+) -> Result<TransactionReceiver, Box<dyn Error>> {
+    if options.commitment != "finalized" {
+        return start_live_unfinalized(options, cache);
+    }
+    let (crawler, recv) = WebSocketCrawler::new(
+        options.programs.clone(),
+        options.rpc_url.clone(),
+        options.ws_url.clone(),
+        vec![],
+        sleep_duration(options),
+        options.encoding(),
+    );
+    let mut crawler = crawler.with_cache(cache.clone());
+    std::thread::spawn(move || crawler.crawl());
+    println!("started crawling, please wait - establishing web socket connection (this can take upto 20 seconds)");
+    Ok(recv)
+}
+
+// swaps are stored as confirmed and a tracker finalizes or removes them as the cluster decides
+fn start_live_unfinalized(
+    options: &CrawlOptions,
+    cache: &Option<Arc<TransactionCache>>,
+) -> Result<TransactionReceiver, Box<dyn Error>> {
+    let commitment = CommitmentConfig::from_str(&options.commitment)?;
+    let (crawler, events) = LiveCrawler::new(
+        options.programs.clone(),
+        options.rpc_url.clone(),
+        options.ws_url.clone(),
+        vec![],
+        sleep_duration(options),
+        commitment,
+        options.encoding(),
+    );
+    let mut crawler = crawler.with_cache(cache.clone());
+    std::thread::spawn(move || crawler.crawl());
+    let (recv, finality) = split_events(events);
 
-    let approved_tokens = vec![
-        USDC_MINT.to_string(),
-        USDT_MINT.to_string(),
-        UST_MINT.to_string(),
-    ];
-    let swap_filter = Box::new(JupiterSwapToken {
-        approved_tokens: approved_tokens.clone(),
-        token_program: TOKEN_PROGRAM.to_string(),
-    });
-
-    let (ws_crawler, ws_recv) = solcrawl::crawlers::websocket_crawler::WebSocketCrawler::new(
-        JUPITER_PROGRAM.to_string(),
-        RPC_URL.to_string(),
-        WS_URL.to_string(),
-        vec![swap_filter.clone()],
-        None,
+    let database_url = options
+        .database_url
+        .clone()
+        .ok_or("DATABASE_URL or --database-url is required")?;
+    start_trackers(
+        &options.rpc_url,
+        &[(database_url, options.candle_intervals())],
+        finality,
+        finality_interval(options),
+    )?;
+    println!(
+        "started crawling at {} commitment, please wait - establishing web socket connection",
+        options.commitment
     );
+    Ok(recv)
+}
 
-    let (mut h_crawler, h_recv) = solcrawl::crawlers::historical_crawler::HistoricalCrawler::new(
-        JUPITER_PROGRAM.to_string(),
-        RPC_URL.to_string(),
-        vec![swap_filter],
-        None,
-        curr_sig,
+fn start_historical(
+    options: &CrawlOptions,
+    cache: &Option<Arc<TransactionCache>>,
+    curr_sigs: Vec<Option<String>>,
+) -> Result<TransactionReceiver, Box<dyn Error>> {
+    let (crawler, recv) = HistoricalCrawler::new(
+        options.programs.clone(),
+        options.rpc_url.clone(),
+        vec![],
+        sleep_duration(options),
+        curr_sigs,
+        options.encoding(),
     )?;
+    let mut crawler = crawler.with_cache(cache.clone());
+    std::thread::spawn(move || crawler.crawl());
+    Ok(recv)
+}
 
-    std::thread::spawn(move || ws_crawler.crawl());
-    std::thread::spawn(move || h_crawler.crawl());
+// block walks fetch whole blocks and do not go through the transaction cache
+fn start_backfill(
+    options: &CrawlOptions,
+    cache: &Option<Arc<TransactionCache>>,
+    conn: &DbConnection,
+    args: BackfillArgs,
+) -> Result<(&'static str, TransactionReceiver), Box<dyn Error>> {
+    let start_slot = match args.start_slot {
+        Some(start_slot) => start_slot,
+        None => {
+            let curr_sigs = match args.before_sig {
+                Some(before_sig) => vec![Some(before_sig); options.programs.len()],
+                None => resume_sigs(conn, BACKFILL_PIPELINE, &options.programs),
+            };
+            let recv = start_historical(options, cache, curr_sigs)?;
+            return Ok((HISTORICAL_SOURCE, recv));
+        }
+    };
 
-    println!("started crawling, please wait - establishing web socket connection (this can take upto 20 seconds)");
-    crate::handle_txs::handle_txs(&approved_tokens, conn, vec![ws_recv, h_recv]);
+    let (start_slot, resume_sig) = resume_slot(conn, start_slot, args.end_slot);
+    let (crawler, recv) = BlockCrawler::new(
+        options.programs.clone(),
+        options.rpc_url.clone(),
+        vec![],
+        sleep_duration(options),
+        start_slot,
+        args.end_slot,
+        options.encoding(),
+    );
+    let mut crawler = crawler.with_resume_sig(resume_sig);
+    std::thread::spawn(move || crawler.crawl());
+    Ok((BLOCKS_SOURCE, recv))
+}
 
-    Ok(())
+fn sleep_duration(options: &CrawlOptions) -> Option<Duration> {
+    options.sleep_ms.map(Duration::from_millis)
+}
+
+fn finality_interval(options: &CrawlOptions) -> Duration {
+    Duration::from_secs(options.finality_interval_secs)
+}
+
+fn build_filters(options: &CrawlOptions) -> Vec<Box<dyn TransactionFilter + Send>> {
+    vec![Box::new(JupiterSwapToken {
+        approved_tokens: options.tokens.clone(),
+        token_programs: vec![TOKEN_PROGRAM.to_string(), TOKEN_2022_PROGRAM.to_string()],
+    })]
+}
+
+// archiving keeps every fetched transaction (or only swaps with the post stage)
+// so extraction can be re-run with the replay crawler instead of re-crawling mainnet
+fn open_archive(options: &CrawlOptions) -> Result<Option<Archive>, Box<dyn Error>> {
+    let dir = match &options.archive_dir {
+        Some(dir) => dir.clone(),
+        None => return Ok(None),
+    };
+    let stage = match options.archive_stage.as_str() {
+        "post" => ArchiveStage::PostFilter,
+        _ => ArchiveStage::PreFilter,
+    };
+    let writer = ArchiveWriter::new(dir, ArchiveFormat::Jsonl, true, ARCHIVE_SEGMENT_BYTES)?;
+    Ok(Some((writer, stage)))
+}
+
+// one cache is shared by every crawler, it keeps its index in memory
+fn open_tx_cache(options: &CrawlOptions) -> Result<Option<Arc<TransactionCache>>, Box<dyn Error>> {
+    let dir = match &options.tx_cache_dir {
+        Some(dir) => dir.clone(),
+        None => return Ok(None),
+    };
+    let cache = TransactionCache::open(dir.clone(), options.tx_cache_max_mb * 1024 * 1024)?;
+    println!(
+        "tx cache {} - {} transactions, {:.1} MB",
+        dir.display(),
+        cache.len(),
+        cache.bytes() as f64 / (1024.0 * 1024.0)
+    );
+    Ok(Some(Arc::new(cache)))
+}
+
+// alerts always go to the log, and to the webhook and file when they are set
+fn build_depeg_monitor(options: &CrawlOptions) -> Result<DepegMonitor, Box<dyn Error>> {
+    let mut notifiers: Vec<Box<dyn Sink<DepegAlert> + Send>> =
+        vec![Box::new(LogNotifier::default())];
+    if let Some(url) = &options.alert_webhook {
+        notifiers.push(Box::new(WebhookSink::new(url.clone(), ALERT_TIMEOUT)?));
+    }
+    if let Some(path) = &options.alert_file {
+        notifiers.push(Box::new(JsonlFileSink::new(path)?));
+    }
+    let config = DepegConfig {
+        stable_tokens: options.tokens.clone(),
+        thresholds: options.depeg_thresholds.clone(),
+        window_secs: options.depeg_window_secs,
+        sustain_secs: options.depeg_sustain_secs,
+        min_volume: options.depeg_min_volume,
+    };
+    DepegMonitor::new(config, notifiers)
+}
+
+fn run_pipeline(
+    name: &str,
+    options: &CrawlOptions,
+    conn: DbConnection,
+    sources: Vec<(&str, TransactionReceiver)>,
+    archive: Option<Archive>,
+) -> Result<(), Box<dyn Error>> {
+    let (names, recvs): (Vec<_>, Vec<_>) = sources
+        .into_iter()
+        .map(|(name, recv)| (name.to_string(), recv))
+        .unzip();
+    let mint_registry = MintRegistry::new(Some(options.rpc_url.clone()));
+    if let Some(path) = &options.token_list {
+        let count = mint_registry.load_token_list(path.as_str())?;
+        println!("loaded {} tokens from token list", count);
+    }
+    let extractor = JupiterSwapExtractor {
+        mint_registry: Some(Arc::new(mint_registry)),
+        stable_tokens: options.tokens.clone(),
+        ..Default::default()
+    };
+
+    let mut sinks: Vec<Box<dyn Sink<SwapEvent> + Send>> = vec![Box::new(
+        DatabaseSink::new(options.tokens.clone(), conn, options.update_existing)
+            .with_candles(options.candle_intervals()),
+    )];
+    if options.depeg_monitor {
+        sinks.push(Box::new(build_depeg_monitor(options)?));
+    }
+    // after the database sink, so stream clients only see committed swaps
+    if let Some(listen) = &options.stream_listen {
+        let hub = BroadcastHub::new(options.stream_replay);
+        stream::serve_stream(listen, hub.clone(), options.stream_replay)?;
+        let tokens = options.tokens.clone();
+        sinks.push(Box::new(BroadcastSink::new(hub).with_filter(Box::new(
+            move |swap| unapproved_token(&tokens, swap).is_none(),
+        ))));
+    }
+    let mut unfiltered_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>> = Vec::new();
+    let mut raw_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>> = Vec::new();
+    match archive {
+        Some((writer, ArchiveStage::PreFilter)) => unfiltered_sinks.push(Box::new(writer)),
+        Some((writer, ArchiveStage::PostFilter)) => raw_sinks.push(Box::new(writer)),
+        None => {}
+    }
+    Pipeline::new(name.to_string(), recvs, extractor, sinks)
+        .with_source_names(names)
+        .with_filters(build_filters(options))
+        .with_unfiltered_sinks(unfiltered_sinks)
+        .with_raw_sinks(raw_sinks)
+        .with_batching(
+            options.batch_size,
+            Duration::from_millis(options.flush_interval_ms),
+        )
+        .run()
 }
@@ -0,0 +1,643 @@
+use solcrawl::archive::ArchiveWriter;
+use solcrawl::cache::TransactionCache;
+use solcrawl::candles::CandleInterval;
+use solcrawl::crawlers::block_crawler::BlockCrawler;
+use solcrawl::crawlers::historical_crawler::HistoricalCrawler;
+use solcrawl::crawlers::live_crawler::{split_events, LiveCrawler};
+use solcrawl::crawlers::replay_crawler::FileReplayCrawler;
+use solcrawl::crawlers::websocket_crawler::WebSocketCrawler;
+use solcrawl::crawlers::{CrawledTransaction, TransactionReceiver};
+use solcrawl::extractors::jupiter_swap::{JupiterSwapExtractor, SwapEvent};
+use solcrawl::filters::jupiter_swap_token::JupiterSwapToken;
+use solcrawl::filters::transaction::{FailedTransaction, Mentions};
+use solcrawl::filters::TransactionFilter;
+use solcrawl::monitors::depeg::{DepegAlert, DepegConfig, DepegMonitor, LogNotifier};
+use solcrawl::pipeline::Pipeline;
+use solcrawl::sinks::broadcast::{BroadcastHub, BroadcastSink};
+use solcrawl::sinks::export::{ExportFilter, ExportSink};
+use solcrawl::sinks::jsonl_file::JsonlFileSink;
+use solcrawl::sinks::stdout::StdoutSink;
+use solcrawl::sinks::webhook::WebhookSink;
+use solcrawl::sinks::{checkpoint_name, cursor_checkpoint_name, Sink};
+use solcrawl::tokens::mint_registry::MintRegistry;
+use solcrawl::tokens::{TOKEN_2022_PROGRAM, TOKEN_PROGRAM};
+
+use crate::cli::{parse_encoding, CrawlOptions, PipelinesArgs};
+use crate::config::{
+    Config, ExtractorConfig, FilterConfig, NotifierConfig, PipelineConfig, SinkConfig,
+    SourceConfig, WebhookItems,
+};
+use crate::finality::start_trackers;
+use crate::handle_txs::{unapproved_token, DatabaseSink};
+use crate::storage::checkpoints::load_checkpoint;
+use crate::storage::conn::DbConnection;
+use crate::stream::serve_stream;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::UiTransactionEncoding;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// urls a pipeline falls back to when its config leaves them out, and the
+// transaction cache shared by the crawlers of every pipeline
+struct Defaults {
+    database_url: Option<String>,
+    rpc_url: String,
+    ws_url: String,
+    tx_cache: Option<Arc<TransactionCache>>,
+    encoding: UiTransactionEncoding,
+}
+
+// the parts of a pipeline that can fail to open, built for every pipeline
+// before any crawler is started
+struct PreparedPipeline {
+    config: PipelineConfig,
+    filters: Vec<Box<dyn TransactionFilter + Send>>,
+    extractor: JupiterSwapExtractor,
+    sinks: Vec<Box<dyn Sink<SwapEvent> + Send>>,
+    raw_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>>,
+    // url and candle intervals of every database sink, for the finality trackers
+    databases: Vec<(String, Vec<CandleInterval>)>,
+}
+
+// TODO dont print to std out - use a logger
+pub fn run_pipelines(options: &CrawlOptions, args: PipelinesArgs) -> Result<(), Box<dyn Error>> {
+    let config = Config::load(&args.config)?;
+    config.validate(options.database_url.as_deref())?;
+    if args.check {
+        println!(
+            "{}: {} pipelines are valid",
+            args.config.display(),
+            config.pipelines.len()
+        );
+        return Ok(());
+    }
+
+    let defaults = Defaults {
+        database_url: config
+            .database_url
+            .clone()
+            .or_else(|| options.database_url.clone()),
+        rpc_url: config
+            .rpc_url
+            .clone()
+            .unwrap_or_else(|| options.rpc_url.clone()),
+        ws_url: config
+            .ws_url
+            .clone()
+            .unwrap_or_else(|| options.ws_url.clone()),
+        tx_cache: open_tx_cache(&config, options)?,
+        encoding: parse_encoding(config.encoding.as_deref().unwrap_or(&options.encoding))?,
+    };
+
+    let mut prepared = Vec::new();
+    for pipeline in config.pipelines {
+        let name = pipeline.name.clone();
+        let p = prepare(pipeline, &defaults).map_err(|e| format!("pipeline {}: {}", name, e))?;
+        prepared.push(p);
+    }
+
+    let mut handles = Vec::new();
+    for p in prepared {
+        let name = p.config.name.clone();
+        let recvs = start_sources(&p.config, &defaults, &p.databases)
+            .map_err(|e| format!("pipeline {}: {}", name, e))?;
+        let sources = (0..p.config.sources.len())
+            .map(|i| source_name(&p.config, i))
+            .collect();
+        let mut pipeline = Pipeline::new(name.clone(), recvs, p.extractor, p.sinks)
+            .with_source_names(sources)
+            .with_filters(p.filters)
+            .with_raw_sinks(p.raw_sinks)
+            .with_batching(
+                p.config.batch_size,
+                Duration::from_millis(p.config.flush_interval_ms),
+            );
+        println!("started pipeline {}", name);
+        handles.push((
+            name,
+            std::thread::spawn(move || pipeline.run().map_err(|e| e.to_string())),
+        ));
+    }
+
+    // pipelines with only bounded sources (blocks with an end_slot, replay) finish on their own,
+    // and a pipeline stops when a batch can not be written
+    for (name, handle) in handles {
+        match handle.join() {
+            Ok(Ok(())) => println!("pipeline {} finished", name),
+            Ok(Err(e)) => return Err(format!("pipeline {} stopped - {}", name, e).into()),
+            Err(_) => return Err(format!("pipeline {} panicked", name).into()),
+        }
+    }
+    Ok(())
+}
+
+fn prepare(
+    config: PipelineConfig,
+    defaults: &Defaults,
+) -> Result<PreparedPipeline, Box<dyn Error>> {
+    let filters = config.filters.iter().map(build_filter).collect();
+
+    let extractor = match &config.extractor {
+        ExtractorConfig::JupiterSwap {
+            stable_tokens,
+            token_list,
+        } => {
+            let mint_registry = MintRegistry::new(Some(defaults.rpc_url.clone()));
+            if let Some(path) = token_list {
+                let count = mint_registry.load_token_list(path.as_str())?;
+                println!("loaded {} tokens from token list", count);
+            }
+            JupiterSwapExtractor {
+                mint_registry: Some(Arc::new(mint_registry)),
+                stable_tokens: stable_tokens.clone(),
+                ..Default::default()
+            }
+        }
+    };
+
+    let mut sinks: Vec<Box<dyn Sink<SwapEvent> + Send>> = Vec::new();
+    let mut raw_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>> = Vec::new();
+    let mut databases = Vec::new();
+    // approved tokens of the database sinks so far, streams only send what they store
+    let mut approved = Vec::new();
+    for sink in &config.sinks {
+        match sink {
+            SinkConfig::Database {
+                database_url,
+                approved_tokens,
+                update_existing,
+                candle_intervals,
+            } => {
+                // validation already made sure one of the urls is set
+                let url = database_url
+                    .as_ref()
+                    .or(defaults.database_url.as_ref())
+                    .ok_or("database sink needs database_url")?;
+                let conn = DbConnection::establish(url)?;
+                conn.run_migrations()?;
+                sinks.push(Box::new(
+                    DatabaseSink::new(approved_tokens.clone(), conn, *update_existing)
+                        .with_candles(candle_intervals.clone()),
+                ));
+                databases.push((url.clone(), candle_intervals.clone()));
+                approved.push(approved_tokens.clone());
+            }
+            SinkConfig::Jsonl { path } => sinks.push(Box::new(JsonlFileSink::new(path)?)),
+            SinkConfig::Stdout => sinks.push(Box::new(StdoutSink::default())),
+            SinkConfig::Export {
+                dir,
+                format,
+                row_group_size,
+            } => sinks.push(Box::new(ExportSink::new(
+                dir.clone(),
+                *format,
+                *row_group_size,
+                ExportFilter::default(),
+            )?)),
+            SinkConfig::Webhook {
+                url,
+                items,
+                mode,
+                secret,
+                secret_env,
+                timeout_ms,
+                max_retries,
+                backoff_ms,
+                max_retry_secs,
+                spool_dir,
+            } => {
+                let mut webhook =
+                    WebhookSink::new(url.clone(), Duration::from_millis(*timeout_ms))?
+                        .with_mode(*mode)
+                        .with_retries(*max_retries, Duration::from_millis(*backoff_ms))
+                        .with_max_retry_time(Duration::from_secs(*max_retry_secs));
+                let secret = match secret_env {
+                    Some(name) => Some(std::env::var(name)?),
+                    None => secret.clone(),
+                };
+                if let Some(secret) = secret {
+                    webhook = webhook.with_secret(secret);
+                }
+                if let Some(dir) = spool_dir {
+                    webhook = webhook.with_spool(dir.clone())?;
+                }
+                match items {
+                    WebhookItems::Swaps => sinks.push(Box::new(webhook)),
+                    WebhookItems::Transactions => raw_sinks.push(Box::new(webhook)),
+                }
+            }
+            SinkConfig::Stream { listen, replay } => {
+                let hub = BroadcastHub::new(*replay);
+                serve_stream(listen, hub.clone(), *replay)?;
+                let mut broadcast = BroadcastSink::new(hub);
+                if !approved.is_empty() {
+                    let approved = approved.clone();
+                    broadcast = broadcast.with_filter(Box::new(move |swap| {
+                        approved
+                            .iter()
+                            .any(|tokens| unapproved_token(tokens, swap).is_none())
+                    }));
+                }
+                sinks.push(Box::new(broadcast));
+            }
+            SinkConfig::DepegMonitor {
+                stable_tokens,
+                thresholds,
+                window_secs,
+                sustain_secs,
+                min_volume,
+                notifiers,
+            } => {
+                let config = DepegConfig {
+                    stable_tokens: stable_tokens.clone(),
+                    thresholds: thresholds.clone(),
+                    window_secs: *window_secs,
+                    sustain_secs: *sustain_secs,
+                    min_volume: *min_volume,
+                };
+                let notifiers = notifiers
+                    .iter()
+                    .map(build_notifier)
+                    .collect::<Result<Vec<_>, _>>()?;
+                sinks.push(Box::new(DepegMonitor::new(config, notifiers)?));
+            }
+            SinkConfig::Archive {
+                dir,
+                format,
+                compress,
+                max_segment_bytes,
+            } => raw_sinks.push(Box::new(ArchiveWriter::new(
+                dir.clone(),
+                *format,
+                *compress,
+                *max_segment_bytes,
+            )?)),
+        }
+    }
+
+    Ok(PreparedPipeline {
+        config,
+        filters,
+        extractor,
+        sinks,
+        raw_sinks,
+        databases,
+    })
+}
+
+fn build_notifier(
+    notifier: &NotifierConfig,
+) -> Result<Box<dyn Sink<DepegAlert> + Send>, Box<dyn Error>> {
+    Ok(match notifier {
+        NotifierConfig::Log => Box::new(LogNotifier::default()),
+        NotifierConfig::Webhook { url, timeout_ms } => Box::new(WebhookSink::new(
+            url.clone(),
+            Duration::from_millis(*timeout_ms),
+        )?),
+        NotifierConfig::File { path } => Box::new(JsonlFileSink::new(path)?),
+    })
+}
+
+fn build_filter(filter: &FilterConfig) -> Box<dyn TransactionFilter + Send> {
+    match filter {
+        FilterConfig::SwapTokens { tokens } => Box::new(JupiterSwapToken {
+            approved_tokens: tokens.clone(),
+            token_programs: vec![TOKEN_PROGRAM.to_string(), TOKEN_2022_PROGRAM.to_string()],
+        }),
+        FilterConfig::Failed => Box::new(FailedTransaction::default()),
+        FilterConfig::Mentions { addresses } => Box::new(Mentions {
+            addresses: addresses.clone(),
+        }),
+    }
+}
+
+// the config's cache settings win over the command line
+fn open_tx_cache(
+    config: &Config,
+    options: &CrawlOptions,
+) -> Result<Option<Arc<TransactionCache>>, Box<dyn Error>> {
+    let dir = match config
+        .tx_cache_dir
+        .as_ref()
+        .or(options.tx_cache_dir.as_ref())
+    {
+        Some(dir) => dir.clone(),
+        None => return Ok(None),
+    };
+    let max_mb = config.tx_cache_max_mb.unwrap_or(options.tx_cache_max_mb);
+    let cache = TransactionCache::open(dir.clone(), max_mb * 1024 * 1024)?;
+    println!(
+        "tx cache {} - {} transactions, {:.1} MB",
+        dir.display(),
+        cache.len(),
+        cache.bytes() as f64 / (1024.0 * 1024.0)
+    );
+    Ok(Some(Arc::new(cache)))
+}
+
+// names the checkpoint of a source, its type and position in the pipeline
+fn source_name(config: &PipelineConfig, i: usize) -> String {
+    let kind = match &config.sources[i] {
+        SourceConfig::Live { .. } => "live",
+        SourceConfig::Historical { .. } => "historical",
+        SourceConfig::Blocks { .. } => "blocks",
+        SourceConfig::Replay { .. } => "replay",
+    };
+    format!("{}-{}", kind, i)
+}
+
+// checkpoints are kept in the first database of the pipeline
+fn checkpoint_conn(
+    config: &PipelineConfig,
+    databases: &[(String, Vec<CandleInterval>)],
+) -> Option<DbConnection> {
+    match databases.first() {
+        Some((url, _)) => match DbConnection::establish(url) {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                println!("{} checkpoint err - {}", config.name, e);
+                None
+            }
+        },
+        None => None,
+    }
+}
+
+// the signatures a historical source continues every program below, the last one it
+// committed for the program, before_sig until then
+fn resume_sigs(
+    config: &PipelineConfig,
+    i: usize,
+    databases: &[(String, Vec<CandleInterval>)],
+    programs: &[String],
+    before_sig: &Option<String>,
+) -> Vec<Option<String>> {
+    let conn = checkpoint_conn(config, databases);
+    programs
+        .iter()
+        .map(|program| {
+            let name = cursor_checkpoint_name(&config.name, &source_name(config, i), program);
+            let checkpoint = match &conn {
+                Some(conn) => load_checkpoint(conn, &name),
+                None => Ok(None),
+            };
+            match checkpoint {
+                Ok(Some(checkpoint)) => {
+                    println!("{} resumes below {}", name, checkpoint.sig);
+                    Some(checkpoint.sig)
+                }
+                Ok(None) => before_sig.clone(),
+                Err(e) => {
+                    println!("{} checkpoint err - {}", name, e);
+                    before_sig.clone()
+                }
+            }
+        })
+        .collect()
+}
+
+// the slot a blocks source continues from and the last signature it committed in that
+// slot, start_slot until it has a checkpoint within its range
+fn resume_slot(
+    config: &PipelineConfig,
+    i: usize,
+    databases: &[(String, Vec<CandleInterval>)],
+    start_slot: u64,
+    end_slot: Option<u64>,
+) -> (u64, Option<String>) {
+    let name = checkpoint_name(&config.name, &source_name(config, i));
+    let checkpoint = match checkpoint_conn(config, databases) {
+        Some(conn) => load_checkpoint(&conn, &name),
+        None => Ok(None),
+    };
+    match checkpoint {
+        Ok(Some(checkpoint))
+            if checkpoint.slot as u64 >= start_slot
+                && end_slot.is_none_or(|end| checkpoint.slot as u64 <= end) =>
+        {
+            println!("{} resumes after {}", name, checkpoint.sig);
+            (checkpoint.slot as u64, Some(checkpoint.sig))
+        }
+        Ok(_) => (start_slot, None),
+        Err(e) => {
+            println!("{} checkpoint err - {}", name, e);
+            (start_slot, None)
+        }
+    }
+}
+
+// every source gets its own crawler thread, the pipeline filters what they publish
+fn start_sources(
+    config: &PipelineConfig,
+    defaults: &Defaults,
+    databases: &[(String, Vec<CandleInterval>)],
+) -> Result<Vec<TransactionReceiver>, Box<dyn Error>> {
+    let mut recvs = Vec::new();
+    for (i, source) in config.sources.iter().enumerate() {
+        let recv = match source {
+            SourceConfig::Live {
+                programs,
+                rpc_url,
+                ws_url,
+                sleep_ms,
+                commitment,
+                finality_interval_secs,
+            } if commitment != "finalized" => {
+                let rpc_url = rpc_url.clone().unwrap_or_else(|| defaults.rpc_url.clone());
+                let (crawler, events) = LiveCrawler::new(
+                    programs.clone(),
+                    rpc_url.clone(),
+                    ws_url.clone().unwrap_or_else(|| defaults.ws_url.clone()),
+                    vec![],
+                    sleep_ms.map(Duration::from_millis),
+                    CommitmentConfig::from_str(commitment)?,
+                    defaults.encoding,
+                );
+                let mut crawler = crawler.with_cache(defaults.tx_cache.clone());
+                std::thread::spawn(move || crawler.crawl());
+                let (recv, finality) = split_events(events);
+                start_trackers(
+                    &rpc_url,
+                    databases,
+                    finality,
+                    Duration::from_secs(*finality_interval_secs),
+                )?;
+                recv
+            }
+            SourceConfig::Live {
+                programs,
+                rpc_url,
+                ws_url,
+                sleep_ms,
+                ..
+            } => {
+                let (crawler, recv) = WebSocketCrawler::new(
+                    programs.clone(),
+                    rpc_url.clone().unwrap_or_else(|| defaults.rpc_url.clone()),
+                    ws_url.clone().unwrap_or_else(|| defaults.ws_url.clone()),
+                    vec![],
+                    sleep_ms.map(Duration::from_millis),
+                    defaults.encoding,
+                );
+                let mut crawler = crawler.with_cache(defaults.tx_cache.clone());
+                std::thread::spawn(move || crawler.crawl());
+                recv
+            }
+            SourceConfig::Historical {
+                programs,
+                rpc_url,
+                before_sig,
+                sleep_ms,
+            } => {
+                let curr_sigs = resume_sigs(config, i, databases, programs, before_sig);
+                let (crawler, recv) = HistoricalCrawler::new(
+                    programs.clone(),
+                    rpc_url.clone().unwrap_or_else(|| defaults.rpc_url.clone()),
+                    vec![],
+                    sleep_ms.map(Duration::from_millis),
+                    curr_sigs,
+                    defaults.encoding,
+                )?;
+                let mut crawler = crawler.with_cache(defaults.tx_cache.clone());
+                std::thread::spawn(move || crawler.crawl());
+                recv
+            }
+            SourceConfig::Blocks {
+                programs,
+                rpc_url,
+                start_slot,
+                end_slot,
+                sleep_ms,
+            } => {
+                let (start_slot, resume_sig) =
+                    resume_slot(config, i, databases, *start_slot, *end_slot);
+                let (crawler, recv) = BlockCrawler::new(
+                    programs.clone(),
+                    rpc_url.clone().unwrap_or_else(|| defaults.rpc_url.clone()),
+                    vec![],
+                    sleep_ms.map(Duration::from_millis),
+                    start_slot,
+                    *end_slot,
+                    defaults.encoding,
+                );
+                let mut crawler = crawler.with_resume_sig(resume_sig);
+                std::thread::spawn(move || crawler.crawl());
+                recv
+            }
+            SourceConfig::Replay {
+                paths,
+                programs,
+                speed,
+            } => {
+                let (crawler, recv) =
+                    FileReplayCrawler::new(paths.clone(), programs.clone(), vec![], *speed)?;
+                std::thread::spawn(move || crawler.crawl());
+                recv
+            }
+        };
+        recvs.push(recv);
+    }
+    Ok(recvs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::test_util::temp_db;
+    use solcrawl::sinks::Checkpoint;
+
+    const A: &str = "JUP2jxvXaqu7NQY1GmNF4m1vodw12LVXYxbFL2uJvfo";
+    const B: &str = "JUP3c2Uh3WA4Ng34tw6kPd2G4C5BB21Xo36Je1s32Ph";
+
+    #[test]
+    fn historical_sources_resume_every_program_below_its_own_checkpoint() {
+        let (dir, conn) = temp_db();
+        let url = dir.path().join("test.db").to_string_lossy().to_string();
+        let config: Config = toml::from_str(&format!(
+            r#"
+[[pipelines]]
+name = "p"
+[[pipelines.sources]]
+type = "historical"
+programs = ["{}", "{}"]
+before_sig = "start"
+[pipelines.extractor]
+type = "jupiter_swap"
+[[pipelines.sinks]]
+type = "stdout"
+"#,
+            A, B
+        ))
+        .unwrap();
+        let config = &config.pipelines[0];
+
+        let mut sink = DatabaseSink::new(vec![], conn, false);
+        sink.checkpoint(&Checkpoint {
+            name: cursor_checkpoint_name("p", "historical-0", A),
+            sig: "a".to_string(),
+            slot: 900,
+        })
+        .unwrap();
+
+        let databases = vec![(url, vec![])];
+        let before_sig = Some("start".to_string());
+        assert_eq!(
+            resume_sigs(
+                config,
+                0,
+                &databases,
+                &[A.to_string(), B.to_string()],
+                &before_sig
+            ),
+            vec![Some("a".to_string()), Some("start".to_string())]
+        );
+        // without a database every program starts at before_sig
+        assert_eq!(
+            resume_sigs(config, 0, &[], &[A.to_string()], &before_sig),
+            vec![Some("start".to_string())]
+        );
+    }
+
+    #[test]
+    fn blocks_sources_resume_from_a_checkpoint_in_their_range() {
+        let (dir, conn) = temp_db();
+        let url = dir.path().join("test.db").to_string_lossy().to_string();
+        let config: Config = toml::from_str(&format!(
+            r#"
+[[pipelines]]
+name = "p"
+[[pipelines.sources]]
+type = "blocks"
+programs = ["{}"]
+start_slot = 100
+[pipelines.extractor]
+type = "jupiter_swap"
+[[pipelines.sinks]]
+type = "stdout"
+"#,
+            A
+        ))
+        .unwrap();
+        let config = &config.pipelines[0];
+        let databases = vec![(url, vec![])];
+        assert_eq!(resume_slot(config, 0, &databases, 100, None), (100, None));
+
+        let mut sink = DatabaseSink::new(vec![], conn, false);
+        sink.checkpoint(&Checkpoint {
+            name: checkpoint_name("p", "blocks-0"),
+            sig: "a".to_string(),
+            slot: 150,
+        })
+        .unwrap();
+        assert_eq!(
+            resume_slot(config, 0, &databases, 100, None),
+            (150, Some("a".to_string()))
+        );
+        // a checkpoint outside of the configured range is from an earlier walk
+        assert_eq!(resume_slot(config, 0, &databases, 200, None), (200, None));
+        assert_eq!(resume_slot(config, 0, &databases, 0, Some(99)), (0, None));
+    }
+}
@@ -0,0 +1,53 @@
+use diesel::prelude::*;
+
+use solcrawl::crawlers::Commitment;
+
+use crate::storage::conn::DbConnection;
+use crate::storage::models::checkpoint::Checkpoint;
+use crate::storage::schema::{checkpoint, swap_legs, tx};
+use crate::with_conn;
+use std::error::Error;
+
+// prints how many swaps are stored, the block times they span, the swaps per token pair
+// and where every pipeline has checkpointed
+pub fn print_stats(conn: &DbConnection) -> Result<(), Box<dyn Error>> {
+    let swaps = with_conn!(conn, c => tx::table.count().get_result::<i64>(c)?);
+    let first = with_conn!(conn, c => tx::table
+        .select(diesel::dsl::min(tx::block_time))
+        .first::<Option<i64>>(c)?);
+    let last = with_conn!(conn, c => tx::table
+        .select(diesel::dsl::max(tx::block_time))
+        .first::<Option<i64>>(c)?);
+    let legs = with_conn!(conn, c => swap_legs::table.count().get_result::<i64>(c)?);
+    println!("swaps - {}", swaps);
+    let unfinalized = with_conn!(conn, c => tx::table
+        .filter(tx::commitment.ne(Commitment::Finalized.as_str()))
+        .count()
+        .get_result::<i64>(c)?);
+    println!("swap legs - {}", legs);
+    println!("unfinalized swaps - {}", unfinalized);
+    if let (Some(first), Some(last)) = (first, last) {
+        println!("block times - {} to {}", first, last);
+    }
+
+    let pairs = with_conn!(conn, c => tx::table
+        .select((tx::input_token, tx::output_token))
+        .distinct()
+        .load::<(String, String)>(c)?);
+    for (input, output) in pairs {
+        let count = with_conn!(conn, c => tx::table
+            .filter(tx::input_token.eq(&input))
+            .filter(tx::output_token.eq(&output))
+            .count()
+            .get_result::<i64>(c)?);
+        println!("pair {} -> {} - {}", input, output, count);
+    }
+
+    let checkpoints = with_conn!(conn, c => checkpoint::table
+        .order(checkpoint::name.asc())
+        .load::<Checkpoint>(c)?);
+    for cp in checkpoints {
+        println!("checkpoint {} - {} at slot {}", cp.name, cp.sig, cp.slot);
+    }
+    Ok(())
+}
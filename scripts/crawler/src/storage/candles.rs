@@ -0,0 +1,186 @@
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Text};
+
+use solcrawl::candles::{aggregate, Candle as CandleEvent, CandleInterval};
+
+use crate::storage::conn::DbConnection;
+use crate::storage::models::tx::Tx;
+use crate::storage::schema::{candles, tx};
+use crate::with_conn;
+use std::error::Error;
+
+// swaps loaded per query while rebuilding
+const REBUILD_PAGE_SIZE: i64 = 10_000;
+
+// swaps stored before their decimals were kept can not be priced in ui amounts,
+// so rebuilding and recomputing candles leaves them out
+
+// merges candles into the stored ones in one statement each, so concurrent writers
+// never lose each others trades, callers run it inside their transaction
+pub fn merge_candles(
+    conn: &DbConnection,
+    new_candles: Vec<CandleEvent>,
+) -> Result<(), diesel::result::Error> {
+    let query = merge_candle_sql(conn);
+    for candle in new_candles {
+        with_conn!(conn, c => diesel::sql_query(query.as_str())
+            .bind::<Text, _>(&candle.base_token)
+            .bind::<Text, _>(&candle.quote_token)
+            .bind::<BigInt, _>(candle.interval_secs)
+            .bind::<BigInt, _>(candle.start_time)
+            .bind::<Double, _>(candle.open)
+            .bind::<Double, _>(candle.high)
+            .bind::<Double, _>(candle.low)
+            .bind::<Double, _>(candle.close)
+            .bind::<BigInt, _>(candle.open_time)
+            .bind::<BigInt, _>(candle.close_time)
+            .bind::<Double, _>(candle.base_volume)
+            .bind::<Double, _>(candle.quote_volume)
+            .bind::<BigInt, _>(candle.trades)
+            .execute(c)?);
+    }
+    Ok(())
+}
+
+// the same merge as Candle::merge, ties on block time keep the stored open and take the
+// new close. Only the placeholders and the two argument max and min differ per backend
+fn merge_candle_sql(conn: &DbConnection) -> String {
+    let (placeholders, greatest, least) = match conn {
+        DbConnection::Postgres(_) => (
+            (1..=13).map(|i| format!("${}", i)).collect::<Vec<_>>(),
+            "GREATEST",
+            "LEAST",
+        ),
+        DbConnection::Sqlite(_) => (vec!["?".to_string(); 13], "MAX", "MIN"),
+    };
+    format!(
+        "INSERT INTO candles (base_token, quote_token, interval_secs, start_time, open, high, low, \
+         close, open_time, close_time, base_volume, quote_volume, trades) VALUES ({}) \
+         ON CONFLICT (base_token, quote_token, interval_secs, start_time) DO UPDATE SET \
+         open = CASE WHEN excluded.open_time < candles.open_time \
+         THEN excluded.open ELSE candles.open END, \
+         open_time = CASE WHEN excluded.open_time < candles.open_time \
+         THEN excluded.open_time ELSE candles.open_time END, \
+         close = CASE WHEN excluded.close_time >= candles.close_time \
+         THEN excluded.close ELSE candles.close END, \
+         close_time = CASE WHEN excluded.close_time >= candles.close_time \
+         THEN excluded.close_time ELSE candles.close_time END, \
+         high = {}(candles.high, excluded.high), \
+         low = {}(candles.low, excluded.low), \
+         base_volume = candles.base_volume + excluded.base_volume, \
+         quote_volume = candles.quote_volume + excluded.quote_volume, \
+         trades = candles.trades + excluded.trades",
+        placeholders.join(", "),
+        greatest,
+        least
+    )
+}
+
+// recomputes one stored candle from the swaps left in the tx table, for after swaps were
+// removed, callers run it inside their transaction
+pub fn recompute_candle(
+    conn: &DbConnection,
+    interval: CandleInterval,
+    key: (String, String, i64, i64),
+) -> Result<(), diesel::result::Error> {
+    let (base_token, quote_token, _, start_time) = key.clone();
+    with_conn!(conn, c => diesel::delete(candles::table.find(key.clone())).execute(c)?);
+    let txs = with_conn!(conn, c => tx::table
+        .filter(
+            tx::input_token
+                .eq(&base_token)
+                .and(tx::output_token.eq(&quote_token))
+                .or(tx::input_token.eq(&quote_token).and(tx::output_token.eq(&base_token))),
+        )
+        .filter(tx::block_time.ge(start_time))
+        .filter(tx::block_time.lt(start_time + interval.secs()))
+        .filter(tx::input_decimals.is_not_null().and(tx::output_decimals.is_not_null()))
+        .load::<Tx>(c)?);
+    let events = txs
+        .into_iter()
+        .map(|t| t.into_swap_event(vec![]))
+        .collect::<Vec<_>>();
+    merge_candles(conn, aggregate(&events, &[interval]))
+}
+
+// recomputes the candles of the intervals from every stored swap, in one transaction
+// so readers never see a half built chart
+// TODO dont print to std out - use a logger
+pub fn rebuild_candles(
+    conn: &DbConnection,
+    intervals: &[CandleInterval],
+) -> Result<usize, Box<dyn Error>> {
+    let interval_secs = intervals.iter().map(|i| i.secs()).collect::<Vec<i64>>();
+    let mut swaps = 0;
+    let mut rebuild = || -> Result<(), diesel::result::Error> {
+        let deleted = with_conn!(conn, c => diesel::delete(
+            candles::table.filter(candles::interval_secs.eq_any(&interval_secs)),
+        )
+        .execute(c)?);
+        println!("deleted {} candles", deleted);
+
+        let mut last_id = 0;
+        loop {
+            let txs = with_conn!(conn, c => tx::table
+                .filter(tx::id.gt(last_id))
+                .filter(tx::input_decimals.is_not_null().and(tx::output_decimals.is_not_null()))
+                .order(tx::id.asc())
+                .limit(REBUILD_PAGE_SIZE)
+                .load::<Tx>(c)?);
+            last_id = match txs.last() {
+                Some(last) => last.id,
+                None => break,
+            };
+            swaps += txs.len();
+            let events = txs
+                .into_iter()
+                .map(|t| t.into_swap_event(vec![]))
+                .collect::<Vec<_>>();
+            merge_candles(conn, aggregate(&events, intervals))?;
+            println!("aggregated {} swaps", swaps);
+        }
+        Ok(())
+    };
+    with_conn!(conn, c => c.transaction(&mut rebuild)?);
+    Ok(swaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::models::candle::Candle;
+    use crate::storage::test_util::{swap, temp_db};
+
+    #[test]
+    fn merging_upserts_stored_candles() {
+        let (_dir, conn) = temp_db();
+        let interval = [CandleInterval::OneHour];
+        // written newest first, like the historical crawler does
+        merge_candles(
+            &conn,
+            aggregate(&[swap("b", 3_700, 1_000_000, 990_000)], &interval),
+        )
+        .unwrap();
+        merge_candles(
+            &conn,
+            aggregate(
+                &[
+                    swap("a", 3_650, 2_000_000, 2_020_000),
+                    swap("c", 3_600, 1_000_000, 1_000_000),
+                ],
+                &interval,
+            ),
+        )
+        .unwrap();
+
+        let candles = with_conn!(&conn, c => candles::table.load::<Candle>(c)).unwrap();
+        assert_eq!(candles.len(), 1);
+        let candle = &candles[0];
+        assert_eq!(candle.start_time, 3_600);
+        assert_eq!((candle.open, candle.open_time), (1.0, 3_600));
+        assert_eq!((candle.close, candle.close_time), (0.99, 3_700));
+        assert_eq!((candle.high, candle.low), (1.01, 0.99));
+        assert_eq!(candle.base_volume, 4.0);
+        assert_eq!(candle.trades, 3);
+    }
+}
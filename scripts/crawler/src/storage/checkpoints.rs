@@ -0,0 +1,17 @@
+use diesel::prelude::*;
+
+use crate::storage::conn::DbConnection;
+use crate::storage::models::checkpoint::Checkpoint;
+use crate::storage::schema::checkpoint;
+use crate::with_conn;
+
+// the checkpoint stored under name, see solcrawl::sinks::checkpoint_name
+pub fn load_checkpoint(
+    conn: &DbConnection,
+    name: &str,
+) -> Result<Option<Checkpoint>, diesel::result::Error> {
+    with_conn!(conn, c => checkpoint::table
+        .find(name)
+        .first::<Checkpoint>(c)
+        .optional())
+}
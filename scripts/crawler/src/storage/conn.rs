@@ -1,12 +1,132 @@
+use diesel::connection::SimpleConnection;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
 
 use std::env;
 use std::error::Error;
 
-pub fn establish_connection() -> Result<PgConnection, Box<dyn Error>> {
+// longest a sqlite connection waits for another connections write to finish
+const SQLITE_BUSY_TIMEOUT_MS: u64 = 30_000;
+
+// each backend has its own migrations directory, sqlite lacks some postgres syntax
+mod postgres_migrations {
+    use diesel::pg::PgConnection;
+    use diesel_migrations::RunMigrationsError;
+
+    embed_migrations!("migrations");
+
+    pub fn run(conn: &PgConnection) -> Result<(), RunMigrationsError> {
+        embedded_migrations::run(conn)
+    }
+}
+
+mod sqlite_migrations {
+    use diesel::sqlite::SqliteConnection;
+    use diesel_migrations::RunMigrationsError;
+
+    embed_migrations!("migrations_sqlite");
+
+    pub fn run(conn: &SqliteConnection) -> Result<(), RunMigrationsError> {
+        embedded_migrations::run(conn)
+    }
+}
+
+// DbConnection is the storage backend selected by the DATABASE_URL scheme,
+// postgres:// and postgresql:// urls use postgres, anything else is a sqlite file
+pub enum DbConnection {
+    Postgres(PgConnection),
+    Sqlite(SqliteConnection),
+}
+
+// runs the same diesel expression against whichever backend the connection is using
+#[macro_export]
+macro_rules! with_conn {
+    ($conn:expr, $c:ident => $body:expr) => {
+        match $conn {
+            $crate::storage::conn::DbConnection::Postgres($c) => $body,
+            $crate::storage::conn::DbConnection::Sqlite($c) => $body,
+        }
+    };
+}
+
+impl DbConnection {
+    pub fn establish(database_url: &str) -> Result<Self, Box<dyn Error>> {
+        if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            return Ok(DbConnection::Postgres(PgConnection::establish(
+                database_url,
+            )?));
+        }
+
+        let path = database_url
+            .strip_prefix("sqlite://")
+            .unwrap_or(database_url);
+        let conn = SqliteConnection::establish(path)?;
+        // sqlite leaves foreign keys unenforced unless asked for every connection.
+        // finality trackers and pipelines write to the same file from their own
+        // connections, so writers wait for each other instead of failing as locked,
+        // and with wal readers do not block them
+        conn.batch_execute(&format!(
+            "PRAGMA foreign_keys = ON; PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL;",
+            SQLITE_BUSY_TIMEOUT_MS
+        ))?;
+        Ok(DbConnection::Sqlite(conn))
+    }
+
+    pub fn run_migrations(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            DbConnection::Postgres(c) => postgres_migrations::run(c)?,
+            DbConnection::Sqlite(c) => sqlite_migrations::run(c)?,
+        }
+        Ok(())
+    }
+}
+
+pub fn establish_connection() -> Result<DbConnection, Box<dyn Error>> {
     dotenv::dotenv().ok();
 
     let database_url = env::var("DATABASE_URL")?;
-    Ok(PgConnection::establish(&database_url)?)
+    DbConnection::establish(&database_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_util::temp_db;
+    use std::time::Duration;
+
+    #[test]
+    fn sqlite_writers_wait_for_each_other() {
+        let (dir, conn) = temp_db();
+        let url = dir.path().join("test.db").to_string_lossy().to_string();
+        let other = DbConnection::establish(&url).unwrap();
+
+        let (locked_send, locked_recv) = crossbeam::channel::bounded(0);
+        let writer = std::thread::spawn(move || {
+            with_conn!(&other, c => c.transaction::<_, diesel::result::Error, _>(|| {
+                c.execute("INSERT INTO checkpoint (name, sig, slot) VALUES ('a', 'a', 1)")?;
+                locked_send.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(300));
+                Ok(())
+            }))
+            .unwrap();
+        });
+
+        locked_recv.recv().unwrap();
+        with_conn!(&conn, c => c.execute("INSERT INTO checkpoint (name, sig, slot) VALUES ('b', 'b', 1)"))
+            .unwrap();
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn sqlite_migrations_run_once() {
+        let (_dir, conn) = temp_db();
+        // already migrated by temp_db, running them again finds nothing to do
+        conn.run_migrations().unwrap();
+
+        for table in ["tx", "swap_legs", "checkpoint", "candles"] {
+            let query = format!("SELECT * FROM {}", table);
+            with_conn!(&conn, c => c.execute(&query)).unwrap();
+        }
+    }
 }
@@ -0,0 +1,121 @@
+use diesel::prelude::*;
+
+use solcrawl::candles::{aggregate, CandleInterval};
+use solcrawl::crawlers::Commitment;
+
+use crate::storage::candles::recompute_candle;
+use crate::storage::conn::DbConnection;
+use crate::storage::models::tx::Tx;
+use crate::storage::schema::tx;
+use crate::with_conn;
+use std::error::Error;
+
+// sig and slot of stored swaps below finalized, lowest slot first
+pub fn unfinalized_swaps(
+    conn: &DbConnection,
+    limit: i64,
+) -> Result<Vec<(String, Option<i64>)>, diesel::result::Error> {
+    let finalized = Commitment::Finalized.as_str();
+    with_conn!(conn, c => tx::table
+        .select((tx::sig, tx::slot))
+        .filter(tx::commitment.ne(finalized))
+        .order(tx::slot.asc())
+        .limit(limit)
+        .load::<(String, Option<i64>)>(c))
+}
+
+// marks swaps finalized at the slot the cluster finalized them in, returns how many
+// stored swaps changed
+pub fn mark_finalized(
+    conn: &DbConnection,
+    finalized: &[(String, u64)],
+) -> Result<usize, diesel::result::Error> {
+    let commitment = Commitment::Finalized.as_str();
+    let mut updated = 0;
+    let mut mark = || -> Result<(), diesel::result::Error> {
+        for (sig, slot) in finalized {
+            updated += with_conn!(conn, c => diesel::update(
+                tx::table
+                    .filter(tx::sig.eq(sig))
+                    .filter(tx::commitment.ne(commitment)),
+            )
+            .set((tx::commitment.eq(commitment), tx::slot.eq(*slot as i64)))
+            .execute(c)?);
+        }
+        Ok(())
+    };
+    with_conn!(conn, c => c.transaction(&mut mark))?;
+    Ok(updated)
+}
+
+// deletes rolled back swaps with their legs and recomputes the candles of the intervals
+// they were merged into, in one transaction, returns how many swaps were stored
+pub fn remove_swaps(
+    conn: &DbConnection,
+    sigs: &[String],
+    intervals: &[CandleInterval],
+) -> Result<usize, Box<dyn Error>> {
+    let mut removed = 0;
+    let mut remove = || -> Result<(), diesel::result::Error> {
+        let txs = with_conn!(conn, c => tx::table
+            .filter(tx::sig.eq_any(sigs))
+            .load::<Tx>(c)?);
+        if txs.is_empty() {
+            return Ok(());
+        }
+        let swaps = txs
+            .into_iter()
+            .map(|t| t.into_swap_event(vec![]))
+            .collect::<Vec<_>>();
+        let mut touched = Vec::new();
+        for interval in intervals {
+            for candle in aggregate(&swaps, &[*interval]) {
+                touched.push((*interval, candle.key()));
+            }
+        }
+
+        // their swap_legs are deleted by the foreign key cascade
+        removed = with_conn!(conn, c => diesel::delete(tx::table.filter(tx::sig.eq_any(sigs)))
+            .execute(c)?);
+        for (interval, key) in touched {
+            recompute_candle(conn, interval, key)?;
+        }
+        Ok(())
+    };
+    with_conn!(conn, c => c.transaction(&mut remove)?);
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_txs::DatabaseSink;
+    use crate::storage::schema::swap_legs;
+    use crate::storage::test_util::{swap, temp_db};
+    use solcrawl::sinks::{Checkpoint, Sink};
+
+    #[test]
+    fn removed_swaps_take_their_legs() {
+        let (_dir, conn) = temp_db();
+        let mut sink = DatabaseSink::new(vec![], conn, false);
+        sink.write_batch(&[
+            swap("a", 100, 1_000_000, 999_000),
+            swap("b", 101, 1_000_000, 999_000),
+        ])
+        .unwrap();
+        let checkpoint = Checkpoint {
+            name: "test/live".to_string(),
+            sig: "b".to_string(),
+            slot: 101,
+        };
+        sink.checkpoint(&checkpoint).unwrap();
+        let conn = sink.into_conn();
+
+        assert_eq!(remove_swaps(&conn, &["a".to_string()], &[]).unwrap(), 1);
+        let legs = with_conn!(&conn, c => swap_legs::table
+            .select(swap_legs::sig)
+            .load::<String>(c))
+        .unwrap();
+        assert_eq!(legs, vec!["b"]);
+    }
+}
@@ -1,3 +1,9 @@
+pub mod candles;
+pub mod checkpoints;
 pub mod conn;
+pub mod finality;
 pub mod models;
 pub mod schema;
+pub mod swaps;
+#[cfg(test)]
+pub mod test_util;
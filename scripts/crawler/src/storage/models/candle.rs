@@ -0,0 +1,61 @@
+use crate::storage::schema::candles;
+use solcrawl::candles::Candle as CandleEvent;
+
+#[derive(Debug, Queryable, Insertable, AsChangeset)]
+#[table_name = "candles"]
+#[primary_key(base_token, quote_token, interval_secs, start_time)]
+pub struct Candle {
+    pub base_token: String,
+    pub quote_token: String,
+    pub interval_secs: i64,
+    pub start_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub open_time: i64,
+    pub close_time: i64,
+    pub base_volume: f64,
+    pub quote_volume: f64,
+    pub trades: i64,
+}
+
+impl From<CandleEvent> for Candle {
+    fn from(c: CandleEvent) -> Self {
+        Self {
+            base_token: c.base_token,
+            quote_token: c.quote_token,
+            interval_secs: c.interval_secs,
+            start_time: c.start_time,
+            open: c.open,
+            high: c.high,
+            low: c.low,
+            close: c.close,
+            open_time: c.open_time,
+            close_time: c.close_time,
+            base_volume: c.base_volume,
+            quote_volume: c.quote_volume,
+            trades: c.trades,
+        }
+    }
+}
+
+impl From<Candle> for CandleEvent {
+    fn from(c: Candle) -> Self {
+        Self {
+            base_token: c.base_token,
+            quote_token: c.quote_token,
+            interval_secs: c.interval_secs,
+            start_time: c.start_time,
+            open: c.open,
+            high: c.high,
+            low: c.low,
+            close: c.close,
+            open_time: c.open_time,
+            close_time: c.close_time,
+            base_volume: c.base_volume,
+            quote_volume: c.quote_volume,
+            trades: c.trades,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use crate::storage::schema::checkpoint;
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "checkpoint"]
+pub struct Checkpoint {
+    pub name: String,
+    pub sig: String,
+    pub slot: i64,
+}
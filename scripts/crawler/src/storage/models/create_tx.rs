@@ -1,6 +1,6 @@
 use crate::storage::schema::tx;
 
-#[derive(Debug, Insertable)]
+#[derive(Clone, Debug, Insertable)]
 #[table_name = "tx"]
 pub struct CreateTx {
     pub sig: String,
@@ -9,4 +9,23 @@ pub struct CreateTx {
     pub input_amount: i64,
     pub output_amount: i64,
     pub block_time: i64,
+    pub input_token_program: String,
+    pub output_token_program: String,
+    pub input_transfer_fee: i64,
+    pub output_transfer_fee: i64,
+    pub price: Option<f64>,
+    pub inverse_price: Option<f64>,
+    pub peg_deviation: Option<f64>,
+    pub quoted_out_amount: Option<i64>,
+    pub minimum_out_amount: Option<i64>,
+    pub price_impact_bps: Option<f64>,
+    pub slot: Option<i64>,
+    pub trader: Option<String>,
+    pub fee: Option<i64>,
+    pub success: bool,
+    pub input_decimals: Option<i16>,
+    pub output_decimals: Option<i16>,
+    pub program_version: Option<i16>,
+    pub commitment: String,
+    pub extractor_version: Option<i16>,
 }
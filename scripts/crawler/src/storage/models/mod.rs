@@ -1,2 +1,5 @@
+pub mod candle;
+pub mod checkpoint;
 pub mod create_tx;
+pub mod swap_leg;
 pub mod tx;
@@ -0,0 +1,38 @@
+use crate::storage::schema::swap_legs;
+
+#[derive(Queryable)]
+pub struct SwapLeg {
+    pub id: i32,
+    pub sig: String,
+    pub leg_index: i16,
+    pub amm_program: String,
+    pub input_token: String,
+    pub output_token: String,
+    pub input_amount: i64,
+    pub output_amount: i64,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "swap_legs"]
+pub struct CreateSwapLeg {
+    pub sig: String,
+    pub leg_index: i16,
+    pub amm_program: String,
+    pub input_token: String,
+    pub output_token: String,
+    pub input_amount: i64,
+    pub output_amount: i64,
+}
+
+impl SwapLeg {
+    pub fn into_leg(self) -> solcrawl::extractors::jupiter_route::SwapLeg {
+        solcrawl::extractors::jupiter_route::SwapLeg {
+            index: self.leg_index as u8,
+            amm_program: self.amm_program,
+            input_token: self.input_token,
+            output_token: self.output_token,
+            input_amount: self.input_amount as u64,
+            output_amount: self.output_amount as u64,
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use solcrawl::extractors::jupiter_route::SwapLeg;
+use solcrawl::extractors::jupiter_swap::SwapEvent;
+use solcrawl::tokens::mint_registry::ui_amount;
+
 #[derive(Queryable)]
 pub struct Tx {
     pub id: i32,
@@ -7,4 +11,63 @@ pub struct Tx {
     pub input_amount: i64,
     pub output_amount: i64,
     pub block_time: i64,
+    pub input_token_program: String,
+    pub output_token_program: String,
+    pub input_transfer_fee: i64,
+    pub output_transfer_fee: i64,
+    pub price: Option<f64>,
+    pub inverse_price: Option<f64>,
+    pub peg_deviation: Option<f64>,
+    pub quoted_out_amount: Option<i64>,
+    pub minimum_out_amount: Option<i64>,
+    pub price_impact_bps: Option<f64>,
+    pub slot: Option<i64>,
+    pub trader: Option<String>,
+    pub fee: Option<i64>,
+    pub success: bool,
+    pub input_decimals: Option<i16>,
+    pub output_decimals: Option<i16>,
+    pub program_version: Option<i16>,
+    pub commitment: String,
+    pub extractor_version: Option<i16>,
+}
+
+impl Tx {
+    // swaps stored before the swap details migration have no slot, trader, fee or decimals,
+    // they are exported as zero and their ui amounts are left unscaled
+    pub fn into_swap_event(self, legs: Vec<SwapLeg>) -> SwapEvent {
+        let input_decimals = self.input_decimals.unwrap_or_default() as u8;
+        let output_decimals = self.output_decimals.unwrap_or_default() as u8;
+        SwapEvent {
+            slot: self.slot.unwrap_or_default() as u64,
+            block_time: Some(self.block_time),
+            trader: self.trader.unwrap_or_default(),
+            fee: self.fee.unwrap_or_default() as u64,
+            success: self.success,
+            input_amount: self.input_amount as u64,
+            output_amount: self.output_amount as u64,
+            input_token_program: self.input_token_program,
+            output_token_program: self.output_token_program,
+            input_transfer_fee: self.input_transfer_fee as u64,
+            output_transfer_fee: self.output_transfer_fee as u64,
+            input_decimals,
+            output_decimals,
+            input_ui_amount: ui_amount(self.input_amount as u64, input_decimals),
+            output_ui_amount: ui_amount(self.output_amount as u64, output_decimals),
+            input_symbol: None,
+            output_symbol: None,
+            price: self.price.unwrap_or_default(),
+            inverse_price: self.inverse_price.unwrap_or_default(),
+            peg_deviation: self.peg_deviation,
+            quoted_out_amount: self.quoted_out_amount.map(|a| a as u64),
+            minimum_out_amount: self.minimum_out_amount.map(|a| a as u64),
+            price_impact_bps: self.price_impact_bps,
+            program_version: self.program_version.map(|v| v as u8),
+            legs,
+            commitment: self.commitment.parse().unwrap_or_default(),
+            sig: self.sig,
+            input_token: self.input_token,
+            output_token: self.output_token,
+        }
+    }
 }
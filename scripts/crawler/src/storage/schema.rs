@@ -7,5 +7,65 @@ table! {
         input_amount -> Int8,
         output_amount -> Int8,
         block_time -> Int8,
+        input_token_program -> Varchar,
+        output_token_program -> Varchar,
+        input_transfer_fee -> Int8,
+        output_transfer_fee -> Int8,
+        price -> Nullable<Float8>,
+        inverse_price -> Nullable<Float8>,
+        peg_deviation -> Nullable<Float8>,
+        quoted_out_amount -> Nullable<Int8>,
+        minimum_out_amount -> Nullable<Int8>,
+        price_impact_bps -> Nullable<Float8>,
+        slot -> Nullable<Int8>,
+        trader -> Nullable<Varchar>,
+        fee -> Nullable<Int8>,
+        success -> Bool,
+        input_decimals -> Nullable<Int2>,
+        output_decimals -> Nullable<Int2>,
+        program_version -> Nullable<Int2>,
+        commitment -> Varchar,
+        extractor_version -> Nullable<Int2>,
     }
 }
+
+table! {
+    swap_legs (id) {
+        id -> Int4,
+        sig -> Varchar,
+        leg_index -> Int2,
+        amm_program -> Varchar,
+        input_token -> Varchar,
+        output_token -> Varchar,
+        input_amount -> Int8,
+        output_amount -> Int8,
+    }
+}
+
+table! {
+    checkpoint (name) {
+        name -> Varchar,
+        sig -> Varchar,
+        slot -> Int8,
+    }
+}
+
+table! {
+    candles (base_token, quote_token, interval_secs, start_time) {
+        base_token -> Varchar,
+        quote_token -> Varchar,
+        interval_secs -> Int8,
+        start_time -> Int8,
+        open -> Float8,
+        high -> Float8,
+        low -> Float8,
+        close -> Float8,
+        open_time -> Int8,
+        close_time -> Int8,
+        base_volume -> Float8,
+        quote_volume -> Float8,
+        trades -> Int8,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(candles, checkpoint, swap_legs, tx);
@@ -0,0 +1,38 @@
+use diesel::prelude::*;
+
+use solcrawl::extractors::jupiter_swap::SwapEvent;
+
+use crate::storage::conn::DbConnection;
+use crate::storage::models::swap_leg::SwapLeg;
+use crate::storage::models::tx::Tx;
+use crate::storage::schema::swap_legs;
+use crate::with_conn;
+use std::collections::HashMap;
+use std::error::Error;
+
+// loads the route legs of the stored swaps and turns them into swap events, in the same order
+pub fn load_swap_events(
+    conn: &DbConnection,
+    txs: Vec<Tx>,
+) -> Result<Vec<SwapEvent>, Box<dyn Error>> {
+    let sigs = txs.iter().map(|t| t.sig.clone()).collect::<Vec<String>>();
+    let legs = with_conn!(conn, c => swap_legs::table
+        .filter(swap_legs::sig.eq_any(&sigs))
+        .order((swap_legs::sig, swap_legs::leg_index))
+        .load::<SwapLeg>(c)?);
+    let mut legs_by_sig: HashMap<String, Vec<_>> = HashMap::new();
+    for leg in legs {
+        legs_by_sig
+            .entry(leg.sig.clone())
+            .or_default()
+            .push(leg.into_leg());
+    }
+
+    Ok(txs
+        .into_iter()
+        .map(|t| {
+            let legs = legs_by_sig.remove(&t.sig).unwrap_or_default();
+            t.into_swap_event(legs)
+        })
+        .collect())
+}
@@ -0,0 +1,58 @@
+use solcrawl::extractors::jupiter_swap::SwapEvent;
+
+use crate::storage::conn::DbConnection;
+use tempfile::TempDir;
+
+pub const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+pub const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+// a migrated sqlite database in a temporary directory, removed with the directory
+pub fn temp_db() -> (TempDir, DbConnection) {
+    let dir = tempfile::tempdir().unwrap();
+    let url = dir.path().join("test.db").to_string_lossy().to_string();
+    let conn = DbConnection::establish(&url).unwrap();
+    conn.run_migrations().unwrap();
+    (dir, conn)
+}
+
+// a usdc to usdt swap with one leg, amounts in raw units of 6 decimals
+pub fn swap(sig: &str, block_time: i64, input_amount: u64, output_amount: u64) -> SwapEvent {
+    serde_json::from_value(serde_json::json!({
+        "sig": sig,
+        "slot": block_time as u64,
+        "block_time": block_time,
+        "trader": "trader",
+        "fee": 5000,
+        "success": true,
+        "input_token": USDC,
+        "output_token": USDT,
+        "input_amount": input_amount,
+        "output_amount": output_amount,
+        "input_token_program": solcrawl::tokens::TOKEN_PROGRAM,
+        "output_token_program": solcrawl::tokens::TOKEN_PROGRAM,
+        "input_transfer_fee": 0,
+        "output_transfer_fee": 0,
+        "input_decimals": 6,
+        "output_decimals": 6,
+        "input_ui_amount": input_amount as f64 / 1e6,
+        "output_ui_amount": output_amount as f64 / 1e6,
+        "input_symbol": null,
+        "output_symbol": null,
+        "price": output_amount as f64 / input_amount as f64,
+        "inverse_price": input_amount as f64 / output_amount as f64,
+        "peg_deviation": null,
+        "quoted_out_amount": null,
+        "minimum_out_amount": null,
+        "price_impact_bps": null,
+        "program_version": 4,
+        "legs": [{
+            "index": 0,
+            "amm_program": "amm",
+            "input_token": USDC,
+            "output_token": USDT,
+            "input_amount": input_amount,
+            "output_amount": output_amount,
+        }],
+    }))
+    .unwrap()
+}
@@ -0,0 +1,171 @@
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use solcrawl::extractors::jupiter_swap::SwapEvent;
+use solcrawl::sinks::broadcast::{BroadcastHub, SubscriberFilter};
+
+use crate::export::parse_pair;
+use crossbeam::channel::RecvTimeoutError;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::time::Duration;
+
+// comment lines sent while no swaps arrive, so proxies keep the stream open and
+// clients that went away are noticed
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+// StreamFilter is what a client subscribes with, every set field has to match
+struct StreamFilter {
+    pair: Option<(String, String)>,
+    // smallest input amount in ui units
+    min_size: Option<f64>,
+    trader: Option<String>,
+}
+
+impl StreamFilter {
+    fn matches(&self, swap: &SwapEvent) -> bool {
+        if let Some((a, b)) = &self.pair {
+            let forward = &swap.input_token == a && &swap.output_token == b;
+            let backward = &swap.input_token == b && &swap.output_token == a;
+            if !forward && !backward {
+                return false;
+            }
+        }
+        if let Some(min_size) = self.min_size {
+            if swap.input_ui_amount < min_size {
+                return false;
+            }
+        }
+        if let Some(trader) = &self.trader {
+            if &swap.trader != trader {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// serves GET /swaps/stream as server-sent events on a background thread, clients
+// filter with pair, min_size and trader and get up to replay recent swaps first
+// TODO dont print to std out - use a logger
+pub fn serve_stream(
+    listen: &str,
+    hub: BroadcastHub<SwapEvent>,
+    max_replay: usize,
+) -> Result<(), Box<dyn Error>> {
+    let server =
+        Server::http(listen).map_err(|e| format!("could not listen on {}: {}", listen, e))?;
+    println!("streaming swaps on http://{}/swaps/stream", listen);
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let hub = hub.clone();
+            // every stream holds its connection open, so each gets its own thread
+            std::thread::spawn(move || handle_request(request, hub, max_replay));
+        }
+    });
+    Ok(())
+}
+
+fn handle_request(request: Request, hub: BroadcastHub<SwapEvent>, max_replay: usize) {
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+    if request.method() != &Method::Get || path.trim_end_matches('/') != "/swaps/stream" {
+        respond_error(request, 404, format!("no route {}", path));
+        return;
+    }
+    let params = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect::<HashMap<String, String>>();
+    let (filter, replay) = match parse_params(&params, max_replay) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            respond_error(request, 400, e);
+            return;
+        }
+    };
+
+    // set by EventSource when it reconnects, so the swaps it already got are not replayed
+    let last_event_id = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Last-Event-ID"))
+        .and_then(|h| h.value.as_str().trim().parse::<u64>().ok());
+
+    let matches: SubscriberFilter<SwapEvent> = Box::new(move |swap| filter.matches(swap));
+    let subscription = hub.subscribe(matches, replay, last_event_id);
+    println!("stream client connected, {} subscribers", hub.subscribers());
+
+    // tiny_http buffers chunked bodies, so the event stream is written by hand and
+    // flushed after every event
+    let mut writer = request.into_writer();
+    let headers = "HTTP/1.1 200 OK\r\n\
+                   Content-Type: text/event-stream\r\n\
+                   Cache-Control: no-cache\r\n\
+                   Connection: close\r\n\
+                   Access-Control-Allow-Origin: *\r\n\r\n";
+    if writer.write_all(headers.as_bytes()).is_err() || writer.flush().is_err() {
+        return;
+    }
+    loop {
+        let event = match subscription.receiver.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok((id, swap)) => match serde_json::to_string(&*swap) {
+                Ok(data) => format!("id: {}\nevent: swap\ndata: {}\n\n", id, data),
+                Err(e) => {
+                    println!("stream err - {}", e);
+                    continue;
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_string(),
+            // the hub dropped this subscriber for falling behind, the client reconnects
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if writer.write_all(event.as_bytes()).is_err() || writer.flush().is_err() {
+            break;
+        }
+    }
+    println!("stream client disconnected");
+}
+
+fn parse_params(
+    params: &HashMap<String, String>,
+    max_replay: usize,
+) -> Result<(StreamFilter, usize), String> {
+    let pair = match params.get("pair") {
+        Some(pair) => Some(parse_pair(pair)?),
+        None => None,
+    };
+    let min_size = match params.get("min_size") {
+        Some(min_size) => Some(
+            min_size
+                .parse::<f64>()
+                .map_err(|_| "min_size must be a number".to_string())?,
+        ),
+        None => None,
+    };
+    let replay = match params.get("replay") {
+        Some(replay) => replay
+            .parse::<usize>()
+            .map_err(|_| "replay must be a positive integer".to_string())?
+            .min(max_replay),
+        None => max_replay,
+    };
+    let filter = StreamFilter {
+        pair,
+        min_size,
+        trader: params.get("trader").cloned(),
+    };
+    Ok((filter, replay))
+}
+
+fn respond_error(request: Request, status: u16, message: String) {
+    let body = serde_json::json!({ "error": message }).to_string();
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        println!("stream respond err - {}", e);
+    }
+}
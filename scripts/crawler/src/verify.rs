@@ -0,0 +1,488 @@
+use clap::Args;
+use diesel::prelude::*;
+
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use solcrawl::cache::TransactionCache;
+use solcrawl::crawlers::fetch_transaction;
+use solcrawl::extractors::jupiter_swap::{JupiterSwapExtractor, EXTRACTOR_VERSION};
+use solcrawl::extractors::TransactionExtractor;
+use solcrawl::filters::jupiter_swap_token::JupiterSwapToken;
+use solcrawl::filters::TransactionFilter;
+use solcrawl::sinks::{Checkpoint, Sink};
+use solcrawl::tokens::{TOKEN_2022_PROGRAM, TOKEN_PROGRAM};
+
+use crate::cli::CrawlOptions;
+use crate::handle_txs::{build_create_tx_obj, DatabaseSink};
+use crate::storage::conn::DbConnection;
+use crate::storage::models::create_tx::CreateTx;
+use crate::storage::models::tx::Tx;
+use crate::storage::schema::tx;
+use crate::with_conn;
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// checkpoint name of the swaps stored by verify --backfill
+const VERIFY_CHECKPOINT: &str = "verify";
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// First slot of the range
+    #[clap(long)]
+    pub start_slot: Option<u64>,
+
+    /// Last slot of the range
+    #[clap(long)]
+    pub end_slot: Option<u64>,
+
+    /// Only swaps at or after this unix time
+    #[clap(long)]
+    pub from: Option<i64>,
+
+    /// Only swaps before this unix time
+    #[clap(long)]
+    pub to: Option<i64>,
+
+    /// Store the missing swaps and re-extract the mismatched and stale ones
+    #[clap(long)]
+    pub backfill: bool,
+}
+
+impl VerifyArgs {
+    fn in_range(&self, slot: u64, block_time: Option<i64>) -> bool {
+        if matches!(self.start_slot, Some(start) if slot < start)
+            || matches!(self.end_slot, Some(end) if slot > end)
+        {
+            return false;
+        }
+        if self.from.is_some() || self.to.is_some() {
+            let block_time = match block_time {
+                Some(block_time) => block_time,
+                None => return false,
+            };
+            if matches!(self.from, Some(from) if block_time < from)
+                || matches!(self.to, Some(to) if block_time >= to)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    // signatures are listed newest first, so the listing is done once one is older than the range
+    fn below_range(&self, slot: u64, block_time: Option<i64>) -> bool {
+        matches!(self.start_slot, Some(start) if slot < start)
+            || matches!((self.from, block_time), (Some(from), Some(block_time)) if block_time < from)
+    }
+}
+
+// VerifyReport is what verify found in the range
+#[derive(Default)]
+pub struct VerifyReport {
+    // signatures the cluster lists for the programs in the range
+    pub listed: usize,
+    pub stored: usize,
+    pub matched: usize,
+    // swaps extraction stores that are not in the table
+    pub missing: Vec<String>,
+    // stored swaps the cluster does not list in the range
+    pub extra: Vec<String>,
+    // stored swaps of the current extractor version that differ from extracting them again
+    pub mismatched: Vec<(String, Vec<&'static str>)>,
+    // stored swaps of an older extractor version that differ, re-extracting updates them
+    pub stale: Vec<(String, Vec<&'static str>)>,
+    // listed transactions that are not swaps between approved tokens
+    pub skipped: usize,
+    // listed transactions the rpc node did not return
+    pub unavailable: Vec<String>,
+    // swaps written by --backfill
+    pub backfilled: usize,
+}
+
+// re-lists the signatures of the programs in the range, compares them with the stored swaps
+// by extracting every transaction again, and with backfill stores what is missing or outdated.
+// there is no dead letter store of transactions that failed extraction to compare with, so
+// listed transactions that are not stored are fetched to tell missing swaps from
+// transactions that are no swaps. the report and the help text say so.
+// TODO dont print to std out - use a logger
+pub fn verify(
+    conn: DbConnection,
+    options: &CrawlOptions,
+    args: VerifyArgs,
+    cache: Option<Arc<TransactionCache>>,
+) -> Result<VerifyReport, Box<dyn Error>> {
+    if args.start_slot.is_none() && args.from.is_none() {
+        return Err("verify needs --start-slot or --from".into());
+    }
+
+    let mut stored = load_stored(&conn, &args)?;
+    let client = RpcClient::new(options.rpc_url.clone());
+    let before = anchor_sig(&conn, &args)?;
+    let listed = list_signatures(&client, &options.programs, before, &args)?;
+
+    let mut report = VerifyReport {
+        listed: listed.len(),
+        stored: stored.len(),
+        ..Default::default()
+    };
+    report.extra = stored
+        .keys()
+        .filter(|sig| !listed.contains_key(*sig))
+        .cloned()
+        .collect();
+    report.extra.sort();
+
+    let filter = JupiterSwapToken {
+        approved_tokens: options.tokens.clone(),
+        token_programs: vec![TOKEN_PROGRAM.to_string(), TOKEN_2022_PROGRAM.to_string()],
+    };
+    let extractor = JupiterSwapExtractor {
+        stable_tokens: options.tokens.clone(),
+        ..Default::default()
+    };
+    let sleep_duration = options.sleep_ms.map(Duration::from_millis);
+
+    let mut listed = listed.into_iter().collect::<Vec<_>>();
+    listed.sort_by_key(|(_, slot)| *slot);
+    let mut backfill = Vec::new();
+    for (sig, _) in listed {
+        let tx = match fetch_transaction(
+            &client,
+            &Signature::from_str(&sig)?,
+            options.encoding(),
+            None,
+            sleep_duration,
+            cache.as_deref(),
+        ) {
+            Some(tx) => tx,
+            None => {
+                report.unavailable.push(sig);
+                continue;
+            }
+        };
+        let fresh = if filter.filter(&tx) {
+            None
+        } else {
+            extractor
+                .extract(&tx)
+                .ok()
+                .and_then(|swap| Some((build_create_tx_obj(&options.tokens, &swap).ok()?, swap)))
+        };
+
+        match (stored.remove(&sig), fresh) {
+            (Some(row), Some((create_tx, swap))) => {
+                let diffs = differences(&row, &create_tx);
+                if diffs.is_empty() {
+                    report.matched += 1;
+                    continue;
+                }
+                if row.extractor_version == Some(EXTRACTOR_VERSION as i16) {
+                    report.mismatched.push((sig, diffs));
+                } else {
+                    report.stale.push((sig, diffs));
+                }
+                backfill.push(swap);
+            }
+            (Some(row), None) => {
+                let diffs = vec!["not a swap"];
+                if row.extractor_version == Some(EXTRACTOR_VERSION as i16) {
+                    report.mismatched.push((sig, diffs));
+                } else {
+                    report.stale.push((sig, diffs));
+                }
+            }
+            (None, Some((_, swap))) => {
+                report.missing.push(sig);
+                backfill.push(swap);
+            }
+            (None, None) => report.skipped += 1,
+        }
+    }
+
+    if args.backfill && !backfill.is_empty() {
+        let last = backfill.last().ok_or("nothing to backfill")?;
+        let checkpoint = Checkpoint {
+            name: VERIFY_CHECKPOINT.to_string(),
+            sig: last.sig.clone(),
+            slot: last.slot,
+        };
+        let mut sink = DatabaseSink::new(options.tokens.clone(), conn, true)
+            .with_candles(options.candle_intervals());
+        sink.write_batch(&backfill)?;
+        sink.checkpoint(&checkpoint)?;
+        report.backfilled = backfill.len();
+    }
+    Ok(report)
+}
+
+impl VerifyReport {
+    pub fn print(&self) {
+        for sig in &self.missing {
+            println!("missing {}", sig);
+        }
+        for sig in &self.extra {
+            println!("extra {}", sig);
+        }
+        for (sig, diffs) in &self.mismatched {
+            println!("mismatched {} - {}", sig, diffs.join(", "));
+        }
+        for (sig, diffs) in &self.stale {
+            println!("stale {} - {}", sig, diffs.join(", "));
+        }
+        for sig in &self.unavailable {
+            println!("unavailable {}", sig);
+        }
+        println!(
+            "listed {}, stored {}, matched {}, missing {}, extra {}, mismatched {}, stale {}, not swaps {}, unavailable {}",
+            self.listed,
+            self.stored,
+            self.matched,
+            self.missing.len(),
+            self.extra.len(),
+            self.mismatched.len(),
+            self.stale.len(),
+            self.skipped,
+            self.unavailable.len(),
+        );
+        println!(
+            "failed extractions are not recorded, every listed transaction was extracted again"
+        );
+        if self.backfilled > 0 {
+            // swaps that were already stored are not merged into the candles again
+            println!(
+                "backfilled {} swaps, run rebuild-candles if any were mismatched or stale",
+                self.backfilled
+            );
+        }
+    }
+}
+
+// stored swaps in the range by signature, swaps stored without a slot only match time ranges
+fn load_stored(
+    conn: &DbConnection,
+    args: &VerifyArgs,
+) -> Result<HashMap<String, Tx>, diesel::result::Error> {
+    let rows = with_conn!(conn, c => {
+        let mut query = tx::table.into_boxed();
+        if let Some(start_slot) = args.start_slot {
+            query = query.filter(tx::slot.ge(start_slot as i64));
+        }
+        if let Some(end_slot) = args.end_slot {
+            query = query.filter(tx::slot.le(end_slot as i64));
+        }
+        if let Some(from) = args.from {
+            query = query.filter(tx::block_time.ge(from));
+        }
+        if let Some(to) = args.to {
+            query = query.filter(tx::block_time.lt(to));
+        }
+        query.load::<Tx>(c)?
+    });
+    Ok(rows.into_iter().map(|row| (row.sig.clone(), row)).collect())
+}
+
+// the first stored swap after the range, listing starts below it instead of at the
+// newest transaction so older ranges do not page through everything since
+fn anchor_sig(conn: &DbConnection, args: &VerifyArgs) -> Result<Option<Signature>, Box<dyn Error>> {
+    let sig = match (args.end_slot, args.to) {
+        (Some(end_slot), _) => with_conn!(conn, c => tx::table
+            .select(tx::sig)
+            .filter(tx::slot.gt(end_slot as i64))
+            .order(tx::slot.asc())
+            .first::<String>(c)
+            .optional()?),
+        (None, Some(to)) => with_conn!(conn, c => tx::table
+            .select(tx::sig)
+            .filter(tx::block_time.ge(to))
+            .order(tx::block_time.asc())
+            .first::<String>(c)
+            .optional()?),
+        (None, None) => None,
+    };
+    Ok(match sig {
+        Some(sig) => Some(Signature::from_str(&sig)?),
+        None => None,
+    })
+}
+
+// signatures of the programs in the range with their slot
+fn list_signatures(
+    client: &RpcClient,
+    programs: &[String],
+    before: Option<Signature>,
+    args: &VerifyArgs,
+) -> Result<HashMap<String, u64>, Box<dyn Error>> {
+    let mut listed = HashMap::new();
+    for program in programs {
+        let address = Pubkey::from_str(program)?;
+        let mut before = before;
+        loop {
+            let statuses = client.get_signatures_for_address_with_config(
+                &address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: None,
+                    commitment: Some(CommitmentConfig::finalized()),
+                },
+            )?;
+            let last = match statuses.last() {
+                Some(last) => last,
+                None => break,
+            };
+            before = Some(Signature::from_str(&last.signature)?);
+            let done = args.below_range(last.slot, last.block_time);
+
+            for status in &statuses {
+                if args.in_range(status.slot, status.block_time) {
+                    listed.insert(status.signature.clone(), status.slot);
+                }
+            }
+            println!(
+                "verify listed {} - {} signatures down to slot {}",
+                program,
+                listed.len(),
+                last.slot
+            );
+            if done {
+                break;
+            }
+        }
+    }
+    Ok(listed)
+}
+
+// columns of a stored swap that differ from extracting its transaction again
+fn differences(stored: &Tx, fresh: &CreateTx) -> Vec<&'static str> {
+    let columns = [
+        ("input_token", stored.input_token == fresh.input_token),
+        ("output_token", stored.output_token == fresh.output_token),
+        ("input_amount", stored.input_amount == fresh.input_amount),
+        ("output_amount", stored.output_amount == fresh.output_amount),
+        ("block_time", stored.block_time == fresh.block_time),
+        ("slot", stored.slot == fresh.slot),
+        ("trader", stored.trader == fresh.trader),
+        ("fee", stored.fee == fresh.fee),
+        ("success", stored.success == fresh.success),
+        (
+            "input_transfer_fee",
+            stored.input_transfer_fee == fresh.input_transfer_fee,
+        ),
+        (
+            "output_transfer_fee",
+            stored.output_transfer_fee == fresh.output_transfer_fee,
+        ),
+        (
+            "input_decimals",
+            stored.input_decimals == fresh.input_decimals,
+        ),
+        (
+            "output_decimals",
+            stored.output_decimals == fresh.output_decimals,
+        ),
+        (
+            "quoted_out_amount",
+            stored.quoted_out_amount == fresh.quoted_out_amount,
+        ),
+        (
+            "minimum_out_amount",
+            stored.minimum_out_amount == fresh.minimum_out_amount,
+        ),
+        (
+            "program_version",
+            stored.program_version == fresh.program_version,
+        ),
+    ];
+    columns
+        .iter()
+        .filter(|(_, same)| !same)
+        .map(|(column, _)| *column)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_util::{swap, temp_db, USDC, USDT};
+
+    fn args(
+        start_slot: Option<u64>,
+        end_slot: Option<u64>,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> VerifyArgs {
+        VerifyArgs {
+            start_slot,
+            end_slot,
+            from,
+            to,
+            backfill: false,
+        }
+    }
+
+    #[test]
+    fn slot_ranges_are_inclusive() {
+        let args = args(Some(100), Some(200), None, None);
+        assert!(!args.in_range(99, Some(1)));
+        assert!(args.in_range(100, None));
+        assert!(args.in_range(200, None));
+        assert!(!args.in_range(201, None));
+
+        assert!(args.below_range(99, None));
+        assert!(!args.below_range(100, None));
+        assert!(!args.below_range(201, None));
+    }
+
+    #[test]
+    fn time_ranges_end_before_to() {
+        let args = args(None, None, Some(1_000), Some(2_000));
+        assert!(args.in_range(5, Some(1_000)));
+        assert!(args.in_range(5, Some(1_999)));
+        assert!(!args.in_range(5, Some(2_000)));
+        assert!(!args.in_range(5, Some(999)));
+        // without a block time a transaction can not be placed in a time range
+        assert!(!args.in_range(5, None));
+
+        assert!(args.below_range(5, Some(999)));
+        assert!(!args.below_range(5, Some(1_000)));
+        assert!(!args.below_range(5, None));
+    }
+
+    #[test]
+    fn differences_name_the_changed_columns() {
+        let (_dir, conn) = temp_db();
+        let tokens = vec![USDC.to_string(), USDT.to_string()];
+        let stored_swap = swap("a", 150, 1_000_000, 999_000);
+        let mut sink = DatabaseSink::new(tokens.clone(), conn, false);
+        sink.write_batch(std::slice::from_ref(&stored_swap))
+            .unwrap();
+        sink.checkpoint(&Checkpoint {
+            name: "test".to_string(),
+            sig: "a".to_string(),
+            slot: 150,
+        })
+        .unwrap();
+        let conn = sink.into_conn();
+
+        let stored = load_stored(&conn, &args(Some(100), Some(200), None, None)).unwrap();
+        let row = &stored["a"];
+        let fresh = build_create_tx_obj(&tokens, &stored_swap).unwrap();
+        assert!(differences(row, &fresh).is_empty());
+
+        let mut changed = stored_swap.clone();
+        changed.output_amount = 998_000;
+        changed.trader = "other".to_string();
+        let fresh = build_create_tx_obj(&tokens, &changed).unwrap();
+        assert_eq!(differences(row, &fresh), vec!["output_amount", "trader"]);
+
+        assert!(load_stored(&conn, &args(Some(151), None, None, None))
+            .unwrap()
+            .is_empty());
+    }
+}
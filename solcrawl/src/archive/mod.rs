@@ -0,0 +1,363 @@
+use crate::crawlers::replay_crawler::open_archive;
+use serde::{Deserialize, Serialize};
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub const INDEX_FILE: &str = "index.jsonl";
+const SEGMENT_PREFIX: &str = "segment-";
+const ZSTD_LEVEL: i32 = 3;
+// flushing often bounds what is lost when the process dies without closing the writer
+const FLUSH_EVERY_RECORDS: u64 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Jsonl,
+    Cbor,
+}
+
+impl ArchiveFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Jsonl => "jsonl",
+            ArchiveFormat::Cbor => "cbor",
+        }
+    }
+
+    // archives are named segment-000001.jsonl or segment-000001.cbor.zst
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let name = name.strip_suffix(".zst").unwrap_or(name);
+        if name.ends_with(".cbor") {
+            Some(ArchiveFormat::Cbor)
+        } else if name.ends_with(".jsonl") || name.ends_with(".json") {
+            Some(ArchiveFormat::Jsonl)
+        } else {
+            None
+        }
+    }
+}
+
+// IndexEntry locates an archived transaction, position is its record number in the segment
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub sig: String,
+    pub slot: u64,
+    pub segment: String,
+    pub position: u64,
+}
+
+// ArchiveWriter appends transactions to rotating segment files in a directory and
+// records each of them in the directory's index. Segments are rotated once they hold
+// max_segment_bytes of uncompressed data.
+pub struct ArchiveWriter {
+    dir: PathBuf,
+    format: ArchiveFormat,
+    compress: bool,
+    max_segment_bytes: u64,
+    segment_number: u64,
+    segment: Option<SegmentWriter>,
+    segment_bytes: u64,
+    segment_records: u64,
+    index: BufWriter<File>,
+}
+
+enum SegmentWriter {
+    Plain(BufWriter<File>),
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+}
+
+impl SegmentWriter {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            SegmentWriter::Plain(w) => w,
+            SegmentWriter::Zstd(w) => w,
+        }
+    }
+
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            SegmentWriter::Plain(mut w) => w.flush()?,
+            SegmentWriter::Zstd(w) => w.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl ArchiveWriter {
+    // continues numbering after the segments already in dir, existing segments are never appended to
+    pub fn new(
+        dir: PathBuf,
+        format: ArchiveFormat,
+        compress: bool,
+        max_segment_bytes: u64,
+    ) -> Result<Self, Box<dyn Error>> {
+        std::fs::create_dir_all(&dir)?;
+        let mut segment_number = 0;
+        for entry in std::fs::read_dir(&dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if let Some(number) = name
+                .strip_prefix(SEGMENT_PREFIX)
+                .and_then(|n| n.split('.').next())
+                .and_then(|n| n.parse::<u64>().ok())
+            {
+                segment_number = segment_number.max(number);
+            }
+        }
+
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(INDEX_FILE))?;
+        Ok(Self {
+            dir,
+            format,
+            compress,
+            max_segment_bytes,
+            segment_number,
+            segment: None,
+            segment_bytes: 0,
+            segment_records: 0,
+            index: BufWriter::new(index),
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        sig: &str,
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut record = match self.format {
+            ArchiveFormat::Jsonl => serde_json::to_vec(tx)?,
+            ArchiveFormat::Cbor => serde_cbor::to_vec(tx)?,
+        };
+        if self.format == ArchiveFormat::Jsonl {
+            record.push(b'\n');
+        }
+
+        if self.segment.is_none() || self.segment_bytes >= self.max_segment_bytes {
+            self.rotate()?;
+        }
+        let segment = self.segment.as_mut().ok_or("archive segment is not open")?;
+        segment.writer().write_all(&record)?;
+
+        let entry = IndexEntry {
+            sig: sig.to_string(),
+            slot: tx.slot,
+            segment: self.segment_name(),
+            position: self.segment_records,
+        };
+        serde_json::to_writer(&mut self.index, &entry)?;
+        self.index.write_all(b"\n")?;
+
+        self.segment_bytes += record.len() as u64;
+        self.segment_records += 1;
+        if self.segment_records.is_multiple_of(FLUSH_EVERY_RECORDS) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // flushed compressed data can be read back, but the segment is only a complete
+    // zstd frame once it has been closed
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(segment) = self.segment.as_mut() {
+            segment.writer().flush()?;
+        }
+        self.index.flush()?;
+        Ok(())
+    }
+
+    pub fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(segment) = self.segment.take() {
+            segment.finish()?;
+        }
+        self.index.flush()?;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), Box<dyn Error>> {
+        self.close()?;
+
+        self.segment_number += 1;
+        self.segment_bytes = 0;
+        self.segment_records = 0;
+        let file = BufWriter::new(File::create(self.dir.join(self.segment_name()))?);
+        self.segment = Some(match self.compress {
+            true => SegmentWriter::Zstd(zstd::stream::write::Encoder::new(file, ZSTD_LEVEL)?),
+            false => SegmentWriter::Plain(file),
+        });
+        Ok(())
+    }
+
+    fn segment_name(&self) -> String {
+        let mut name = format!(
+            "{}{:06}.{}",
+            SEGMENT_PREFIX,
+            self.segment_number,
+            self.format.extension()
+        );
+        if self.compress {
+            name.push_str(".zst");
+        }
+        name
+    }
+}
+
+impl Drop for ArchiveWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            println!("archive close err - {}", e);
+        }
+    }
+}
+
+// ArchiveIndex looks up archived transactions by signature or slot and reads them back
+// from their segment
+pub struct ArchiveIndex {
+    dir: PathBuf,
+    by_sig: HashMap<String, IndexEntry>,
+    by_slot: BTreeMap<u64, Vec<IndexEntry>>,
+}
+
+impl ArchiveIndex {
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut by_sig = HashMap::new();
+        let mut by_slot: BTreeMap<u64, Vec<IndexEntry>> = BTreeMap::new();
+        let reader = BufReader::new(File::open(dir.join(INDEX_FILE))?);
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: IndexEntry = serde_json::from_str(&line)?;
+            by_slot.entry(entry.slot).or_default().push(entry.clone());
+            by_sig.insert(entry.sig.clone(), entry);
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            by_sig,
+            by_slot,
+        })
+    }
+
+    pub fn get(&self, sig: &str) -> Option<&IndexEntry> {
+        self.by_sig.get(sig)
+    }
+
+    // entries of every transaction archived for slots start..=end
+    pub fn slot_range(&self, start: u64, end: u64) -> Vec<&IndexEntry> {
+        self.by_slot
+            .range(start..=end)
+            .flat_map(|(_, entries)| entries.iter())
+            .collect()
+    }
+
+    // reads the transaction of an entry, skipping the records before it in its segment
+    pub fn read(
+        &self,
+        entry: &IndexEntry,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, Box<dyn Error>> {
+        let path = self.dir.join(&entry.segment);
+        let reader = open_archive(&path)?;
+        let position = entry.position as usize;
+        let tx = match ArchiveFormat::from_path(&path) {
+            Some(ArchiveFormat::Cbor) => serde_cbor::Deserializer::from_reader(reader)
+                .into_iter::<EncodedConfirmedTransactionWithStatusMeta>()
+                .nth(position)
+                .transpose()?,
+            _ => match reader.lines().nth(position).transpose()? {
+                Some(line) => Some(serde_json::from_str(&line)?),
+                None => None,
+            },
+        };
+        tx.ok_or_else(|| format!("{} has no record {}", entry.segment, entry.position).into())
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_sig.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_sig.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{signature, transaction};
+    use solana_transaction_status::UiTransactionEncoding;
+
+    fn archive(dir: &Path, format: ArchiveFormat, compress: bool, max_segment_bytes: u64) {
+        let mut writer =
+            ArchiveWriter::new(dir.to_path_buf(), format, compress, max_segment_bytes).unwrap();
+        for n in 1..=3 {
+            let tx = transaction(&signature(n), 100 + n as u64, UiTransactionEncoding::Json);
+            writer.write(&signature(n).to_string(), &tx).unwrap();
+        }
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn index_looks_up_and_reads_archived_transactions() {
+        for (format, compress) in [
+            (ArchiveFormat::Jsonl, false),
+            (ArchiveFormat::Cbor, false),
+            (ArchiveFormat::Cbor, true),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            archive(dir.path(), format, compress, u64::MAX);
+            let index = ArchiveIndex::load(dir.path()).unwrap();
+            assert_eq!(index.len(), 3);
+
+            let entry = index.get(&signature(2).to_string()).unwrap();
+            assert_eq!(entry.slot, 102);
+            assert_eq!(entry.position, 1);
+            let tx = index.read(entry).unwrap();
+            assert_eq!(
+                serde_json::to_value(&tx).unwrap(),
+                serde_json::to_value(transaction(&signature(2), 102, UiTransactionEncoding::Json))
+                    .unwrap()
+            );
+
+            let slots = index
+                .slot_range(102, 103)
+                .iter()
+                .map(|entry| entry.sig.clone())
+                .collect::<Vec<String>>();
+            assert_eq!(
+                slots,
+                vec![signature(2).to_string(), signature(3).to_string()]
+            );
+            assert!(index.get(&signature(4).to_string()).is_none());
+        }
+    }
+
+    #[test]
+    fn segments_rotate_and_number_on_after_existing_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        // every record fills a segment
+        archive(dir.path(), ArchiveFormat::Jsonl, false, 1);
+        archive(dir.path(), ArchiveFormat::Jsonl, false, 1);
+
+        let mut segments = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name != INDEX_FILE)
+            .collect::<Vec<String>>();
+        segments.sort();
+        assert_eq!(segments.len(), 6);
+        assert_eq!(segments[5], "segment-000006.jsonl");
+
+        let index = ArchiveIndex::load(dir.path()).unwrap();
+        let entry = index.get(&signature(3).to_string()).unwrap();
+        assert_eq!(entry.segment, "segment-000006.jsonl");
+        assert_eq!(entry.position, 0);
+        assert_eq!(index.read(entry).unwrap().slot, 103);
+    }
+}
@@ -0,0 +1,373 @@
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const ZSTD_LEVEL: i32 = 3;
+const ENTRY_EXTENSION: &str = ".zst";
+const TMP_EXTENSION: &str = ".tmp";
+// entries are spread over subdirectories named by the first characters of the signature
+const SHARD_CHARS: usize = 2;
+// evicting below the limit leaves room for the next inserts instead of evicting on every one
+const EVICT_TO_PERCENT: u64 = 90;
+
+// numbers the temporary files of this process, so concurrent writers of one entry
+// never share a file
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// TransactionCache keeps fetched transactions on disk keyed by signature and encoding, so
+// restarted crawls and re-extraction read them locally instead of fetching them again.
+// Only finalized transactions are put in it, so an entry never goes stale. Once the
+// entries take more than max_bytes the least recently used are evicted.
+pub struct TransactionCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    entries: Mutex<CacheEntries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+// CacheEntries is the in memory index of the cache directory
+#[derive(Default)]
+struct CacheEntries {
+    // entry name -> last use and size in bytes
+    by_name: HashMap<String, (u64, u64)>,
+    // last use -> entry name, least recently used first
+    by_use: BTreeMap<u64, String>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl CacheEntries {
+    fn insert(&mut self, name: String, bytes: u64) {
+        self.clock += 1;
+        self.by_use.insert(self.clock, name.clone());
+        if let Some((last_use, old_bytes)) = self.by_name.insert(name, (self.clock, bytes)) {
+            self.by_use.remove(&last_use);
+            self.bytes -= old_bytes;
+        }
+        self.bytes += bytes;
+    }
+
+    fn touch(&mut self, name: &str) {
+        if let Some((last_use, _)) = self.by_name.get_mut(name) {
+            self.by_use.remove(last_use);
+            self.clock += 1;
+            *last_use = self.clock;
+            self.by_use.insert(self.clock, name.to_string());
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((last_use, bytes)) = self.by_name.remove(name) {
+            self.by_use.remove(&last_use);
+            self.bytes -= bytes;
+        }
+    }
+
+    fn pop_least_recent(&mut self) -> Option<String> {
+        let (_, name) = self.by_use.pop_first()?;
+        if let Some((_, bytes)) = self.by_name.remove(&name) {
+            self.bytes -= bytes;
+        }
+        Some(name)
+    }
+}
+
+// TODO dont print to std out - use a logger
+impl TransactionCache {
+    // indexes the entries already in dir, their modification time is their last use
+    pub fn open(dir: PathBuf, max_bytes: u64) -> Result<Self, Box<dyn Error>> {
+        std::fs::create_dir_all(&dir)?;
+        let mut found = Vec::new();
+        for shard in std::fs::read_dir(&dir)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(shard.path())? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                // left behind by a process that died while writing
+                if name.ends_with(TMP_EXTENSION) {
+                    std::fs::remove_file(entry.path())?;
+                    continue;
+                }
+                if !name.ends_with(ENTRY_EXTENSION) {
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                found.push((modified, name, metadata.len()));
+            }
+        }
+        found.sort();
+
+        let mut entries = CacheEntries::default();
+        for (_, name, bytes) in found {
+            entries.insert(name, bytes);
+        }
+        let cache = Self {
+            dir,
+            max_bytes,
+            entries: Mutex::new(entries),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        };
+        // the limit may have been lowered since the entries were written
+        cache.evict()?;
+        Ok(cache)
+    }
+
+    pub fn get(
+        &self,
+        sig: &str,
+        encoding: UiTransactionEncoding,
+    ) -> Option<EncodedConfirmedTransactionWithStatusMeta> {
+        let name = entry_name(sig, encoding);
+        let cached = self.lock().ok()?.by_name.contains_key(&name);
+        if !cached {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let path = self.entry_path(&name);
+        match read_entry(&path) {
+            Ok(tx) => {
+                if let Ok(mut entries) = self.lock() {
+                    entries.touch(&name);
+                }
+                // keeps the order of use across restarts, losing it only costs a worse eviction
+                if let Ok(file) = File::options().write(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(tx)
+            }
+            Err(e) => {
+                println!("tx cache dropping {} - {}", name, e);
+                if let Ok(mut entries) = self.lock() {
+                    entries.remove(&name);
+                }
+                let _ = std::fs::remove_file(&path);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn put(
+        &self,
+        sig: &str,
+        encoding: UiTransactionEncoding,
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = entry_name(sig, encoding);
+        if self.lock()?.by_name.contains_key(&name) {
+            return Ok(());
+        }
+
+        let data = zstd::encode_all(serde_json::to_vec(tx)?.as_slice(), ZSTD_LEVEL)?;
+        let path = self.entry_path(&name);
+        if let Some(shard) = path.parent() {
+            std::fs::create_dir_all(shard)?;
+        }
+        // written aside and renamed so a reader never sees a partial entry
+        let tmp = path.with_file_name(format!(
+            "{}.{}-{}{}",
+            name,
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+            TMP_EXTENSION
+        ));
+        std::fs::write(&tmp, &data)?;
+        std::fs::rename(&tmp, &path)?;
+
+        self.lock()?.insert(name, data.len() as u64);
+        self.evict()
+    }
+
+    // number of cached transactions
+    pub fn len(&self) -> usize {
+        self.lock().map(|e| e.by_name.len()).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // size of the cached transactions on disk
+    pub fn bytes(&self) -> u64 {
+        self.lock().map(|e| e.bytes).unwrap_or_default()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // evicts the least recently used entries once the cache is over its limit
+    fn evict(&self) -> Result<(), Box<dyn Error>> {
+        let mut entries = self.lock()?;
+        if entries.bytes <= self.max_bytes {
+            return Ok(());
+        }
+        let target = self.max_bytes / 100 * EVICT_TO_PERCENT;
+        while entries.bytes > target {
+            let name = match entries.pop_least_recent() {
+                Some(name) => name,
+                None => break,
+            };
+            match std::fs::remove_file(self.entry_path(&name)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, name: &str) -> PathBuf {
+        let shard = name.get(..SHARD_CHARS).unwrap_or(name);
+        self.dir.join(shard).join(name)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, CacheEntries>, String> {
+        self.entries
+            .lock()
+            .map_err(|_| "tx cache lock poisoned".to_string())
+    }
+}
+
+// the same transaction is a different document in every encoding
+fn entry_name(sig: &str, encoding: UiTransactionEncoding) -> String {
+    let encoding = match encoding {
+        UiTransactionEncoding::Binary => "binary",
+        UiTransactionEncoding::Base58 => "base58",
+        UiTransactionEncoding::Base64 => "base64",
+        UiTransactionEncoding::Json => "json",
+        UiTransactionEncoding::JsonParsed => "jsonParsed",
+    };
+    format!("{}.{}{}", sig, encoding, ENTRY_EXTENSION)
+}
+
+fn read_entry(path: &Path) -> Result<EncodedConfirmedTransactionWithStatusMeta, Box<dyn Error>> {
+    let data = zstd::decode_all(File::open(path)?)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{signature, transaction};
+    use std::sync::{Arc, Barrier};
+
+    const ENCODING: UiTransactionEncoding = UiTransactionEncoding::Json;
+
+    fn put(cache: &TransactionCache, n: u8) {
+        let tx = transaction(&signature(n), n as u64, ENCODING);
+        cache.put(&signature(n).to_string(), ENCODING, &tx).unwrap();
+    }
+
+    fn get(cache: &TransactionCache, n: u8) -> Option<u64> {
+        cache
+            .get(&signature(n).to_string(), ENCODING)
+            .map(|tx| tx.slot)
+    }
+
+    #[test]
+    fn reopening_indexes_the_stored_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TransactionCache::open(dir.path().to_path_buf(), u64::MAX).unwrap();
+        put(&cache, 1);
+        put(&cache, 2);
+        let bytes = cache.bytes();
+        drop(cache);
+
+        let cache = TransactionCache::open(dir.path().to_path_buf(), u64::MAX).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.bytes(), bytes);
+        assert_eq!(get(&cache, 1), Some(1));
+        assert_eq!(get(&cache, 3), None);
+        assert_eq!(
+            cache.get(&signature(1).to_string(), UiTransactionEncoding::Base64),
+            None
+        );
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let sizing = TransactionCache::open(dir.path().join("sizing"), u64::MAX).unwrap();
+        put(&sizing, 1);
+        let entry_bytes = sizing.bytes();
+
+        // room for two and a half entries
+        let cache = TransactionCache::open(dir.path().join("cache"), entry_bytes * 5 / 2).unwrap();
+        put(&cache, 1);
+        put(&cache, 2);
+        assert_eq!(get(&cache, 1), Some(1));
+        put(&cache, 3);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(get(&cache, 2), None);
+        assert_eq!(get(&cache, 1), Some(1));
+        assert_eq!(get(&cache, 3), Some(3));
+        assert!(!cache
+            .entry_path(&entry_name(&signature(2).to_string(), ENCODING))
+            .exists());
+    }
+
+    #[test]
+    fn cleans_up_tmp_files_and_drops_corrupt_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TransactionCache::open(dir.path().to_path_buf(), u64::MAX).unwrap();
+        put(&cache, 1);
+        put(&cache, 2);
+        let corrupt = cache.entry_path(&entry_name(&signature(1).to_string(), ENCODING));
+        let shard = corrupt.parent().unwrap().to_path_buf();
+        drop(cache);
+
+        std::fs::write(&corrupt, b"not zstd").unwrap();
+        let tmp = shard.join(format!("left-behind{}", TMP_EXTENSION));
+        std::fs::write(&tmp, b"partial").unwrap();
+
+        let cache = TransactionCache::open(dir.path().to_path_buf(), u64::MAX).unwrap();
+        assert!(!tmp.exists());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(get(&cache, 1), None);
+        assert!(!corrupt.exists());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(get(&cache, 2), Some(2));
+    }
+
+    #[test]
+    fn concurrent_puts_of_one_entry_succeed() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(TransactionCache::open(dir.path().to_path_buf(), u64::MAX).unwrap());
+        let barrier = Arc::new(Barrier::new(8));
+        let handles = (0..8)
+            .map(|_| {
+                let (cache, barrier) = (cache.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    let tx = transaction(&signature(1), 1, ENCODING);
+                    barrier.wait();
+                    cache.put(&signature(1).to_string(), ENCODING, &tx).is_ok()
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+        assert_eq!(cache.len(), 1);
+        assert_eq!(get(&cache, 1), Some(1));
+    }
+}
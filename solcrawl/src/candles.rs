@@ -0,0 +1,283 @@
+use crate::extractors::jupiter_swap::SwapEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+pub const ALL_INTERVALS: [CandleInterval; 4] = [
+    CandleInterval::OneMinute,
+    CandleInterval::FiveMinutes,
+    CandleInterval::OneHour,
+    CandleInterval::OneDay,
+];
+
+impl CandleInterval {
+    pub fn secs(&self) -> i64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::OneDay => 24 * 60 * 60,
+        }
+    }
+
+    // start of the candle a block time falls into, candles are aligned to the unix epoch
+    pub fn start_of(&self, block_time: i64) -> i64 {
+        block_time - block_time.rem_euclid(self.secs())
+    }
+}
+
+impl FromStr for CandleInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1m" => Ok(CandleInterval::OneMinute),
+            "5m" => Ok(CandleInterval::FiveMinutes),
+            "1h" => Ok(CandleInterval::OneHour),
+            "1d" => Ok(CandleInterval::OneDay),
+            _ => Err(format!(
+                "unknown candle interval {}, expected 1m, 5m, 1h or 1d",
+                s
+            )),
+        }
+    }
+}
+
+// Candle is the OHLCV of one pair over one interval. Pairs are stored once, with the
+// mints in lexicographic order, so swaps in both directions end up in the same candle.
+// The price is quote tokens per base token in ui amounts. Swaps do not arrive in
+// block time order (the historical crawler walks backwards), so the block times of
+// the open and close swaps are kept to merge candles in any order.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Candle {
+    pub base_token: String,
+    pub quote_token: String,
+    pub interval_secs: i64,
+    pub start_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub open_time: i64,
+    pub close_time: i64,
+    pub base_volume: f64,
+    pub quote_volume: f64,
+    pub trades: i64,
+}
+
+impl Candle {
+    // None for swaps that can not be priced: failed, without block time or with a zero side
+    pub fn from_swap(swap: &SwapEvent, interval: CandleInterval) -> Option<Self> {
+        let block_time = swap.block_time?;
+        if !swap.success || swap.input_ui_amount <= 0.0 || swap.output_ui_amount <= 0.0 {
+            return None;
+        }
+
+        let (base_token, quote_token, base_amount, quote_amount) =
+            if swap.input_token <= swap.output_token {
+                (
+                    &swap.input_token,
+                    &swap.output_token,
+                    swap.input_ui_amount,
+                    swap.output_ui_amount,
+                )
+            } else {
+                (
+                    &swap.output_token,
+                    &swap.input_token,
+                    swap.output_ui_amount,
+                    swap.input_ui_amount,
+                )
+            };
+        let price = quote_amount / base_amount;
+
+        Some(Self {
+            base_token: base_token.clone(),
+            quote_token: quote_token.clone(),
+            interval_secs: interval.secs(),
+            start_time: interval.start_of(block_time),
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            open_time: block_time,
+            close_time: block_time,
+            base_volume: base_amount,
+            quote_volume: quote_amount,
+            trades: 1,
+        })
+    }
+
+    pub fn key(&self) -> (String, String, i64, i64) {
+        (
+            self.base_token.clone(),
+            self.quote_token.clone(),
+            self.interval_secs,
+            self.start_time,
+        )
+    }
+
+    // merges another candle of the same pair and interval into this one,
+    // ties on block time keep the open already stored and take the newer close
+    pub fn merge(&mut self, other: &Candle) {
+        if other.open_time < self.open_time {
+            self.open = other.open;
+            self.open_time = other.open_time;
+        }
+        if other.close_time >= self.close_time {
+            self.close = other.close;
+            self.close_time = other.close_time;
+        }
+        self.high = self.high.max(other.high);
+        self.low = self.low.min(other.low);
+        self.base_volume += other.base_volume;
+        self.quote_volume += other.quote_volume;
+        self.trades += other.trades;
+    }
+}
+
+// aggregates swaps into one candle per pair, interval and start time
+pub fn aggregate(swaps: &[SwapEvent], intervals: &[CandleInterval]) -> Vec<Candle> {
+    let mut candles: HashMap<(String, String, i64, i64), Candle> = HashMap::new();
+    for swap in swaps {
+        for interval in intervals {
+            let candle = match Candle::from_swap(swap, *interval) {
+                Some(candle) => candle,
+                None => continue,
+            };
+            match candles.get_mut(&candle.key()) {
+                Some(existing) => existing.merge(&candle),
+                None => {
+                    candles.insert(candle.key(), candle);
+                }
+            }
+        }
+    }
+    candles.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+    fn swap(
+        input_token: &str,
+        output_token: &str,
+        block_time: i64,
+        input: f64,
+        output: f64,
+    ) -> SwapEvent {
+        serde_json::from_value(serde_json::json!({
+            "sig": format!("sig{}", block_time),
+            "slot": block_time,
+            "block_time": block_time,
+            "trader": "trader",
+            "fee": 5000,
+            "success": true,
+            "input_token": input_token,
+            "output_token": output_token,
+            "input_amount": (input * 1e6) as u64,
+            "output_amount": (output * 1e6) as u64,
+            "input_token_program": crate::tokens::TOKEN_PROGRAM,
+            "output_token_program": crate::tokens::TOKEN_PROGRAM,
+            "input_transfer_fee": 0,
+            "output_transfer_fee": 0,
+            "input_decimals": 6,
+            "output_decimals": 6,
+            "input_ui_amount": input,
+            "output_ui_amount": output,
+            "price": output / input,
+            "inverse_price": input / output,
+            "legs": [],
+        }))
+        .unwrap()
+    }
+
+    fn candle(block_time: i64, price: f64) -> Candle {
+        Candle::from_swap(
+            &swap(USDC, USDT, block_time, 1.0, price),
+            CandleInterval::OneHour,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn merge_keeps_open_and_close_by_block_time() {
+        let mut merged = candle(3_700, 1.02);
+        merged.merge(&candle(3_650, 0.98));
+        merged.merge(&candle(3_800, 1.0));
+        merged.merge(&candle(3_600, 1.01));
+
+        assert_eq!((merged.open, merged.open_time), (1.01, 3_600));
+        assert_eq!((merged.close, merged.close_time), (1.0, 3_800));
+        assert_eq!((merged.high, merged.low), (1.02, 0.98));
+        assert_eq!(merged.base_volume, 4.0);
+        assert_eq!(merged.trades, 4);
+    }
+
+    #[test]
+    fn merge_ties_keep_stored_open_and_take_new_close() {
+        let mut merged = candle(3_600, 1.0);
+        merged.merge(&candle(3_600, 1.01));
+
+        assert_eq!(merged.open, 1.0);
+        assert_eq!(merged.close, 1.01);
+    }
+
+    #[test]
+    fn aggregate_groups_both_directions_of_a_pair() {
+        let swaps = [
+            swap(USDC, USDT, 3_600, 1.0, 1.01),
+            // usdt for usdc, priced the other way round
+            swap(USDT, USDC, 3_610, 2.0, 1.98),
+            swap(USDC, USDT, 7_200, 1.0, 1.0),
+        ];
+        let mut candles = aggregate(&swaps, &[CandleInterval::OneHour, CandleInterval::OneDay]);
+        candles.sort_by_key(|c| (c.interval_secs, c.start_time));
+
+        assert_eq!(candles.len(), 3);
+        let first_hour = &candles[0];
+        assert_eq!(
+            (
+                first_hour.base_token.as_str(),
+                first_hour.quote_token.as_str()
+            ),
+            (USDC, USDT)
+        );
+        assert_eq!(first_hour.start_time, 3_600);
+        assert_eq!(first_hour.trades, 2);
+        assert_eq!(first_hour.base_volume, 2.98);
+        assert_eq!(first_hour.quote_volume, 3.01);
+        assert_eq!(first_hour.close, 2.0 / 1.98);
+        assert_eq!(candles[1].start_time, 7_200);
+        assert_eq!(candles[2].start_time, 0);
+        assert_eq!(candles[2].trades, 3);
+    }
+
+    #[test]
+    fn aggregate_skips_swaps_that_can_not_be_priced() {
+        let mut failed = swap(USDC, USDT, 3_600, 1.0, 1.0);
+        failed.success = false;
+        let mut no_time = swap(USDC, USDT, 3_600, 1.0, 1.0);
+        no_time.block_time = None;
+        let mut empty = swap(USDC, USDT, 3_600, 1.0, 1.0);
+        empty.output_ui_amount = 0.0;
+
+        assert!(aggregate(&[failed, no_time, empty], &ALL_INTERVALS).is_empty());
+    }
+}
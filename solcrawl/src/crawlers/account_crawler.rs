@@ -0,0 +1,191 @@
+use crate::filters::AccountFilter;
+use crate::tokens::{TOKEN_2022_PROGRAM, TOKEN_PROGRAM};
+
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::RpcFilterType;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_response::{OptionalContext, RpcKeyedAccount};
+
+use solana_program::program_pack::Pack;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::error::Error;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountSource {
+    // initial state from getProgramAccounts
+    Snapshot,
+    // change streamed by programSubscribe
+    Update,
+}
+
+// AccountEvent is the state of a program owned account as of a slot
+#[derive(Clone, Debug)]
+pub struct AccountEvent {
+    pub pubkey: String,
+    pub slot: u64,
+    pub account: Account,
+    pub source: AccountSource,
+}
+
+impl AccountEvent {
+    // decodes the account as a token account, token 2022 accounts share the classic layout
+    // with extensions appended after it
+    pub fn token_account(&self) -> Result<spl_token::state::Account, Box<dyn Error>> {
+        if self.account.owner.to_string() != TOKEN_PROGRAM
+            && self.account.owner.to_string() != TOKEN_2022_PROGRAM
+        {
+            return Err(format!("account {} is not a token account", self.pubkey).into());
+        }
+        if self.account.data.len() < spl_token::state::Account::LEN {
+            return Err(format!("account {} is too small for a token account", self.pubkey).into());
+        }
+        Ok(spl_token::state::Account::unpack_from_slice(
+            &self.account.data[..spl_token::state::Account::LEN],
+        )?)
+    }
+}
+
+pub type AccountSender = crossbeam::channel::Sender<AccountEvent>;
+pub type AccountReceiver = crossbeam::channel::Receiver<AccountEvent>;
+
+// AccountCrawler publishes the state of every account owned by a program, first as a
+// snapshot and then as a stream of changes. rpc_filters (memcmp, dataSize) are applied by
+// the rpc node to both, filters are applied locally to every event.
+pub struct AccountCrawler {
+    rpc_url: String,
+    ws_url: String,
+    program_addr: String,
+    rpc_filters: Vec<RpcFilterType>,
+    filters: Vec<Box<dyn AccountFilter + Send>>,
+    publisher: AccountSender,
+    commitment: CommitmentConfig,
+}
+
+// TODO dont print to std out - use a logger
+impl AccountCrawler {
+    pub fn new(
+        program_addr: String,
+        rpc_url: String,
+        ws_url: String,
+        rpc_filters: Vec<RpcFilterType>,
+        filters: Vec<Box<dyn AccountFilter + Send>>,
+        commitment: CommitmentConfig,
+    ) -> (Self, AccountReceiver) {
+        let (publisher, account_recv) = crossbeam::channel::unbounded();
+        (
+            Self {
+                rpc_url,
+                ws_url,
+                program_addr,
+                rpc_filters,
+                filters,
+                publisher,
+                commitment,
+            },
+            account_recv,
+        )
+    }
+
+    // every reconnect starts with a fresh snapshot, so no change is lost while disconnected
+    pub fn crawl(&self) {
+        loop {
+            let res = self.try_crawl();
+            if let Err(e) = res {
+                println!("account crawl err - {}", e);
+            }
+        }
+    }
+
+    fn try_crawl(&self) -> Result<(), Box<dyn Error>> {
+        let client = solana_client::rpc_client::RpcClient::new_with_commitment(
+            self.rpc_url.clone(),
+            self.commitment,
+        );
+        let program = Pubkey::from_str(self.program_addr.as_str())?;
+        let config = RpcProgramAccountsConfig {
+            filters: Some(self.rpc_filters.clone()),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                data_slice: None,
+                commitment: Some(self.commitment),
+                min_context_slot: None,
+            },
+            with_context: Some(true),
+        };
+
+        // subscribe before taking the snapshot so changes made in between are not missed
+        let (_sub, recv) = solana_client::pubsub_client::PubsubClient::program_subscribe(
+            self.ws_url.as_str(),
+            &program,
+            Some(config.clone()),
+        )?;
+
+        let (snapshot_slot, accounts) = snapshot(&client, &program, config)?;
+        println!(
+            "account crawler - snapshot of {} accounts at slot {}",
+            accounts.len(),
+            snapshot_slot
+        );
+        for keyed in accounts {
+            let account = keyed
+                .account
+                .decode::<Account>()
+                .ok_or_else(|| format!("could not decode account {}", keyed.pubkey))?;
+            self.publish(AccountEvent {
+                pubkey: keyed.pubkey,
+                slot: snapshot_slot,
+                account,
+                source: AccountSource::Snapshot,
+            })?;
+        }
+
+        loop {
+            let update = recv.recv()?;
+            // already part of the snapshot
+            if update.context.slot < snapshot_slot {
+                continue;
+            }
+
+            let account = update
+                .value
+                .account
+                .decode::<Account>()
+                .ok_or("could not decode account update")?;
+            self.publish(AccountEvent {
+                pubkey: update.value.pubkey,
+                slot: update.context.slot,
+                account,
+                source: AccountSource::Update,
+            })?;
+        }
+    }
+
+    fn publish(&self, event: AccountEvent) -> Result<(), Box<dyn Error>> {
+        if self.filters.iter().any(|filter| filter.filter(&event)) {
+            return Ok(());
+        }
+        self.publisher.send(event)?;
+        Ok(())
+    }
+}
+
+// getProgramAccounts with the slot the accounts were read at. The client drops the context
+// of the response, and a slot fetched on its own may be older or newer than the snapshot
+fn snapshot(
+    client: &solana_client::rpc_client::RpcClient,
+    program: &Pubkey,
+    config: RpcProgramAccountsConfig,
+) -> Result<(u64, Vec<RpcKeyedAccount>), Box<dyn Error>> {
+    let response = client.send::<OptionalContext<Vec<RpcKeyedAccount>>>(
+        RpcRequest::GetProgramAccounts,
+        serde_json::json!([program.to_string(), config]),
+    )?;
+    match response {
+        OptionalContext::Context(response) => Ok((response.context.slot, response.value)),
+        OptionalContext::NoContext(_) => Err("getProgramAccounts returned no context".into()),
+    }
+}
@@ -0,0 +1,302 @@
+use crate::crawlers::{
+    should_filter, watched_addresses, Commitment, CrawledTransaction, TransactionReceiver,
+    TransactionSender,
+};
+use crate::filters::TransactionFilter;
+use crate::transaction_view::TransactionView;
+
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_config::RpcBlockConfig;
+use solana_client::rpc_custom_error::{
+    JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED, JSON_RPC_SERVER_ERROR_SLOT_SKIPPED,
+};
+use solana_client::rpc_request::RpcError;
+
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransactionWithStatusMeta,
+    TransactionDetails, UiTransactionEncoding,
+};
+use std::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+// number of slots requested per getBlocks call
+const SLOTS_PER_REQUEST: u64 = 1000;
+// how long to wait for new blocks once the crawler has caught up with the cluster
+const CAUGHT_UP_SLEEP: Duration = Duration::from_secs(5);
+
+// BlockCrawler walks every block of a slot range, which unlike signature listings
+// or logs subscriptions does not miss transactions. An empty address list publishes
+// every transaction that passes the filters.
+pub struct BlockCrawler {
+    rpc_url: String,
+    addresses: Vec<String>,
+    filters: Vec<Box<dyn TransactionFilter + Send>>,
+    publisher: TransactionSender,
+    sleep_duration: Option<Duration>,
+    encoding: UiTransactionEncoding,
+    curr_slot: u64,
+    // transactions of curr_slot already handled, so a retry does not publish them again
+    curr_tx: usize,
+    // last transaction published from the start slot by an earlier run
+    resume_sig: Option<String>,
+    // inclusive, None follows the cluster forever
+    end_slot: Option<u64>,
+}
+
+// TODO dont print to std out - use a logger
+impl BlockCrawler {
+    pub fn new(
+        addresses: Vec<String>,
+        rpc_url: String,
+        filters: Vec<Box<dyn TransactionFilter + Send>>,
+        sleep_duration: Option<Duration>,
+        start_slot: u64,
+        end_slot: Option<u64>,
+        encoding: UiTransactionEncoding,
+    ) -> (Self, TransactionReceiver) {
+        let (publisher, tx_recv) = crossbeam::channel::unbounded();
+        (
+            Self {
+                rpc_url,
+                addresses,
+                filters,
+                publisher,
+                sleep_duration,
+                encoding,
+                curr_slot: start_slot,
+                curr_tx: 0,
+                resume_sig: None,
+                end_slot,
+            },
+            tx_recv,
+        )
+    }
+
+    // continues after sig in the start slot, the checkpoint of an earlier run
+    pub fn with_resume_sig(mut self, sig: Option<String>) -> Self {
+        self.resume_sig = sig;
+        self
+    }
+
+    // next slot to be crawled
+    pub fn curr_slot(&self) -> u64 {
+        self.curr_slot
+    }
+
+    // returns once the end slot has been crawled
+    pub fn crawl(&mut self) {
+        while !self.is_done() {
+            let res = self.try_crawl();
+            if let Err(e) = res {
+                println!("block crawl err - {}", e);
+            }
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self.end_slot, Some(end) if self.curr_slot > end)
+    }
+
+    fn try_crawl(&mut self) -> Result<(), Box<dyn Error>> {
+        let client = solana_client::rpc_client::RpcClient::new_with_commitment(
+            self.rpc_url.clone(),
+            CommitmentConfig::finalized(),
+        );
+
+        while !self.is_done() {
+            let mut last_slot = self.curr_slot + SLOTS_PER_REQUEST - 1;
+            if let Some(end) = self.end_slot {
+                last_slot = last_slot.min(end);
+            }
+
+            // skipped slots are left out of the listing
+            let slots = client.get_blocks(self.curr_slot, Some(last_slot))?;
+            for slot in slots {
+                if slot > self.curr_slot {
+                    self.curr_slot = slot;
+                    self.curr_tx = 0;
+                }
+                self.crawl_block(&client, slot)?;
+                self.curr_slot = slot + 1;
+                self.curr_tx = 0;
+            }
+
+            // blocks past the latest finalized slot may still appear, so wait for them
+            if client.get_slot()? >= last_slot {
+                self.curr_slot = self.curr_slot.max(last_slot + 1);
+            } else {
+                sleep(CAUGHT_UP_SLEEP);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn crawl_block(
+        &mut self,
+        client: &solana_client::rpc_client::RpcClient,
+        slot: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(dur) = self.sleep_duration {
+            sleep(dur);
+        }
+
+        let block = match client.get_block_with_config(
+            slot,
+            RpcBlockConfig {
+                encoding: Some(self.encoding),
+                transaction_details: Some(TransactionDetails::Full),
+                rewards: Some(true),
+                commitment: Some(CommitmentConfig::finalized()),
+                max_supported_transaction_version: Some(0),
+            },
+        ) {
+            Ok(block) => block,
+            Err(e) if is_skipped_slot(&e) => {
+                println!("block crawler - slot {} was skipped", slot);
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        println!("block crawler - {}", slot);
+
+        let txs = block.transactions.unwrap_or_default();
+        // only the first block crawled can hold the resume signature
+        if let Some(resume_sig) = self.resume_sig.take() {
+            self.curr_tx = txs
+                .iter()
+                .position(|tx| tx_signature(tx).as_deref() == Some(resume_sig.as_str()))
+                .map_or(0, |i| i + 1);
+        }
+
+        for tx in txs.into_iter().skip(self.curr_tx) {
+            self.publish(EncodedConfirmedTransactionWithStatusMeta {
+                slot,
+                transaction: tx,
+                block_time: block.block_time,
+            })?;
+            self.curr_tx += 1;
+        }
+
+        Ok(())
+    }
+
+    fn publish(&self, tx: EncodedConfirmedTransactionWithStatusMeta) -> Result<(), Box<dyn Error>> {
+        let view = match TransactionView::new(&tx) {
+            Ok(view) => view,
+            Err(e) => {
+                println!(
+                    "block crawler - could not decode tx in slot {} - {}",
+                    tx.slot, e
+                );
+                return Ok(());
+            }
+        };
+        let sig = match view.signature() {
+            Some(sig) => sig.to_string(),
+            None => {
+                println!("block crawler - tx without signature in slot {}", tx.slot);
+                return Ok(());
+            }
+        };
+        let matched = watched_addresses(&view, &self.addresses);
+        if !self.addresses.is_empty() && matched.is_empty() {
+            return Ok(());
+        }
+
+        if !should_filter(&self.filters, &tx) {
+            self.publisher.send(CrawledTransaction {
+                sig,
+                matched,
+                commitment: Commitment::Finalized,
+                cursor: None,
+                tx,
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn tx_signature(tx: &EncodedTransactionWithStatusMeta) -> Option<String> {
+    let tx = EncodedConfirmedTransactionWithStatusMeta {
+        slot: 0,
+        transaction: tx.clone(),
+        block_time: None,
+    };
+    let view = TransactionView::new(&tx).ok()?;
+    view.signature().map(|sig| sig.to_string())
+}
+
+fn is_skipped_slot(err: &ClientError) -> bool {
+    matches!(
+        err.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if *code == JSON_RPC_SERVER_ERROR_SLOT_SKIPPED
+                || *code == JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{rpc_server, signature, transaction};
+    use serde_json::{json, Value};
+
+    fn block(txs: Vec<Value>) -> Value {
+        json!({
+            "previousBlockhash": "11111111111111111111111111111111",
+            "blockhash": "11111111111111111111111111111111",
+            "parentSlot": 0,
+            "transactions": txs,
+            "blockTime": 0,
+            "blockHeight": 0,
+        })
+    }
+
+    fn tx(n: u8, slot: u64) -> Value {
+        serde_json::to_value(
+            transaction(&signature(n), slot, UiTransactionEncoding::Json).transaction,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn resumes_after_the_checkpoint_and_skips_unsigned_txs() {
+        let (url, _stop) = rpc_server(|method, params| match method {
+            "getBlocks" => json!([10, 11]),
+            "getBlock" => match params[0].as_u64().unwrap() {
+                10 => {
+                    let mut unsigned = tx(3, 10);
+                    unsigned["transaction"]["signatures"] = json!([]);
+                    block(vec![tx(1, 10), tx(2, 10), unsigned, tx(4, 10)])
+                }
+                _ => block(vec![tx(5, 11)]),
+            },
+            "getSlot" => json!(100),
+            _ => Value::Null,
+        });
+
+        let (crawler, recv) = BlockCrawler::new(
+            vec![],
+            url,
+            vec![],
+            None,
+            10,
+            Some(11),
+            UiTransactionEncoding::Json,
+        );
+        let mut crawler = crawler.with_resume_sig(Some(signature(2).to_string()));
+        crawler.crawl();
+        drop(crawler);
+
+        let published = recv.iter().map(|tx| tx.sig).collect::<Vec<String>>();
+        assert_eq!(
+            published,
+            vec![signature(4).to_string(), signature(5).to_string()]
+        );
+    }
+}
@@ -1,3 +1,8 @@
+use crate::cache::TransactionCache;
+use crate::crawlers::{
+    fetch_transaction, matched_addresses, should_filter, Commitment, CrawledTransaction,
+    SeenSignatures, TransactionReceiver, TransactionSender,
+};
 use crate::filters::TransactionFilter;
 
 use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
@@ -5,57 +10,74 @@ use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
 use solana_program::pubkey::Pubkey;
 
 use solana_sdk::signature::Signature;
-use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use solana_transaction_status::UiTransactionEncoding;
 use std::error::Error;
 use std::str::FromStr;
-use std::thread::sleep;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub struct HistoricalCrawler {
     rpc_url: String,
-    program_addr: String,
+    addresses: Vec<String>,
     filters: Vec<Box<dyn TransactionFilter>>,
-    publisher: crossbeam::channel::Sender<(String, EncodedConfirmedTransactionWithStatusMeta)>,
+    publisher: TransactionSender,
     sleep_duration: Option<Duration>,
-    curr_sig: Option<Signature>,
+    encoding: UiTransactionEncoding,
+    // fetched transactions are read from and put in the cache when set
+    cache: Option<Arc<TransactionCache>>,
+    // one cursor per watched address, each walks back through that address's history
+    curr_sigs: Vec<Option<Signature>>,
+    seen: SeenSignatures,
 }
 
 unsafe impl Send for HistoricalCrawler {}
 
 // TODO dont print to std out - use a logger
 impl HistoricalCrawler {
+    // curr_sig_strs holds the signature every address continues below, in the same order
+    // as addresses, None starts at its newest transaction
     pub fn new(
-        program_addr: String,
+        addresses: Vec<String>,
         rpc_url: String,
         filters: Vec<Box<dyn TransactionFilter>>,
         sleep_duration: Option<Duration>,
-        curr_sig_str: Option<String>,
-    ) -> Result<
-        (
-            Self,
-            crossbeam::channel::Receiver<(String, EncodedConfirmedTransactionWithStatusMeta)>,
-        ),
-        Box<dyn Error>,
-    > {
-        let mut curr_sig: Option<Signature> = None;
-        if let Some(sig) = curr_sig_str {
-            curr_sig = Some(Signature::from_str(sig.as_str())?)
+        curr_sig_strs: Vec<Option<String>>,
+        encoding: UiTransactionEncoding,
+    ) -> Result<(Self, TransactionReceiver), Box<dyn Error>> {
+        if addresses.is_empty() {
+            return Err("historical crawler needs at least one address".into());
         }
+        if curr_sig_strs.len() != addresses.len() {
+            return Err("historical crawler needs one resume signature per address".into());
+        }
+
+        let curr_sigs = curr_sig_strs
+            .iter()
+            .map(|sig| sig.as_deref().map(Signature::from_str).transpose())
+            .collect::<Result<Vec<Option<Signature>>, _>>()?;
 
         let (publisher, tx_recv) = crossbeam::channel::unbounded();
         Ok((
             Self {
                 rpc_url,
-                program_addr,
+                curr_sigs,
+                addresses,
                 filters,
                 publisher,
                 sleep_duration,
-                curr_sig,
+                encoding,
+                cache: None,
+                seen: SeenSignatures::new(),
             },
             tx_recv,
         ))
     }
 
+    pub fn with_cache(mut self, cache: Option<Arc<TransactionCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
     pub fn crawl(&mut self) {
         loop {
             let res = self.try_crawl();
@@ -66,53 +88,188 @@ impl HistoricalCrawler {
     }
     fn try_crawl(&mut self) -> Result<(), Box<dyn Error>> {
         let client = solana_client::rpc_client::RpcClient::new(self.rpc_url.clone());
+        let addresses = self
+            .addresses
+            .iter()
+            .map(|addr| Pubkey::from_str(addr.as_str()))
+            .collect::<Result<Vec<Pubkey>, _>>()?;
 
         loop {
-            let tx_statuses = client.get_signatures_for_address_with_config(
-                &Pubkey::from_str(self.program_addr.as_str())?,
-                GetConfirmedSignaturesForAddress2Config {
-                    before: self.curr_sig,
-                    until: None,
-                    limit: None,
-                    commitment: None,
-                },
-            )?;
-            for tx_status in tx_statuses {
+            // merge a page of every address, newest first like a single address listing
+            let mut tx_statuses = Vec::new();
+            for (i, addr) in addresses.iter().enumerate() {
+                let statuses = client.get_signatures_for_address_with_config(
+                    addr,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before: self.curr_sigs[i],
+                        until: None,
+                        limit: None,
+                        commitment: None,
+                    },
+                )?;
+                tx_statuses.extend(statuses.into_iter().map(|status| (i, status)));
+            }
+            tx_statuses.sort_by_key(|(_, status)| std::cmp::Reverse(status.slot));
+
+            for (i, tx_status) in tx_statuses {
                 let sig = Signature::from_str(tx_status.signature.as_str())?;
-                self.curr_sig = Some(sig);
+                self.curr_sigs[i] = Some(sig);
+                if !self.seen.insert(tx_status.signature.as_str()) {
+                    continue;
+                }
 
                 println!("hs crawler - {}", sig);
 
-                let mut tx: Option<EncodedConfirmedTransactionWithStatusMeta> = None;
-                for _ in 0..5 {
-                    if let Some(dur) = self.sleep_duration {
-                        sleep(dur);
-                    }
-
-                    let res = client.get_transaction(
-                        &sig,
-                        solana_transaction_status::UiTransactionEncoding::Json,
-                    );
-                    if let Ok(res) = res {
-                        tx = Some(res);
-                        break;
+                let tx = fetch_transaction(
+                    &client,
+                    &sig,
+                    self.encoding,
+                    None,
+                    self.sleep_duration,
+                    self.cache.as_deref(),
+                );
+                if let Some(tx) = tx {
+                    if !should_filter(&self.filters, &tx) {
+                        self.publisher.send(CrawledTransaction {
+                            sig: sig.to_string(),
+                            matched: matched_addresses(&tx, &self.addresses, &self.addresses[i]),
+                            commitment: Commitment::Finalized,
+                            cursor: Some(self.addresses[i].clone()),
+                            tx,
+                        })?;
                     }
                 }
+            }
+        }
+    }
+}
 
-                if let Some(tx) = tx {
-                    let mut should_filter = false;
-                    for filter in &self.filters {
-                        if filter.filter(&tx) {
-                            should_filter = true;
-                            break;
-                        }
-                    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{key, rpc_server, signature, transaction};
+    use serde_json::{json, Value};
+    use std::sync::atomic::Ordering;
+    use std::sync::Mutex;
 
-                    if !should_filter {
-                        self.publisher.send((sig.to_string(), tx))?;
+    const PAGE_SIZE: usize = 2;
+
+    #[test]
+    fn every_address_resumes_from_its_own_cursor() {
+        let (a, b) = (key(10).to_string(), key(11).to_string());
+        // newest first, a has paged down to slot 800 and b to slot 850
+        let history = [
+            (
+                a.clone(),
+                vec![
+                    (signature(20), 900),
+                    (signature(21), 800),
+                    (signature(22), 700),
+                ],
+            ),
+            (
+                b.clone(),
+                vec![
+                    (signature(30), 850),
+                    (signature(31), 500),
+                    (signature(32), 400),
+                ],
+            ),
+        ];
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let log = requested.clone();
+        let (url, stop) = rpc_server(move |method, params| match method {
+            "getSignaturesForAddress" => {
+                let addr = params[0].as_str().unwrap().to_string();
+                let before = params[1]["before"].as_str().map(|s| s.to_string());
+                log.lock().unwrap().push((addr.clone(), before.clone()));
+                let (_, listing) = history.iter().find(|(a, _)| *a == addr).unwrap();
+                let start = match before {
+                    Some(before) => {
+                        listing
+                            .iter()
+                            .position(|(s, _)| s.to_string() == before)
+                            .unwrap()
+                            + 1
                     }
-                }
+                    None => 0,
+                };
+                listing
+                    .iter()
+                    .skip(start)
+                    .take(PAGE_SIZE)
+                    .map(|(sig, slot)| {
+                        json!({
+                            "signature": sig.to_string(),
+                            "slot": slot,
+                            "err": null,
+                            "memo": null,
+                            "blockTime": slot,
+                            "confirmationStatus": "finalized",
+                        })
+                    })
+                    .collect::<Vec<Value>>()
+                    .into()
             }
-        }
+            "getTransaction" => {
+                let sig = Signature::from_str(params[0].as_str().unwrap()).unwrap();
+                let slot = history
+                    .iter()
+                    .flat_map(|(_, listing)| listing.iter())
+                    .find(|(s, _)| *s == sig)
+                    .map(|(_, slot)| *slot)
+                    .unwrap();
+                serde_json::to_value(transaction(&sig, slot, UiTransactionEncoding::Json)).unwrap()
+            }
+            _ => Value::Null,
+        });
+
+        let (mut crawler, recv) = HistoricalCrawler::new(
+            vec![a.clone(), b.clone()],
+            url,
+            vec![],
+            None,
+            vec![
+                Some(signature(21).to_string()),
+                Some(signature(30).to_string()),
+            ],
+            UiTransactionEncoding::Json,
+        )
+        .unwrap();
+        let handle = std::thread::spawn(move || crawler.try_crawl().is_err());
+
+        let published = (0..3)
+            .map(|_| {
+                let tx = recv.recv_timeout(Duration::from_secs(10)).unwrap();
+                (tx.sig, tx.cursor.unwrap())
+            })
+            .collect::<Vec<(String, String)>>();
+        stop.store(true, Ordering::SeqCst);
+        assert!(handle.join().unwrap());
+
+        assert_eq!(
+            published,
+            vec![
+                (signature(22).to_string(), a.clone()),
+                (signature(31).to_string(), b.clone()),
+                (signature(32).to_string(), b.clone()),
+            ]
+        );
+        let requested = requested.lock().unwrap();
+        assert_eq!(requested[0], (a, Some(signature(21).to_string())));
+        assert_eq!(requested[1], (b, Some(signature(30).to_string())));
+    }
+
+    #[test]
+    fn needs_a_resume_signature_per_address() {
+        let res = HistoricalCrawler::new(
+            vec![key(10).to_string(), key(11).to_string()],
+            "http://127.0.0.1:0".to_string(),
+            vec![],
+            None,
+            vec![None],
+            UiTransactionEncoding::Json,
+        );
+        assert!(res.is_err());
     }
 }
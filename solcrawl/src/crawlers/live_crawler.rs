@@ -0,0 +1,363 @@
+use crate::cache::TransactionCache;
+use crate::crawlers::{
+    fetch_transaction, matched_addresses, should_filter, Commitment, CrawledTransaction,
+    SeenSignatures, TransactionReceiver,
+};
+use crate::filters::TransactionFilter;
+
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// getSignatureStatuses accepts at most 256 signatures per request
+const STATUS_BATCH_SIZE: usize = 256;
+// new roots a transaction that could not be fetched is retried for before it is dropped
+const MAX_FETCH_ROUNDS: u32 = 30;
+
+// LiveEvent is published by the LiveCrawler as a transaction moves towards finality
+#[derive(Debug)]
+pub enum LiveEvent {
+    // the transaction was seen at the crawlers commitment and may still be rolled back
+    Confirmed(Box<CrawledTransaction>),
+    Finalized { sig: String, slot: u64 },
+    // the transaction was dropped by the cluster, its fork was abandoned
+    RolledBack { sig: String, slot: u64 },
+}
+
+pub type LiveEventSender = crossbeam::channel::Sender<LiveEvent>;
+pub type LiveEventReceiver = crossbeam::channel::Receiver<LiveEvent>;
+
+// splits live events into the confirmed transactions, which a pipeline can read like any
+// other crawler channel, and the finalized and rolled back notices
+pub fn split_events(events: LiveEventReceiver) -> (TransactionReceiver, LiveEventReceiver) {
+    let (tx_send, tx_recv) = crossbeam::channel::unbounded();
+    let (finality_send, finality_recv) = crossbeam::channel::unbounded();
+    std::thread::spawn(move || {
+        for event in events {
+            let sent = match event {
+                LiveEvent::Confirmed(tx) => tx_send.send(*tx).is_ok(),
+                event => finality_send.send(event).is_ok(),
+            };
+            if !sent {
+                break;
+            }
+        }
+    });
+    (tx_recv, finality_recv)
+}
+
+// LiveCrawler ingests transactions at processed or confirmed commitment for low latency,
+// then follows the cluster root through a slot subscription and reports each transaction
+// as finalized or rolled back so downstream can correct provisional data.
+pub struct LiveCrawler {
+    rpc_url: String,
+    ws_url: String,
+    addresses: Vec<String>,
+    filters: Vec<Box<dyn TransactionFilter + Send>>,
+    publisher: LiveEventSender,
+    sleep_duration: Option<Duration>,
+    encoding: UiTransactionEncoding,
+    // fetched transactions are read from and put in the cache when set
+    cache: Option<Arc<TransactionCache>>,
+    commitment: CommitmentConfig,
+    seen: SeenSignatures,
+    // published but not yet finalized transactions and the slot they were seen in
+    pending: HashMap<String, u64>,
+    // notified transactions that could not be fetched yet, with the index of the address
+    // that matched them and the rounds they were retried for
+    retries: VecDeque<(String, usize, u32)>,
+}
+
+// TODO dont print to std out - use a logger
+impl LiveCrawler {
+    pub fn new(
+        addresses: Vec<String>,
+        rpc_url: String,
+        ws_url: String,
+        filters: Vec<Box<dyn TransactionFilter + Send>>,
+        sleep_duration: Option<Duration>,
+        commitment: CommitmentConfig,
+        encoding: UiTransactionEncoding,
+    ) -> (Self, LiveEventReceiver) {
+        let (publisher, event_recv) = crossbeam::channel::unbounded();
+        (
+            Self {
+                rpc_url,
+                ws_url,
+                addresses,
+                filters,
+                publisher,
+                sleep_duration,
+                encoding,
+                cache: None,
+                commitment,
+                seen: SeenSignatures::new(),
+                pending: HashMap::new(),
+                retries: VecDeque::new(),
+            },
+            event_recv,
+        )
+    }
+
+    // transactions are fetched below finalized, so the cache is only read
+    pub fn with_cache(mut self, cache: Option<Arc<TransactionCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn crawl(&mut self) {
+        loop {
+            let res = self.try_crawl();
+            if let Err(e) = res {
+                println!("live crawl err - {}", e);
+            }
+        }
+    }
+
+    fn try_crawl(&mut self) -> Result<(), Box<dyn Error>> {
+        let client = RpcClient::new(self.rpc_url.clone());
+
+        // keep the subscriptions alive for as long as we are reading from them
+        let mut subs = Vec::new();
+        let mut recvs = Vec::new();
+        for addr in &self.addresses {
+            let (sub, recv) = solana_client::pubsub_client::PubsubClient::logs_subscribe(
+                self.ws_url.clone().as_str(),
+                RpcTransactionLogsFilter::Mentions(vec![addr.clone()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(self.commitment),
+                },
+            )?;
+            subs.push(sub);
+            recvs.push(recv);
+        }
+        let (_slot_sub, slot_recv) =
+            solana_client::pubsub_client::PubsubClient::slot_subscribe(self.ws_url.as_str())?;
+
+        let mut last_root = 0;
+        let mut sel = crossbeam::channel::Select::new();
+        recvs.iter().for_each(|recv| {
+            sel.recv(recv);
+        });
+        let slot_index = sel.recv(&slot_recv);
+        loop {
+            let opr = sel.select();
+            let i = opr.index();
+            if i == slot_index {
+                let slot_info = opr.recv(&slot_recv)?;
+                if slot_info.root > last_root {
+                    last_root = slot_info.root;
+                    self.retry_fetches(&client)?;
+                    self.track_pending(&client, last_root)?;
+                }
+                continue;
+            }
+
+            let logs = opr.recv(&recvs[i])?;
+            let sig = logs.value.signature;
+            if !self.seen.insert(sig.as_str()) {
+                continue;
+            }
+
+            println!("live crawler - {}", sig);
+
+            // the logs subscription notifies a signature only once, so a transaction that
+            // is not confirmed yet or failed to fetch is tried again on the next roots
+            if !self.fetch_and_publish(&client, &sig, i)? {
+                self.retries.push_back((sig, i, 0));
+            }
+        }
+    }
+
+    // returns false if the transaction could not be fetched
+    fn fetch_and_publish(
+        &mut self,
+        client: &RpcClient,
+        sig: &str,
+        address_index: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        let parsed = Signature::from_str(sig)?;
+        // transactions can not be fetched below confirmed commitment
+        let tx = fetch_transaction(
+            client,
+            &parsed,
+            self.encoding,
+            Some(CommitmentConfig::confirmed()),
+            self.sleep_duration,
+            self.cache.as_deref(),
+        );
+        let tx = match tx {
+            Some(tx) => tx,
+            None => return Ok(false),
+        };
+        if !should_filter(&self.filters, &tx) {
+            self.pending.insert(sig.to_string(), tx.slot);
+            self.publisher
+                .send(LiveEvent::Confirmed(Box::new(CrawledTransaction {
+                    sig: sig.to_string(),
+                    matched: matched_addresses(
+                        &tx,
+                        &self.addresses,
+                        &self.addresses[address_index],
+                    ),
+                    commitment: Commitment::Confirmed,
+                    cursor: None,
+                    tx,
+                })))?;
+        }
+        Ok(true)
+    }
+
+    // tries every queued transaction once more, dropping those that never confirmed
+    fn retry_fetches(&mut self, client: &RpcClient) -> Result<(), Box<dyn Error>> {
+        for _ in 0..self.retries.len() {
+            let (sig, address_index, rounds) = match self.retries.pop_front() {
+                Some(retry) => retry,
+                None => break,
+            };
+            if self.fetch_and_publish(client, &sig, address_index)? {
+                continue;
+            }
+            if rounds + 1 >= MAX_FETCH_ROUNDS {
+                println!("live crawler dropping {} - could not be fetched", sig);
+                self.seen.remove(&sig);
+                continue;
+            }
+            self.retries.push_back((sig, address_index, rounds + 1));
+        }
+        Ok(())
+    }
+
+    // resolves pending transactions at or below the root against the cluster, anything
+    // unknown to the cluster was on an abandoned fork. transactions of later slots can not
+    // be finalized yet and are left for the next roots
+    fn track_pending(&mut self, client: &RpcClient, root: u64) -> Result<(), Box<dyn Error>> {
+        let sigs = self
+            .pending
+            .iter()
+            .filter(|(_, seen_slot)| **seen_slot <= root)
+            .map(|(sig, _)| sig.clone())
+            .collect::<Vec<String>>();
+        for batch in sigs.chunks(STATUS_BATCH_SIZE) {
+            let parsed = batch
+                .iter()
+                .map(|sig| Signature::from_str(sig))
+                .collect::<Result<Vec<Signature>, _>>()?;
+            // without history only recent transactions are found, older pending ones
+            // would be taken for rolled back
+            let statuses = client.get_signature_statuses_with_history(&parsed)?.value;
+
+            for (sig, status) in batch.iter().zip(statuses) {
+                let seen_slot = self.pending[sig];
+                match status {
+                    Some(status) if status.satisfies_commitment(CommitmentConfig::finalized()) => {
+                        self.pending.remove(sig);
+                        self.publisher.send(LiveEvent::Finalized {
+                            sig: sig.clone(),
+                            slot: status.slot,
+                        })?;
+                    }
+                    None => {
+                        self.pending.remove(sig);
+                        self.seen.remove(sig);
+                        self.publisher.send(LiveEvent::RolledBack {
+                            sig: sig.clone(),
+                            slot: seen_slot,
+                        })?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{rpc_server, signature};
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    #[test]
+    fn only_tracks_transactions_at_or_below_the_root() {
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let log = requested.clone();
+        let finalized = signature(1).to_string();
+        let (url, _stop) = rpc_server(move |method, params| match method {
+            "getSignatureStatuses" => {
+                let sigs = params[0].as_array().unwrap().clone();
+                log.lock().unwrap().extend(sigs.clone());
+                let statuses = sigs
+                    .iter()
+                    .map(|sig| match sig.as_str() == Some(finalized.as_str()) {
+                        true => json!({
+                            "slot": 5,
+                            "confirmations": null,
+                            "err": null,
+                            "status": {"Ok": null},
+                            "confirmationStatus": "finalized",
+                        }),
+                        false => Value::Null,
+                    })
+                    .collect::<Vec<Value>>();
+                json!({"context": {"slot": 10}, "value": statuses})
+            }
+            _ => Value::Null,
+        });
+
+        let (mut crawler, events) = LiveCrawler::new(
+            vec![],
+            url.clone(),
+            String::new(),
+            vec![],
+            None,
+            CommitmentConfig::confirmed(),
+            UiTransactionEncoding::Json,
+        );
+        crawler.pending.insert(signature(1).to_string(), 5);
+        crawler.pending.insert(signature(2).to_string(), 10);
+        crawler.pending.insert(signature(3).to_string(), 11);
+        crawler.track_pending(&RpcClient::new(url), 10).unwrap();
+
+        let mut requested = requested.lock().unwrap().clone();
+        requested.sort_by_key(|sig| sig.as_str().unwrap().to_string());
+        let mut expected = vec![
+            json!(signature(1).to_string()),
+            json!(signature(2).to_string()),
+        ];
+        expected.sort_by_key(|sig| sig.as_str().unwrap().to_string());
+        assert_eq!(requested, expected);
+
+        let mut events = events
+            .try_iter()
+            .map(|event| match event {
+                LiveEvent::Finalized { sig, slot } => ("finalized", sig, slot),
+                LiveEvent::RolledBack { sig, slot } => ("rolled back", sig, slot),
+                LiveEvent::Confirmed(tx) => ("confirmed", tx.sig, tx.tx.slot),
+            })
+            .collect::<Vec<(&str, String, u64)>>();
+        events.sort();
+        assert_eq!(
+            events,
+            vec![
+                ("finalized", signature(1).to_string(), 5),
+                ("rolled back", signature(2).to_string(), 10),
+            ]
+        );
+        assert_eq!(
+            crawler.pending.keys().collect::<Vec<&String>>(),
+            vec![&signature(3).to_string()]
+        );
+    }
+}
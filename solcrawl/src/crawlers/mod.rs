@@ -1,2 +1,196 @@
+use crate::cache::TransactionCache;
+use crate::filters::TransactionFilter;
+
+use crate::transaction_view::TransactionView;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+
+pub mod account_crawler;
+pub mod block_crawler;
 pub mod historical_crawler;
+pub mod live_crawler;
+pub mod replay_crawler;
 pub mod websocket_crawler;
+
+const FETCH_RETRIES: usize = 5;
+
+// number of recently published signatures remembered to drop duplicates
+const SEEN_SIGNATURES_CAPACITY: usize = 10_000;
+
+// Commitment is how final a transaction was when it was fetched, transactions are only
+// fetchable from confirmed on and anything below finalized may still be rolled back
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    Confirmed,
+    #[default]
+    Finalized,
+}
+
+impl Commitment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Commitment::Confirmed => "confirmed",
+            Commitment::Finalized => "finalized",
+        }
+    }
+}
+
+impl fmt::Display for Commitment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Commitment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "confirmed" => Ok(Commitment::Confirmed),
+            "finalized" => Ok(Commitment::Finalized),
+            _ => Err(format!("unknown commitment {}", s)),
+        }
+    }
+}
+
+// CrawledTransaction is what every transaction crawler publishes on its channel
+#[derive(Debug, Serialize)]
+pub struct CrawledTransaction {
+    pub sig: String,
+    // watched addresses that appear in the transaction
+    pub matched: Vec<String>,
+    pub commitment: Commitment,
+    // the watched address whose listing the transaction was paged from, set by crawlers
+    // that keep a cursor per address so the pipeline checkpoints each address on its own
+    pub cursor: Option<String>,
+    pub tx: EncodedConfirmedTransactionWithStatusMeta,
+}
+
+pub type TransactionSender = crossbeam::channel::Sender<CrawledTransaction>;
+pub type TransactionReceiver = crossbeam::channel::Receiver<CrawledTransaction>;
+
+// fetches a transaction in the given encoding, retrying a few times since rpc nodes
+// often do not have a transaction available right after its signature shows up.
+// the cache is read first, and finalized transactions are put in it
+pub fn fetch_transaction(
+    client: &RpcClient,
+    sig: &Signature,
+    encoding: UiTransactionEncoding,
+    commitment: Option<CommitmentConfig>,
+    sleep_duration: Option<Duration>,
+    cache: Option<&TransactionCache>,
+) -> Option<EncodedConfirmedTransactionWithStatusMeta> {
+    let sig_str = sig.to_string();
+    if let Some(tx) = cache.and_then(|cache| cache.get(&sig_str, encoding)) {
+        return Some(tx);
+    }
+
+    for _ in 0..FETCH_RETRIES {
+        if let Some(dur) = sleep_duration {
+            sleep(dur);
+        }
+
+        let res = client.get_transaction_with_config(
+            sig,
+            RpcTransactionConfig {
+                encoding: Some(encoding),
+                commitment,
+                max_supported_transaction_version: Some(0),
+            },
+        );
+        if let Ok(res) = res {
+            // the client defaults to finalized, less final transactions may still change
+            if commitment.is_none_or(|c| c.is_finalized()) {
+                if let Some(cache) = cache {
+                    if let Err(e) = cache.put(&sig_str, encoding, &res) {
+                        println!("tx cache err - {}", e);
+                    }
+                }
+            }
+            return Some(res);
+        }
+    }
+
+    None
+}
+
+// returns true if any of the filters wants the transaction filtered out
+pub(crate) fn should_filter<F: TransactionFilter + ?Sized>(
+    filters: &[Box<F>],
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> bool {
+    filters.iter().any(|filter| filter.filter(tx))
+}
+
+// returns the watched addresses referenced by the transaction, falling back to the
+// address the signature was found through if the transaction could not be decoded
+pub(crate) fn matched_addresses(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+    watched: &[String],
+    source: &str,
+) -> Vec<String> {
+    let matched = match TransactionView::new(tx) {
+        Ok(view) => watched_addresses(&view, watched),
+        Err(_) => Vec::new(),
+    };
+    if matched.is_empty() {
+        return vec![source.to_string()];
+    }
+    matched
+}
+
+// returns the watched addresses among the transaction's account keys
+pub(crate) fn watched_addresses(view: &TransactionView, watched: &[String]) -> Vec<String> {
+    watched
+        .iter()
+        .filter(|addr| view.account_keys().contains(addr))
+        .cloned()
+        .collect()
+}
+
+// SeenSignatures remembers the most recent signatures so transactions found through
+// more than one watched address are only published once
+pub(crate) struct SeenSignatures {
+    set: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenSignatures {
+    pub(crate) fn new() -> Self {
+        Self {
+            set: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    // returns false if the signature was already seen
+    pub(crate) fn insert(&mut self, sig: &str) -> bool {
+        if !self.set.insert(sig.to_string()) {
+            return false;
+        }
+        self.order.push_back(sig.to_string());
+        if self.order.len() > SEEN_SIGNATURES_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    // forgets a signature so it can be published again
+    pub(crate) fn remove(&mut self, sig: &str) {
+        if self.set.remove(sig) {
+            self.order.retain(|s| s != sig);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::archive::{ArchiveFormat, INDEX_FILE};
+use crate::crawlers::{
+    should_filter, watched_addresses, Commitment, CrawledTransaction, TransactionReceiver,
+    TransactionSender,
+};
+use crate::filters::TransactionFilter;
+use crate::transaction_view::TransactionView;
+
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// FileReplayCrawler publishes transactions from local archives instead of the network.
+// An archive is a JSONL file of EncodedConfirmedTransactionWithStatusMeta or a CBOR segment
+// written by ArchiveWriter, optionally zstd compressed. Directories are replayed file by
+// file in name order.
+pub struct FileReplayCrawler {
+    paths: Vec<PathBuf>,
+    addresses: Vec<String>,
+    filters: Vec<Box<dyn TransactionFilter + Send>>,
+    publisher: TransactionSender,
+    // None replays as fast as possible, Some(1.0) with the original gaps between block times
+    speed: Option<f64>,
+}
+
+// TODO dont print to std out - use a logger
+impl FileReplayCrawler {
+    pub fn new(
+        paths: Vec<PathBuf>,
+        addresses: Vec<String>,
+        filters: Vec<Box<dyn TransactionFilter + Send>>,
+        speed: Option<f64>,
+    ) -> Result<(Self, TransactionReceiver), Box<dyn Error>> {
+        if let Some(speed) = speed {
+            if speed <= 0.0 {
+                return Err("replay speed must be positive".into());
+            }
+        }
+
+        let (publisher, tx_recv) = crossbeam::channel::unbounded();
+        Ok((
+            Self {
+                paths,
+                addresses,
+                filters,
+                publisher,
+                speed,
+            },
+            tx_recv,
+        ))
+    }
+
+    // returns once every archive has been replayed
+    pub fn crawl(&self) {
+        let mut last_block_time = None;
+        for path in self.archive_files() {
+            let res = self.replay_file(&path, &mut last_block_time);
+            if let Err(e) = res {
+                println!("replay crawl err - {} - {}", path.display(), e);
+            }
+        }
+    }
+
+    fn archive_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for path in &self.paths {
+            if !path.is_dir() {
+                files.push(path.clone());
+                continue;
+            }
+            match std::fs::read_dir(path) {
+                Ok(entries) => {
+                    let mut entries = entries
+                        .filter_map(|e| e.ok().map(|e| e.path()))
+                        .filter(|p| p.is_file() && !p.ends_with(INDEX_FILE))
+                        .collect::<Vec<PathBuf>>();
+                    entries.sort();
+                    files.extend(entries);
+                }
+                Err(e) => println!("replay crawl err - {} - {}", path.display(), e),
+            }
+        }
+        files
+    }
+
+    fn replay_file(
+        &self,
+        path: &Path,
+        last_block_time: &mut Option<i64>,
+    ) -> Result<(), Box<dyn Error>> {
+        let reader = open_archive(path)?;
+        if ArchiveFormat::from_path(path) == Some(ArchiveFormat::Cbor) {
+            let txs = serde_cbor::Deserializer::from_reader(reader)
+                .into_iter::<EncodedConfirmedTransactionWithStatusMeta>();
+            for tx in txs {
+                let tx = tx?;
+                self.wait(tx.block_time, last_block_time);
+                self.publish(tx)?;
+            }
+            return Ok(());
+        }
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let tx = match serde_json::from_str::<EncodedConfirmedTransactionWithStatusMeta>(&line)
+            {
+                Ok(tx) => tx,
+                Err(e) => {
+                    println!("replay crawler - {}:{} - {}", path.display(), i + 1, e);
+                    continue;
+                }
+            };
+            self.wait(tx.block_time, last_block_time);
+            self.publish(tx)?;
+        }
+        Ok(())
+    }
+
+    fn wait(&self, block_time: Option<i64>, last_block_time: &mut Option<i64>) {
+        let (speed, block_time) = match (self.speed, block_time) {
+            (Some(speed), Some(block_time)) => (speed, block_time),
+            _ => return,
+        };
+        if let Some(last) = *last_block_time {
+            if block_time > last {
+                sleep(Duration::from_secs_f64((block_time - last) as f64 / speed));
+            }
+        }
+        *last_block_time = Some(block_time);
+    }
+
+    // transactions that can not be decoded are skipped, only a closed channel ends the replay
+    fn publish(&self, tx: EncodedConfirmedTransactionWithStatusMeta) -> Result<(), Box<dyn Error>> {
+        let view = match TransactionView::new(&tx) {
+            Ok(view) => view,
+            Err(e) => {
+                println!("replay crawler - skipping tx - {}", e);
+                return Ok(());
+            }
+        };
+        let sig = match view.signature() {
+            Some(sig) => sig.to_string(),
+            None => {
+                println!("replay crawler - skipping tx without signature");
+                return Ok(());
+            }
+        };
+        let matched = watched_addresses(&view, &self.addresses);
+        if !self.addresses.is_empty() && matched.is_empty() {
+            return Ok(());
+        }
+
+        if !should_filter(&self.filters, &tx) {
+            self.publisher.send(CrawledTransaction {
+                sig,
+                matched,
+                // archives do not record a commitment, replays are treated as final
+                commitment: Commitment::Finalized,
+                cursor: None,
+                tx,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+// opens an archive file, transparently decompressing it if it starts with the zstd magic
+pub(crate) fn open_archive(path: &Path) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let read = file.read(&mut magic)?;
+    let file = File::open(path)?;
+
+    if read == magic.len() && magic == ZSTD_MAGIC {
+        return Ok(Box::new(BufReader::new(zstd::stream::read::Decoder::new(
+            file,
+        )?)));
+    }
+    Ok(Box::new(BufReader::new(file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchiveWriter;
+    use crate::test_util::{key, signature, transaction};
+    use solana_transaction_status::UiTransactionEncoding;
+
+    fn replay(paths: Vec<PathBuf>, addresses: Vec<String>) -> Vec<(String, u64)> {
+        let (crawler, recv) = FileReplayCrawler::new(paths, addresses, vec![], None).unwrap();
+        crawler.crawl();
+        drop(crawler);
+        recv.iter().map(|tx| (tx.sig, tx.tx.slot)).collect()
+    }
+
+    #[test]
+    fn replays_archives_in_the_order_they_were_written() {
+        let expected = (1..=5)
+            .map(|n| (signature(n).to_string(), 100 + n as u64))
+            .collect::<Vec<(String, u64)>>();
+        for (format, compress) in [
+            (ArchiveFormat::Jsonl, false),
+            (ArchiveFormat::Jsonl, true),
+            (ArchiveFormat::Cbor, false),
+            (ArchiveFormat::Cbor, true),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            // small segments so the replay has to go through them in name order
+            let mut writer =
+                ArchiveWriter::new(dir.path().to_path_buf(), format, compress, 1).unwrap();
+            for (sig, slot) in &expected {
+                let n = *slot as u8 - 100;
+                let tx = transaction(&signature(n), *slot, UiTransactionEncoding::Base64);
+                writer.write(sig, &tx).unwrap();
+            }
+            writer.close().unwrap();
+
+            assert_eq!(replay(vec![dir.path().to_path_buf()], vec![]), expected);
+        }
+    }
+
+    #[test]
+    fn skips_bad_lines_and_unwatched_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("txs.jsonl");
+        let tx = |n: u8| {
+            serde_json::to_string(&transaction(
+                &signature(n),
+                n as u64,
+                UiTransactionEncoding::Json,
+            ))
+            .unwrap()
+        };
+        std::fs::write(&path, format!("{}\nnot json\n\n{}\n", tx(1), tx(2))).unwrap();
+
+        assert_eq!(
+            replay(vec![path.clone()], vec![key(3).to_string()]),
+            vec![(signature(1).to_string(), 1), (signature(2).to_string(), 2)]
+        );
+        assert!(replay(vec![path], vec![key(8).to_string()]).is_empty());
+    }
+}
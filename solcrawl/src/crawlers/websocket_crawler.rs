@@ -1,22 +1,32 @@
+use crate::cache::TransactionCache;
+use crate::crawlers::{
+    fetch_transaction, matched_addresses, should_filter, Commitment, CrawledTransaction,
+    SeenSignatures, TransactionReceiver, TransactionSender,
+};
 use crate::filters::TransactionFilter;
 
 use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
 
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::signature::Signature;
-use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use solana_transaction_status::UiTransactionEncoding;
 use std::error::Error;
+use std::ops::Index;
 use std::str::FromStr;
-use std::thread::sleep;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub struct WebSocketCrawler {
     rpc_url: String,
     ws_url: String,
-    program_addr: String,
+    addresses: Vec<String>,
     filters: Vec<Box<dyn TransactionFilter>>,
-    publisher: crossbeam::channel::Sender<(String, EncodedConfirmedTransactionWithStatusMeta)>,
+    publisher: TransactionSender,
     sleep_duration: Option<Duration>,
+    encoding: UiTransactionEncoding,
+    // fetched transactions are read from and put in the cache when set
+    cache: Option<Arc<TransactionCache>>,
+    seen: SeenSignatures,
 }
 
 unsafe impl Send for WebSocketCrawler {}
@@ -24,30 +34,36 @@ unsafe impl Send for WebSocketCrawler {}
 // TODO dont print to std out - use a logger
 impl WebSocketCrawler {
     pub fn new(
-        program_addr: String,
+        addresses: Vec<String>,
         rpc_url: String,
         ws_url: String,
         filters: Vec<Box<dyn TransactionFilter>>,
         sleep_duration: Option<Duration>,
-    ) -> (
-        Self,
-        crossbeam::channel::Receiver<(String, EncodedConfirmedTransactionWithStatusMeta)>,
-    ) {
+        encoding: UiTransactionEncoding,
+    ) -> (Self, TransactionReceiver) {
         let (publisher, tx_recv) = crossbeam::channel::unbounded();
         (
             Self {
                 rpc_url,
                 ws_url,
-                program_addr,
+                addresses,
                 filters,
                 publisher,
                 sleep_duration,
+                encoding,
+                cache: None,
+                seen: SeenSignatures::new(),
             },
             tx_recv,
         )
     }
 
-    pub fn crawl(&self) {
+    pub fn with_cache(mut self, cache: Option<Arc<TransactionCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn crawl(&mut self) {
         loop {
             let res = self.try_crawl();
             if let Err(e) = res {
@@ -55,47 +71,57 @@ impl WebSocketCrawler {
             }
         }
     }
-    fn try_crawl(&self) -> Result<(), Box<dyn Error>> {
+    fn try_crawl(&mut self) -> Result<(), Box<dyn Error>> {
         let client = solana_client::rpc_client::RpcClient::new(self.rpc_url.clone());
-        let (_sub, recv) = solana_client::pubsub_client::PubsubClient::logs_subscribe(
-            self.ws_url.clone().as_str(),
-            RpcTransactionLogsFilter::Mentions(vec![self.program_addr.clone()]),
-            RpcTransactionLogsConfig {
-                commitment: Some(CommitmentConfig::finalized()),
-            },
-        )?;
 
+        // logs subscriptions only accept a single mentioned address, so subscribe once per address
+        // and keep the subscriptions alive for as long as we are reading from them
+        let mut subs = Vec::new();
+        let mut recvs = Vec::new();
+        for addr in &self.addresses {
+            let (sub, recv) = solana_client::pubsub_client::PubsubClient::logs_subscribe(
+                self.ws_url.clone().as_str(),
+                RpcTransactionLogsFilter::Mentions(vec![addr.clone()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig::finalized()),
+                },
+            )?;
+            subs.push(sub);
+            recvs.push(recv);
+        }
+
+        let mut sel = crossbeam::channel::Select::new();
+        recvs.iter().for_each(|recv| {
+            sel.recv(recv);
+        });
         loop {
-            let sig = recv.recv()?.value.signature;
+            let opr = sel.select();
+            let i = opr.index();
+            let sig = opr.recv(recvs.index(i))?.value.signature;
+            if !self.seen.insert(sig.as_str()) {
+                continue;
+            }
             let sig = Signature::from_str(&sig)?;
 
             println!("ws crawler - {}", sig);
 
-            let mut tx: Option<EncodedConfirmedTransactionWithStatusMeta> = None;
-            for _ in 0..5 {
-                if let Some(dur) = self.sleep_duration {
-                    sleep(dur);
-                }
-
-                let res = client
-                    .get_transaction(&sig, solana_transaction_status::UiTransactionEncoding::Json);
-                if let Ok(res) = res {
-                    tx = Some(res);
-                    break;
-                }
-            }
-
+            let tx = fetch_transaction(
+                &client,
+                &sig,
+                self.encoding,
+                None,
+                self.sleep_duration,
+                self.cache.as_deref(),
+            );
             if let Some(tx) = tx {
-                let mut should_filter = false;
-                for filter in &self.filters {
-                    if filter.filter(&tx) {
-                        should_filter = true;
-                        break;
-                    }
-                }
-
-                if !should_filter {
-                    self.publisher.send((sig.to_string(), tx))?;
+                if !should_filter(&self.filters, &tx) {
+                    self.publisher.send(CrawledTransaction {
+                        sig: sig.to_string(),
+                        matched: matched_addresses(&tx, &self.addresses, &self.addresses[i]),
+                        commitment: Commitment::Finalized,
+                        cursor: None,
+                        tx,
+                    })?;
                 }
             }
         }
@@ -0,0 +1,156 @@
+use crate::transaction_view::{InstructionView, TransactionView};
+use solana_program::hash::hash;
+
+pub const JUPITER_V2_PROGRAM: &str = "JUP2jxvXaqu7NQY1GmNF4m1vodw12LVXYxbFL2uJvfo";
+pub const JUPITER_V3_PROGRAM: &str = "JUP3c2Uh3WA4Ng34tw6kPd2G4C5BB21Xo36Je1s32Ph";
+pub const JUPITER_V4_PROGRAM: &str = "JUP4Fb2cqiRUcaTHdrPC8h2gNsA2ETXiPDD33WcGuJB";
+
+// the jupiter major version of a program id
+pub fn program_version(program_id: &str) -> Option<u8> {
+    match program_id {
+        JUPITER_V2_PROGRAM => Some(2),
+        JUPITER_V3_PROGRAM => Some(3),
+        JUPITER_V4_PROGRAM => Some(4),
+        _ => None,
+    }
+}
+
+// route(swap_leg, in_amount: u64, quoted_out_amount: u64, slippage_bps: u16, platform_fee_bps: u8)
+// swap_leg is variable length, so the fixed size arguments are read from the end of the data
+const ROUTE_TRAILING_ARGS_LEN: usize = 8 + 8 + 2 + 1;
+
+// JupiterQuote is the quote a jupiter route instruction was submitted with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JupiterQuote {
+    pub in_amount: u64,
+    pub quoted_out_amount: u64,
+    pub slippage_bps: u16,
+    pub platform_fee_bps: u8,
+}
+
+impl JupiterQuote {
+    pub fn minimum_out_amount(&self) -> u64 {
+        (self.quoted_out_amount as u128 * (10_000 - self.slippage_bps.min(10_000)) as u128 / 10_000)
+            as u64
+    }
+
+    // shortfall of the route output versus the quote, in basis points. received is what
+    // reached the trader, the platform fee was taken from the route output before that
+    // and token 2022 transfer_fees on the way, so both are added back first
+    pub fn price_impact_bps(&self, received: u64, transfer_fees: u64) -> Option<f64> {
+        if self.quoted_out_amount == 0 {
+            return None;
+        }
+        let after_platform_fee = 1.0 - self.platform_fee_bps as f64 / 10_000.0;
+        let out_amount = (received + transfer_fees) as f64 / after_platform_fee;
+        let quoted = self.quoted_out_amount as f64;
+        Some((quoted - out_amount) / quoted * 10_000.0)
+    }
+}
+
+// finds and decodes the first jupiter route instruction in the transaction. Only the v4
+// program is supported, v2 and v3 have no route instruction carrying the quote, each amm
+// instruction only gets a minimum out amount, so their swaps have no quote or price impact
+pub fn decode_quote(view: &TransactionView) -> Option<JupiterQuote> {
+    let inner = view
+        .inner_instructions()
+        .iter()
+        .flat_map(|i| i.instructions.iter());
+    view.instructions()
+        .iter()
+        .chain(inner)
+        .find_map(decode_route_instruction)
+}
+
+fn decode_route_instruction(ix: &InstructionView) -> Option<JupiterQuote> {
+    if ix.program_id != JUPITER_V4_PROGRAM {
+        return None;
+    }
+    let data = ix.data.as_ref()?;
+    if data.len() < 8 + ROUTE_TRAILING_ARGS_LEN || data[..8] != anchor_discriminator("route") {
+        return None;
+    }
+
+    let args = &data[data.len() - ROUTE_TRAILING_ARGS_LEN..];
+    Some(JupiterQuote {
+        in_amount: u64::from_le_bytes(args[0..8].try_into().ok()?),
+        quoted_out_amount: u64::from_le_bytes(args[8..16].try_into().ok()?),
+        slippage_bps: u16::from_le_bytes(args[16..18].try_into().ok()?),
+        platform_fee_bps: args[18],
+    })
+}
+
+fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a v4 route instruction, discriminator, borsh swap_leg then in_amount 1 usdc,
+    // quoted_out_amount 0.999 usdt, slippage_bps 50 and platform_fee_bps 20
+    fn route_data() -> Vec<u8> {
+        let mut data = anchor_discriminator("route").to_vec();
+        // a chain of two swap legs, its length varies with the route
+        data.extend_from_slice(&[0, 2, 0, 0, 0, 2, 5, 2, 17, 1]);
+        data.extend_from_slice(&1_000_000u64.to_le_bytes());
+        data.extend_from_slice(&999_000u64.to_le_bytes());
+        data.extend_from_slice(&50u16.to_le_bytes());
+        data.push(20);
+        data
+    }
+
+    fn instruction(program_id: &str, data: Vec<u8>) -> InstructionView {
+        InstructionView {
+            program_id: program_id.to_string(),
+            accounts: vec![],
+            data: Some(data),
+            parsed: None,
+            stack_height: None,
+        }
+    }
+
+    #[test]
+    fn decodes_a_v4_route() {
+        let quote = decode_route_instruction(&instruction(JUPITER_V4_PROGRAM, route_data()));
+        let quote = quote.unwrap();
+        assert_eq!(
+            quote,
+            JupiterQuote {
+                in_amount: 1_000_000,
+                quoted_out_amount: 999_000,
+                slippage_bps: 50,
+                platform_fee_bps: 20,
+            }
+        );
+        assert_eq!(quote.minimum_out_amount(), 994_005);
+    }
+
+    #[test]
+    fn ignores_other_programs_and_instructions() {
+        assert!(decode_route_instruction(&instruction(JUPITER_V2_PROGRAM, route_data())).is_none());
+        let mut data = route_data();
+        data[..8].copy_from_slice(&anchor_discriminator("set_token_ledger"));
+        assert!(decode_route_instruction(&instruction(JUPITER_V4_PROGRAM, data)).is_none());
+        assert!(decode_route_instruction(&instruction(JUPITER_V4_PROGRAM, vec![1, 2])).is_none());
+    }
+
+    #[test]
+    fn price_impact_adds_back_fees() {
+        let quote = JupiterQuote {
+            in_amount: 1_000_000,
+            quoted_out_amount: 1_000_000,
+            slippage_bps: 50,
+            platform_fee_bps: 20,
+        };
+        // the route gave exactly the quote, 0.2% went to the platform and 1000 to transfer fees
+        let impact = quote.price_impact_bps(997_000, 1000).unwrap();
+        assert!(impact.abs() < 1e-9, "{}", impact);
+        // 1% less than quoted before fees
+        let impact = quote.price_impact_bps(987_020, 1000).unwrap();
+        assert!((impact - 100.0).abs() < 1e-6, "{}", impact);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::extractors::jupiter_instruction::program_version;
+use crate::tokens::{token_transfer, TokenTransfer, TOKEN_2022_PROGRAM, TOKEN_PROGRAM};
+use crate::transaction_view::{InstructionView, TransactionView};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+
+const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+const ASSOCIATED_TOKEN_PROGRAM: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+
+// SwapLeg is a single amm swap of a jupiter route, a direct swap has one leg
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapLeg {
+    pub index: u8,
+    pub amm_program: String,
+    pub input_token: String,
+    pub output_token: String,
+    pub input_amount: u64,
+    pub output_amount: u64,
+}
+
+// JupiterRoute is the first top level jupiter instruction of a transaction and its legs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JupiterRoute {
+    pub program_id: String,
+    pub version: u8,
+    pub legs: Vec<SwapLeg>,
+}
+
+// legs are the amm programs invoked by jupiter, each paired with the first and last token
+// transfer it made. Amms transfer the input into their vault before paying out, so the
+// first transfer is the leg input and the last one the leg output.
+pub fn decode_route(view: &TransactionView) -> Result<Option<JupiterRoute>, Box<dyn Error>> {
+    let (index, program_id, version) = match view
+        .instructions()
+        .iter()
+        .enumerate()
+        .find_map(|(i, ix)| program_version(&ix.program_id).map(|v| (i, &ix.program_id, v)))
+    {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    let mut mints = HashMap::new();
+    for balance in view
+        .pre_token_balances()?
+        .into_iter()
+        .chain(view.post_token_balances()?)
+    {
+        mints.insert(balance.account, balance.mint);
+    }
+
+    let inner = view
+        .inner_instructions()
+        .iter()
+        .filter(|i| i.index as usize == index)
+        .flat_map(|i| i.instructions.iter());
+
+    let mut hops: Vec<(&InstructionView, Vec<TokenTransfer>)> = Vec::new();
+    for ix in inner {
+        if is_amm_instruction(ix) {
+            hops.push((ix, Vec::new()));
+            continue;
+        }
+        if let Some((hop, transfers)) = hops.last_mut() {
+            let nested = match (hop.stack_height, ix.stack_height) {
+                (Some(hop_height), Some(height)) => height > hop_height,
+                _ => true,
+            };
+            if nested {
+                if let Some(transfer) = token_transfer(ix)? {
+                    transfers.push(transfer);
+                }
+            }
+        }
+    }
+
+    let mut legs = Vec::new();
+    for (hop, transfers) in hops {
+        let (input, output) = match (transfers.first(), transfers.last()) {
+            (Some(input), Some(output)) if transfers.len() >= 2 => (input, output),
+            _ => continue,
+        };
+        let mint = |transfer: &TokenTransfer| {
+            transfer
+                .mint
+                .clone()
+                .or_else(|| mints.get(&transfer.source).cloned())
+                .or_else(|| mints.get(&transfer.destination).cloned())
+                .ok_or(format!(
+                    "unknown mint for token account {}",
+                    transfer.source
+                ))
+        };
+        legs.push(SwapLeg {
+            index: legs.len() as u8,
+            amm_program: hop.program_id.clone(),
+            input_token: mint(input)?,
+            output_token: mint(output)?,
+            input_amount: input.amount,
+            output_amount: output.amount,
+        });
+    }
+
+    Ok(Some(JupiterRoute {
+        program_id: program_id.clone(),
+        version,
+        legs,
+    }))
+}
+
+// an instruction jupiter invoked directly that is not token account bookkeeping
+fn is_amm_instruction(ix: &InstructionView) -> bool {
+    if matches!(ix.stack_height, Some(height) if height != 2) {
+        return false;
+    }
+    ![
+        TOKEN_PROGRAM,
+        TOKEN_2022_PROGRAM,
+        SYSTEM_PROGRAM,
+        ASSOCIATED_TOKEN_PROGRAM,
+    ]
+    .contains(&ix.program_id.as_str())
+        && program_version(&ix.program_id).is_none()
+}
@@ -0,0 +1,239 @@
+use crate::crawlers::{Commitment, CrawledTransaction};
+use crate::extractors::jupiter_instruction::decode_quote;
+use crate::extractors::jupiter_route::{decode_route, SwapLeg};
+use crate::extractors::TransactionExtractor;
+use crate::tokens::mint_registry::{ui_amount, MintRegistry};
+use crate::tokens::{token_program_or_default, transfer_fees, TOKEN_2022_PROGRAM, TOKEN_PROGRAM};
+use crate::transaction_view::{TokenBalance, TransactionView};
+use serde::{Deserialize, Serialize};
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::error::Error;
+use std::ops::Index;
+use std::sync::Arc;
+
+// bumped whenever a change to the extraction stores different swaps for the same
+// transactions, so stored swaps can be told apart from what extraction gives now
+pub const EXTRACTOR_VERSION: u16 = 1;
+
+// SwapEvent is a two token swap made by the signer of a transaction.
+// Amounts are balance deltas of the signers token accounts, so for token 2022 mints with
+// a transfer fee the output amount is what the signer actually received after fees.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SwapEvent {
+    pub sig: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub trader: String,
+    // transaction fee in lamports
+    pub fee: u64,
+    pub success: bool,
+    pub input_token: String,
+    pub output_token: String,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub input_token_program: String,
+    pub output_token_program: String,
+    pub input_transfer_fee: u64,
+    pub output_transfer_fee: u64,
+    pub input_decimals: u8,
+    pub output_decimals: u8,
+    // amounts scaled by the mint decimals
+    pub input_ui_amount: f64,
+    pub output_ui_amount: f64,
+    pub input_symbol: Option<String>,
+    pub output_symbol: Option<String>,
+    // output tokens received per input token and the inverse, both decimal normalized
+    pub price: f64,
+    pub inverse_price: f64,
+    // price - 1 when both tokens are stable tokens pegged to the same asset
+    pub peg_deviation: Option<f64>,
+    // only known when the jupiter instruction could be decoded, which needs jupiter v4
+    pub quoted_out_amount: Option<u64>,
+    pub minimum_out_amount: Option<u64>,
+    // shortfall of the output amount versus the quoted output, in basis points, with the
+    // platform and transfer fees added back. Not the slippage tolerance of the
+    // instruction, that is what minimum_out_amount allows
+    pub price_impact_bps: Option<f64>,
+    // jupiter major version of the program the swap was routed through
+    pub program_version: Option<u8>,
+    // amm swaps of the route in order, empty when the route could not be decoded
+    pub legs: Vec<SwapLeg>,
+    // confirmed swaps may still be rolled back with their slot
+    #[serde(default)]
+    pub commitment: Commitment,
+}
+
+#[derive(Clone)]
+pub struct JupiterSwapExtractor {
+    pub token_programs: Vec<String>,
+    pub mint_registry: Option<Arc<MintRegistry>>,
+    // mints pegged to the same asset, swaps between two of them get a peg deviation
+    pub stable_tokens: Vec<String>,
+}
+
+impl Default for JupiterSwapExtractor {
+    fn default() -> Self {
+        Self {
+            token_programs: vec![TOKEN_PROGRAM.to_string(), TOKEN_2022_PROGRAM.to_string()],
+            mint_registry: None,
+            stable_tokens: Vec::new(),
+        }
+    }
+}
+
+impl TransactionExtractor for JupiterSwapExtractor {
+    type Output = SwapEvent;
+
+    fn extract(
+        &self,
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<SwapEvent, Box<dyn Error>> {
+        let view = TransactionView::new(tx)?;
+        self.extract_view(&view)
+    }
+
+    fn extract_crawled(&self, tx: &CrawledTransaction) -> Result<SwapEvent, Box<dyn Error>> {
+        let mut swap = self.extract(&tx.tx)?;
+        swap.commitment = tx.commitment;
+        Ok(swap)
+    }
+}
+
+impl JupiterSwapExtractor {
+    pub fn extract_view(&self, view: &TransactionView) -> Result<SwapEvent, Box<dyn Error>> {
+        let sig = view.signature().ok_or("could not get tx signature")?;
+        let tx_creator = view.signer().ok_or("could not get tx creator")?;
+
+        let mut all_pre = view
+            .pre_token_balances()?
+            .into_iter()
+            .filter(|t| self.is_trader_balance(t, tx_creator))
+            .collect::<Vec<TokenBalance>>();
+        all_pre.sort_by(|t1, t2| Ord::cmp(&t1.mint, &t2.mint));
+
+        let mut all_post = view
+            .post_token_balances()?
+            .into_iter()
+            .filter(|t| self.is_trader_balance(t, tx_creator))
+            .collect::<Vec<TokenBalance>>();
+        all_post.sort_by(|t1, t2| Ord::cmp(&t1.mint, &t2.mint));
+
+        if all_pre.len() != all_post.len() {
+            return Err("unexpected token balances length".into());
+        }
+
+        let mut pre: Vec<TokenBalance> = Vec::new();
+        let mut post: Vec<TokenBalance> = Vec::new();
+        for i in 0..all_pre.len() {
+            let diff = all_pre.index(i).amount.abs_diff(all_post.index(i).amount);
+            if diff > 0 {
+                pre.push(all_pre.index(i).clone());
+                post.push(all_post.index(i).clone());
+            }
+        }
+
+        if pre.len() != 2 {
+            return Err("unexpected token balances".into());
+        }
+
+        let mut input_index: usize = 1;
+        let mut output_index: usize = 0;
+        if pre.index(0).amount > post.index(0).amount {
+            input_index = 0;
+            output_index = 1;
+        }
+
+        let input = pre.index(input_index);
+        let output = pre.index(output_index);
+        let input_amount = input
+            .amount
+            .checked_sub(post.index(input_index).amount)
+            .ok_or("unexpected input amount")?;
+        let output_amount = post
+            .index(output_index)
+            .amount
+            .checked_sub(output.amount)
+            .ok_or("unexpected output amount")?;
+
+        if input_amount == 0 || output_amount == 0 {
+            return Err("unexpected token amounts".into());
+        }
+
+        let mut input_transfer_fee = 0;
+        let mut output_transfer_fee = 0;
+        for fee in transfer_fees(view)? {
+            if fee.source == input.account && fee.mint == input.mint {
+                input_transfer_fee += fee.fee;
+            }
+            if fee.destination == output.account && fee.mint == output.mint {
+                output_transfer_fee += fee.fee;
+            }
+        }
+
+        let mut input_symbol = None;
+        let mut output_symbol = None;
+        if let Some(registry) = &self.mint_registry {
+            registry.record_balances(&all_post);
+            input_symbol = registry.get(&input.mint)?.symbol;
+            output_symbol = registry.get(&output.mint)?.symbol;
+        }
+
+        let input_ui_amount = ui_amount(input_amount, input.decimals);
+        let output_ui_amount = ui_amount(output_amount, output.decimals);
+        let price = output_ui_amount / input_ui_amount;
+        let mut peg_deviation = None;
+        if self.stable_tokens.contains(&input.mint) && self.stable_tokens.contains(&output.mint) {
+            peg_deviation = Some(price - 1.0);
+        }
+
+        let quote = decode_quote(view);
+        let price_impact_bps = quote
+            .as_ref()
+            .and_then(|q| q.price_impact_bps(output_amount, output_transfer_fee));
+
+        // a route that can not be decoded still leaves a usable swap
+        let route = decode_route(view).unwrap_or_else(|e| {
+            println!("jupiter route err - {} - {}", sig, e);
+            None
+        });
+
+        Ok(SwapEvent {
+            sig: sig.to_string(),
+            slot: view.slot(),
+            block_time: view.block_time(),
+            trader: tx_creator.to_string(),
+            fee: view.fee().unwrap_or_default(),
+            success: view.is_success(),
+            input_token: input.mint.clone(),
+            output_token: output.mint.clone(),
+            input_amount,
+            output_amount,
+            input_token_program: token_program_or_default(&input.program_id),
+            output_token_program: token_program_or_default(&output.program_id),
+            input_transfer_fee,
+            output_transfer_fee,
+            input_decimals: input.decimals,
+            output_decimals: output.decimals,
+            input_ui_amount,
+            output_ui_amount,
+            input_symbol,
+            output_symbol,
+            price,
+            inverse_price: input_ui_amount / output_ui_amount,
+            peg_deviation,
+            quoted_out_amount: quote.as_ref().map(|q| q.quoted_out_amount),
+            minimum_out_amount: quote.as_ref().map(|q| q.minimum_out_amount()),
+            price_impact_bps,
+            program_version: route.as_ref().map(|r| r.version),
+            legs: route.map(|r| r.legs).unwrap_or_default(),
+            commitment: Commitment::Finalized,
+        })
+    }
+
+    fn is_trader_balance(&self, balance: &TokenBalance, trader: &str) -> bool {
+        balance.owner.as_deref() == Some(trader)
+            && self
+                .token_programs
+                .contains(&token_program_or_default(&balance.program_id))
+    }
+}
@@ -0,0 +1,22 @@
+use crate::crawlers::CrawledTransaction;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::error::Error;
+
+pub mod jupiter_instruction;
+pub mod jupiter_route;
+pub mod jupiter_swap;
+
+pub trait TransactionExtractor {
+    type Output;
+
+    fn extract(
+        &self,
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<Self::Output, Box<dyn Error>>;
+
+    // the pipeline extracts through this, so extractors can keep what the crawler knew
+    // about the transaction
+    fn extract_crawled(&self, tx: &CrawledTransaction) -> Result<Self::Output, Box<dyn Error>> {
+        self.extract(&tx.tx)
+    }
+}
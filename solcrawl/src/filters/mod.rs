@@ -1,7 +1,13 @@
+use crate::crawlers::account_crawler::AccountEvent;
 use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
 
 pub mod jupiter_swap_token;
+pub mod transaction;
 
 pub trait TransactionFilter {
     fn filter(&self, tx: &EncodedConfirmedTransactionWithStatusMeta) -> bool;
 }
+
+pub trait AccountFilter {
+    fn filter(&self, event: &AccountEvent) -> bool;
+}
@@ -0,0 +1,30 @@
+use crate::crawlers::watched_addresses;
+use crate::transaction_view::TransactionView;
+use crate::TransactionFilter;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+
+// FailedTransaction filters out transactions that failed on chain
+#[derive(Clone, Default)]
+pub struct FailedTransaction {}
+
+impl TransactionFilter for FailedTransaction {
+    fn filter(&self, tx: &EncodedConfirmedTransactionWithStatusMeta) -> bool {
+        TransactionView::new(tx)
+            .map(|view| !view.is_success())
+            .unwrap_or(true)
+    }
+}
+
+// Mentions filters out transactions that reference none of the addresses
+#[derive(Clone)]
+pub struct Mentions {
+    pub addresses: Vec<String>,
+}
+
+impl TransactionFilter for Mentions {
+    fn filter(&self, tx: &EncodedConfirmedTransactionWithStatusMeta) -> bool {
+        TransactionView::new(tx)
+            .map(|view| watched_addresses(&view, &self.addresses).is_empty())
+            .unwrap_or(true)
+    }
+}
@@ -1,4 +1,15 @@
 use crate::filters::TransactionFilter;
 
+pub mod archive;
+pub mod cache;
+pub mod candles;
 pub mod crawlers;
+pub mod extractors;
 pub mod filters;
+pub mod monitors;
+pub mod pipeline;
+pub mod sinks;
+#[cfg(test)]
+mod test_util;
+pub mod tokens;
+pub mod transaction_view;
@@ -0,0 +1,342 @@
+use crate::extractors::jupiter_swap::SwapEvent;
+use crate::sinks::Sink;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+
+pub const DEFAULT_THRESHOLDS: [f64; 2] = [0.005, 0.02];
+pub const DEFAULT_WINDOW_SECS: i64 = 5 * 60;
+pub const DEFAULT_SUSTAIN_SECS: i64 = 2 * 60;
+pub const DEFAULT_MIN_VOLUME: f64 = 10_000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepegAlertKind {
+    // the pair moved to a higher or lower threshold than last alerted
+    Depeg,
+    // the pair is back within the lowest threshold
+    Recovered,
+}
+
+// DepegAlert is raised when a stable pairs volume weighted price stayed past a threshold
+// (or back within all of them) for the sustain window
+#[derive(Clone, Debug, Serialize)]
+pub struct DepegAlert {
+    pub kind: DepegAlertKind,
+    pub base_token: String,
+    pub quote_token: String,
+    // quote tokens per base token over the window
+    pub vwap: f64,
+    pub deviation: f64,
+    // the highest threshold exceeded, None when recovered
+    pub threshold: Option<f64>,
+    pub window_secs: i64,
+    pub base_volume: f64,
+    // block time the pair first crossed into this state
+    pub since: i64,
+    pub block_time: i64,
+    pub sig: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct DepegConfig {
+    pub stable_tokens: Vec<String>,
+    // absolute deviations from 1.0, e.g. 0.005 and 0.02 for a warning and a critical level
+    pub thresholds: Vec<f64>,
+    pub window_secs: i64,
+    pub sustain_secs: i64,
+    // windows with less base volume are ignored, so one odd swap does not move the price
+    pub min_volume: f64,
+}
+
+#[derive(Default)]
+struct PairState {
+    // (block time, base amount, quote amount) of the swaps in the window
+    swaps: VecDeque<(i64, f64, f64)>,
+    latest: i64,
+    // index of the threshold last alerted, None while on peg
+    alerted: Option<usize>,
+    // level the pair has moved to and since when, until it is alerted
+    pending: Option<(Option<usize>, i64)>,
+}
+
+// DepegMonitor computes a rolling volume weighted price per stable pair from the swap
+// stream and sends alerts to its notifiers. Windows are in block time so replays and
+// backfills alert like the live stream did, swaps older than the window are ignored.
+// TODO dont print to std out - use a logger
+pub struct DepegMonitor {
+    config: DepegConfig,
+    pairs: HashMap<(String, String), PairState>,
+    notifiers: Vec<Box<dyn Sink<DepegAlert> + Send>>,
+}
+
+impl DepegMonitor {
+    pub fn new(
+        mut config: DepegConfig,
+        notifiers: Vec<Box<dyn Sink<DepegAlert> + Send>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if config.thresholds.is_empty() {
+            return Err("depeg monitor needs at least one threshold".into());
+        }
+        if config.thresholds.iter().any(|t| t.is_nan() || *t <= 0.0) {
+            return Err("depeg thresholds must be positive".into());
+        }
+        if config.window_secs <= 0 {
+            return Err("depeg window must be positive".into());
+        }
+        config.thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Ok(Self {
+            config,
+            pairs: HashMap::new(),
+            notifiers,
+        })
+    }
+
+    fn observe(&mut self, swap: &SwapEvent) -> Option<DepegAlert> {
+        let block_time = swap.block_time?;
+        if !swap.success
+            || swap.input_ui_amount <= 0.0
+            || swap.output_ui_amount <= 0.0
+            || !self.config.stable_tokens.contains(&swap.input_token)
+            || !self.config.stable_tokens.contains(&swap.output_token)
+        {
+            return None;
+        }
+
+        // same orientation as the candles, so both directions share one price
+        let (base_token, quote_token, base_amount, quote_amount) =
+            if swap.input_token <= swap.output_token {
+                (
+                    &swap.input_token,
+                    &swap.output_token,
+                    swap.input_ui_amount,
+                    swap.output_ui_amount,
+                )
+            } else {
+                (
+                    &swap.output_token,
+                    &swap.input_token,
+                    swap.output_ui_amount,
+                    swap.input_ui_amount,
+                )
+            };
+
+        let config = &self.config;
+        let state = self
+            .pairs
+            .entry((base_token.clone(), quote_token.clone()))
+            .or_default();
+        if block_time <= state.latest - config.window_secs {
+            return None;
+        }
+        state.latest = state.latest.max(block_time);
+        state
+            .swaps
+            .push_back((block_time, base_amount, quote_amount));
+        while let Some((time, _, _)) = state.swaps.front() {
+            if *time > state.latest - config.window_secs {
+                break;
+            }
+            state.swaps.pop_front();
+        }
+
+        let base_volume = state.swaps.iter().map(|s| s.1).sum::<f64>();
+        let quote_volume = state.swaps.iter().map(|s| s.2).sum::<f64>();
+        if base_volume < config.min_volume {
+            return None;
+        }
+        let vwap = quote_volume / base_volume;
+        let deviation = vwap - 1.0;
+        let level = config
+            .thresholds
+            .iter()
+            .rposition(|threshold| deviation.abs() >= *threshold);
+
+        if level == state.alerted {
+            state.pending = None;
+            return None;
+        }
+        let since = match state.pending {
+            Some((pending, since)) if pending == level => since,
+            _ => {
+                state.pending = Some((level, state.latest));
+                state.latest
+            }
+        };
+        if state.latest - since < config.sustain_secs {
+            return None;
+        }
+
+        state.alerted = level;
+        state.pending = None;
+        Some(DepegAlert {
+            kind: match level {
+                Some(_) => DepegAlertKind::Depeg,
+                None => DepegAlertKind::Recovered,
+            },
+            base_token: base_token.clone(),
+            quote_token: quote_token.clone(),
+            vwap,
+            deviation,
+            threshold: level.map(|l| config.thresholds[l]),
+            window_secs: config.window_secs,
+            base_volume,
+            since,
+            block_time,
+            sig: swap.sig.clone(),
+        })
+    }
+}
+
+impl Sink<SwapEvent> for DepegMonitor {
+    fn write_batch(&mut self, items: &[SwapEvent]) -> Result<(), Box<dyn Error>> {
+        let alerts = items
+            .iter()
+            .filter_map(|swap| self.observe(swap))
+            .collect::<Vec<DepegAlert>>();
+        if alerts.is_empty() {
+            return Ok(());
+        }
+        // one failing notifier should not keep the alert from the others
+        for notifier in self.notifiers.iter_mut() {
+            if let Err(e) = notifier.write_batch(&alerts) {
+                println!("depeg notify err - {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        for notifier in self.notifiers.iter_mut() {
+            if let Err(e) = notifier.flush() {
+                println!("depeg notify err - {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+// LogNotifier prints alerts as readable lines
+#[derive(Clone, Default)]
+pub struct LogNotifier {}
+
+impl Sink<DepegAlert> for LogNotifier {
+    fn write_batch(&mut self, items: &[DepegAlert]) -> Result<(), Box<dyn Error>> {
+        for alert in items {
+            match alert.kind {
+                DepegAlertKind::Depeg => println!(
+                    "DEPEG {}/{} vwap {:.6} ({:+.4}%) past {:.4}% since {} - {}",
+                    alert.base_token,
+                    alert.quote_token,
+                    alert.vwap,
+                    alert.deviation * 100.0,
+                    alert.threshold.unwrap_or_default() * 100.0,
+                    alert.since,
+                    alert.sig,
+                ),
+                DepegAlertKind::Recovered => println!(
+                    "RECOVERED {}/{} vwap {:.6} ({:+.4}%) since {} - {}",
+                    alert.base_token,
+                    alert.quote_token,
+                    alert.vwap,
+                    alert.deviation * 100.0,
+                    alert.since,
+                    alert.sig,
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{swap, USDC, USDT};
+
+    fn monitor() -> DepegMonitor {
+        let config = DepegConfig {
+            stable_tokens: vec![USDC.to_string(), USDT.to_string()],
+            thresholds: DEFAULT_THRESHOLDS.to_vec(),
+            window_secs: DEFAULT_WINDOW_SECS,
+            sustain_secs: DEFAULT_SUSTAIN_SECS,
+            min_volume: DEFAULT_MIN_VOLUME,
+        };
+        DepegMonitor::new(config, vec![]).unwrap()
+    }
+
+    // swaps usdc for usdt, amounts in whole tokens
+    fn observe(
+        monitor: &mut DepegMonitor,
+        block_time: i64,
+        input: u64,
+        output: u64,
+    ) -> Option<(DepegAlertKind, Option<f64>, i64)> {
+        let swap = swap("sig", block_time, input * 1_000_000, output * 1_000_000);
+        monitor
+            .observe(&swap)
+            .map(|alert| (alert.kind, alert.threshold, alert.since))
+    }
+
+    #[test]
+    fn ignores_windows_below_the_min_volume() {
+        let mut monitor = monitor();
+        for block_time in (0..=240).step_by(60) {
+            assert_eq!(observe(&mut monitor, block_time, 1_000, 900), None);
+        }
+    }
+
+    #[test]
+    fn waits_for_a_depeg_to_be_sustained() {
+        let mut monitor = monitor();
+        assert_eq!(observe(&mut monitor, 0, 20_000, 19_800), None);
+        assert_eq!(observe(&mut monitor, 60, 20_000, 19_800), None);
+        // back on peg before the sustain window passed
+        assert_eq!(observe(&mut monitor, 100, 200_000, 200_000), None);
+        // crossing again starts the sustain window over
+        assert_eq!(observe(&mut monitor, 130, 200_000, 190_000), None);
+        assert_eq!(observe(&mut monitor, 200, 20_000, 19_800), None);
+    }
+
+    #[test]
+    fn escalates_and_recovers() {
+        let mut monitor = monitor();
+        assert_eq!(observe(&mut monitor, 0, 20_000, 19_800), None);
+        assert_eq!(
+            observe(&mut monitor, 120, 20_000, 19_800),
+            Some((DepegAlertKind::Depeg, Some(0.005), 0))
+        );
+        // no repeated alert while the level holds
+        assert_eq!(observe(&mut monitor, 125, 20_000, 19_800), None);
+
+        assert_eq!(observe(&mut monitor, 130, 100_000, 90_000), None);
+        assert_eq!(
+            observe(&mut monitor, 250, 100_000, 90_000),
+            Some((DepegAlertKind::Depeg, Some(0.02), 130))
+        );
+
+        // the depegged swaps have left the window
+        assert_eq!(observe(&mut monitor, 600, 1_000_000, 1_000_000), None);
+        assert_eq!(
+            observe(&mut monitor, 720, 1_000_000, 1_000_000),
+            Some((DepegAlertKind::Recovered, None, 600))
+        );
+    }
+
+    #[test]
+    fn ignores_swaps_older_than_the_window() {
+        let mut monitor = monitor();
+        assert_eq!(observe(&mut monitor, 1_000, 20_000, 20_000), None);
+        // would be a critical depeg if it were counted
+        for block_time in [600, 650, 700] {
+            assert_eq!(observe(&mut monitor, block_time, 100_000, 50_000), None);
+        }
+        let state = monitor.pairs.values().next().unwrap();
+        assert_eq!(state.swaps.len(), 1);
+        assert_eq!(state.latest, 1_000);
+
+        // a late swap within the window still counts
+        assert_eq!(observe(&mut monitor, 800, 100_000, 50_000), None);
+        assert_eq!(monitor.pairs.values().next().unwrap().swaps.len(), 2);
+    }
+}
@@ -0,0 +1,436 @@
+use crate::crawlers::{CrawledTransaction, TransactionReceiver};
+use crate::extractors::TransactionExtractor;
+use crate::filters::TransactionFilter;
+use crate::sinks::{checkpoint_name, cursor_checkpoint_name, Checkpoint, Sink};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+const DEFAULT_BATCH_SIZE: usize = 1;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_WRITE_ATTEMPTS: u32 = 10;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+// Pipeline connects crawler channels to sinks. Received transactions go through the
+// pipelines own filters and the extractor, then are written to the sinks in batches of
+// batch_size, or earlier once flush_interval has passed since the last write.
+// Raw sinks receive the crawled transactions themselves, sinks the extracted output.
+// Unfiltered sinks receive every transaction the crawlers published, also those the
+// filters drop.
+// After every batch each source that published into it is checkpointed, per address for
+// sources that keep a cursor per watched address.
+// A batch that fails to be written is retried with backoff before anything new is taken,
+// from the sink that failed on, and the pipeline stops once max_write_attempts are used.
+pub struct Pipeline<E: TransactionExtractor + Send> {
+    name: String,
+    receivers: Vec<TransactionReceiver>,
+    // checkpoint names of the receivers, their index unless set
+    sources: Vec<String>,
+    filters: Vec<Box<dyn TransactionFilter + Send>>,
+    extractor: E,
+    sinks: Vec<Box<dyn Sink<E::Output> + Send>>,
+    raw_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>>,
+    unfiltered_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>>,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_write_attempts: u32,
+    retry_backoff: Duration,
+}
+
+// TODO dont print to std out - use a logger
+impl<E: TransactionExtractor + Send> Pipeline<E> {
+    pub fn new(
+        name: String,
+        receivers: Vec<TransactionReceiver>,
+        extractor: E,
+        sinks: Vec<Box<dyn Sink<E::Output> + Send>>,
+    ) -> Self {
+        Self {
+            name,
+            sources: (0..receivers.len()).map(|i| i.to_string()).collect(),
+            receivers,
+            filters: Vec::new(),
+            extractor,
+            sinks,
+            raw_sinks: Vec::new(),
+            unfiltered_sinks: Vec::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            max_write_attempts: DEFAULT_WRITE_ATTEMPTS,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+        }
+    }
+
+    // one name per receiver, in the same order
+    pub fn with_source_names(mut self, sources: Vec<String>) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    pub fn with_filters(mut self, filters: Vec<Box<dyn TransactionFilter + Send>>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn with_raw_sinks(
+        mut self,
+        raw_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>>,
+    ) -> Self {
+        self.raw_sinks = raw_sinks;
+        self
+    }
+
+    pub fn with_unfiltered_sinks(
+        mut self,
+        unfiltered_sinks: Vec<Box<dyn Sink<CrawledTransaction> + Send>>,
+    ) -> Self {
+        self.unfiltered_sinks = unfiltered_sinks;
+        self
+    }
+
+    pub fn with_batching(mut self, batch_size: usize, flush_interval: Duration) -> Self {
+        self.batch_size = batch_size.max(1);
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    // the backoff doubles after every failed attempt, up to a minute
+    pub fn with_write_retries(mut self, max_write_attempts: u32, retry_backoff: Duration) -> Self {
+        self.max_write_attempts = max_write_attempts.max(1);
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    // runs until every crawler has dropped its end of the channel, or a batch could not
+    // be written
+    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        let receivers = self.receivers.clone();
+        let mut sel = crossbeam::channel::Select::new();
+        receivers.iter().for_each(|recv| {
+            sel.recv(recv);
+        });
+
+        let mut open = receivers.len();
+        // transactions the filters dropped, kept only for the unfiltered sinks
+        let mut dropped: Vec<CrawledTransaction> = Vec::new();
+        let mut txs: Vec<CrawledTransaction> = Vec::new();
+        let mut outputs: Vec<E::Output> = Vec::new();
+        // last transaction every source, or source cursor, published since the previous
+        // batch by checkpoint name
+        let mut positions: BTreeMap<String, (String, u64)> = BTreeMap::new();
+        let mut last_write = Instant::now();
+        while open > 0 {
+            let timeout = self.flush_interval.saturating_sub(last_write.elapsed());
+            if let Ok(opr) = sel.select_timeout(timeout) {
+                let i = opr.index();
+                match opr.recv(&receivers[i]) {
+                    Ok(tx) => {
+                        let name = match &tx.cursor {
+                            Some(cursor) => {
+                                cursor_checkpoint_name(&self.name, &self.sources[i], cursor)
+                            }
+                            None => checkpoint_name(&self.name, &self.sources[i]),
+                        };
+                        positions.insert(name, (tx.sig.clone(), tx.tx.slot));
+                        self.process(tx, &mut dropped, &mut txs, &mut outputs);
+                    }
+                    Err(_) => {
+                        sel.remove(i);
+                        open -= 1;
+                    }
+                }
+            }
+
+            if txs.len() >= self.batch_size || last_write.elapsed() >= self.flush_interval {
+                self.write(&mut dropped, &mut txs, &mut outputs, &mut positions)?;
+                last_write = Instant::now();
+            }
+        }
+        self.write(&mut dropped, &mut txs, &mut outputs, &mut positions)
+    }
+
+    fn process(
+        &self,
+        tx: CrawledTransaction,
+        dropped: &mut Vec<CrawledTransaction>,
+        txs: &mut Vec<CrawledTransaction>,
+        outputs: &mut Vec<E::Output>,
+    ) {
+        if self.filters.iter().any(|filter| filter.filter(&tx.tx)) {
+            if !self.unfiltered_sinks.is_empty() {
+                dropped.push(tx);
+            }
+            return;
+        }
+        match self.extractor.extract_crawled(&tx) {
+            Ok(output) => outputs.push(output),
+            Err(e) => println!("{} extract err - {} - {}", self.name, tx.sig, e),
+        }
+        txs.push(tx);
+    }
+
+    // sources are only checkpointed once every sink took the batch
+    fn write(
+        &mut self,
+        dropped: &mut Vec<CrawledTransaction>,
+        txs: &mut Vec<CrawledTransaction>,
+        outputs: &mut Vec<E::Output>,
+        positions: &mut BTreeMap<String, (String, u64)>,
+    ) -> Result<(), Box<dyn Error>> {
+        if dropped.is_empty() && txs.is_empty() {
+            return Ok(());
+        }
+        let mut written = 0;
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 1;
+        while let Err(e) = self.try_write(dropped, txs, outputs, positions, &mut written) {
+            if attempt >= self.max_write_attempts {
+                return Err(format!("{} write failed {} times - {}", self.name, attempt, e).into());
+            }
+            println!("{} write err - {}, retrying in {:?}", self.name, e, backoff);
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+            attempt += 1;
+        }
+        dropped.clear();
+        txs.clear();
+        outputs.clear();
+        positions.clear();
+        Ok(())
+    }
+
+    fn try_write(
+        &mut self,
+        dropped: &[CrawledTransaction],
+        txs: &[CrawledTransaction],
+        outputs: &[E::Output],
+        positions: &BTreeMap<String, (String, u64)>,
+        written: &mut usize,
+    ) -> Result<(), Box<dyn Error>> {
+        // sinks take the batch in order, written counts those that already did so a retry
+        // does not hand it to them twice
+        let unfiltered = self.unfiltered_sinks.len();
+        let raw = self.raw_sinks.len();
+        // the dropped and the passed transactions go out as two batches
+        for sink in self.unfiltered_sinks.iter_mut().skip(*written) {
+            for batch in [dropped, txs] {
+                if !batch.is_empty() {
+                    sink.write_batch(batch)?;
+                }
+            }
+            sink.flush()?;
+            *written += 1;
+        }
+        for sink in self
+            .raw_sinks
+            .iter_mut()
+            .skip(written.saturating_sub(unfiltered))
+        {
+            if !txs.is_empty() {
+                sink.write_batch(txs)?;
+                sink.flush()?;
+            }
+            *written += 1;
+        }
+        for sink in self
+            .sinks
+            .iter_mut()
+            .skip(written.saturating_sub(unfiltered + raw))
+        {
+            if !outputs.is_empty() {
+                sink.write_batch(outputs)?;
+                sink.flush()?;
+            }
+            *written += 1;
+        }
+
+        for (name, (sig, slot)) in positions {
+            let checkpoint = Checkpoint {
+                name: name.clone(),
+                sig: sig.clone(),
+                slot: *slot,
+            };
+            for sink in self.unfiltered_sinks.iter_mut() {
+                sink.checkpoint(&checkpoint)?;
+            }
+            for sink in self.raw_sinks.iter_mut() {
+                sink.checkpoint(&checkpoint)?;
+            }
+            for sink in self.sinks.iter_mut() {
+                sink.checkpoint(&checkpoint)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawlers::Commitment;
+    use crate::test_util::{key, signature, transaction};
+    use solana_transaction_status::{
+        EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding,
+    };
+    use std::sync::{Arc, Mutex};
+
+    struct Signatures;
+
+    impl TransactionExtractor for Signatures {
+        type Output = String;
+
+        fn extract(
+            &self,
+            tx: &EncodedConfirmedTransactionWithStatusMeta,
+        ) -> Result<String, Box<dyn Error>> {
+            Ok(tx.transaction.transaction.decode().unwrap().signatures[0].to_string())
+        }
+    }
+
+    // keeps what it was given in a log the test holds on to
+    #[derive(Clone, Default)]
+    struct Recorder {
+        written: Arc<Mutex<Vec<String>>>,
+        checkpoints: Arc<Mutex<Vec<Checkpoint>>>,
+    }
+
+    impl Sink<String> for Recorder {
+        fn write_batch(&mut self, items: &[String]) -> Result<(), Box<dyn Error>> {
+            self.written.lock().unwrap().extend(items.iter().cloned());
+            Ok(())
+        }
+
+        fn checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+            self.checkpoints.lock().unwrap().push(checkpoint.clone());
+            Ok(())
+        }
+    }
+
+    // fails its first failures batches
+    struct Failing {
+        failures: u32,
+        recorder: Recorder,
+    }
+
+    impl Sink<String> for Failing {
+        fn write_batch(&mut self, items: &[String]) -> Result<(), Box<dyn Error>> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err("sink is down".into());
+            }
+            self.recorder.write_batch(items)
+        }
+
+        fn checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+            self.recorder.checkpoint(checkpoint)
+        }
+    }
+
+    fn crawled(n: u8, slot: u64, cursor: Option<String>) -> CrawledTransaction {
+        CrawledTransaction {
+            sig: signature(n).to_string(),
+            matched: vec![],
+            commitment: Commitment::Finalized,
+            cursor,
+            tx: transaction(&signature(n), slot, UiTransactionEncoding::Base64),
+        }
+    }
+
+    #[test]
+    fn checkpoints_every_cursor_of_a_source() {
+        let (a, b) = (key(10).to_string(), key(11).to_string());
+        let (historical, historical_recv) = crossbeam::channel::unbounded();
+        let (live, live_recv) = crossbeam::channel::unbounded();
+        historical.send(crawled(1, 900, Some(a.clone()))).unwrap();
+        historical.send(crawled(2, 500, Some(b.clone()))).unwrap();
+        historical.send(crawled(3, 800, Some(a.clone()))).unwrap();
+        live.send(crawled(4, 1000, None)).unwrap();
+        drop((historical, live));
+
+        let recorder = Recorder::default();
+        Pipeline::new(
+            "p".to_string(),
+            vec![historical_recv, live_recv],
+            Signatures,
+            vec![Box::new(recorder.clone())],
+        )
+        .with_source_names(vec!["historical".to_string(), "live".to_string()])
+        .with_batching(10, Duration::from_secs(60))
+        .run()
+        .unwrap();
+
+        let mut checkpoints = recorder
+            .checkpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| (c.name.clone(), c.sig.clone(), c.slot))
+            .collect::<Vec<(String, String, u64)>>();
+        checkpoints.sort();
+        let mut expected = vec![
+            (format!("p/historical/{}", a), signature(3).to_string(), 800),
+            (format!("p/historical/{}", b), signature(2).to_string(), 500),
+            ("p/live".to_string(), signature(4).to_string(), 1000),
+        ];
+        expected.sort();
+        assert_eq!(checkpoints, expected);
+        assert_eq!(recorder.written.lock().unwrap().len(), 4);
+    }
+
+    // a pipeline of a recorder sink followed by a sink failing its first failures batches,
+    // fed two batches of one transaction
+    fn run_failing(
+        failures: u32,
+        max_write_attempts: u32,
+    ) -> (Result<(), Box<dyn Error>>, Recorder, Recorder) {
+        let (sender, recv) = crossbeam::channel::unbounded();
+        sender.send(crawled(1, 10, None)).unwrap();
+        sender.send(crawled(2, 20, None)).unwrap();
+        drop(sender);
+
+        let (first, failing) = (Recorder::default(), Recorder::default());
+        let res = Pipeline::new(
+            "p".to_string(),
+            vec![recv],
+            Signatures,
+            vec![
+                Box::new(first.clone()),
+                Box::new(Failing {
+                    failures,
+                    recorder: failing.clone(),
+                }),
+            ],
+        )
+        .with_write_retries(max_write_attempts, Duration::ZERO)
+        .run();
+        (res, first, failing)
+    }
+
+    #[test]
+    fn failed_batches_are_retried_before_new_ones() {
+        let (res, first, failing) = run_failing(2, 3);
+        res.unwrap();
+
+        let sigs = vec![signature(1).to_string(), signature(2).to_string()];
+        // the sink before the failing one gets every batch once
+        assert_eq!(*first.written.lock().unwrap(), sigs);
+        assert_eq!(*failing.written.lock().unwrap(), sigs);
+        let checkpoints = failing.checkpoints.lock().unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints[0].sig, signature(1).to_string());
+    }
+
+    #[test]
+    fn stops_once_write_attempts_are_used_up() {
+        let (res, first, failing) = run_failing(3, 3);
+
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("write failed 3 times"));
+        assert_eq!(first.written.lock().unwrap().len(), 1);
+        assert!(failing.written.lock().unwrap().is_empty());
+        assert!(first.checkpoints.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,17 @@
+use crate::archive::ArchiveWriter;
+use crate::crawlers::CrawledTransaction;
+use crate::sinks::Sink;
+use std::error::Error;
+
+impl Sink<CrawledTransaction> for ArchiveWriter {
+    fn write_batch(&mut self, items: &[CrawledTransaction]) -> Result<(), Box<dyn Error>> {
+        for item in items {
+            self.write(&item.sig, &item.tx)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        ArchiveWriter::flush(self)
+    }
+}
@@ -0,0 +1,231 @@
+use crate::sinks::{Checkpoint, Sink};
+use crossbeam::channel::{Receiver, Sender};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex, Weak};
+
+// items a slow subscriber may fall behind before it is dropped
+const SUBSCRIBER_CAPACITY: usize = 1000;
+
+pub type SubscriberFilter<T> = Box<dyn Fn(&T) -> bool + Send>;
+
+struct Subscriber<T> {
+    filter: SubscriberFilter<T>,
+    sender: Sender<(u64, Arc<T>)>,
+    // gone once the subscription is dropped, also for subscribers nothing matches
+    alive: Weak<()>,
+}
+
+// Subscription receives (event id, item) pairs until it is dropped
+pub struct Subscription<T> {
+    pub receiver: Receiver<(u64, Arc<T>)>,
+    _alive: Arc<()>,
+}
+
+struct HubState<T> {
+    // sequence number of the next published item, sent along as the event id
+    next_id: u64,
+    recent: VecDeque<(u64, Arc<T>)>,
+    replay_capacity: usize,
+    subscribers: Vec<Subscriber<T>>,
+}
+
+// BroadcastHub fans published items out to every subscriber whose filter matches,
+// and keeps the last items around so new subscribers can catch up
+pub struct BroadcastHub<T> {
+    state: Arc<Mutex<HubState<T>>>,
+}
+
+impl<T> Clone for BroadcastHub<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> BroadcastHub<T> {
+    pub fn new(replay_capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HubState {
+                next_id: 1,
+                recent: VecDeque::with_capacity(replay_capacity),
+                replay_capacity,
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    // the subscription starts with up to replay of the most recent matching items,
+    // only those published after the event id after when a reconnecting client sets it
+    pub fn subscribe(
+        &self,
+        filter: SubscriberFilter<T>,
+        replay: usize,
+        after: Option<u64>,
+    ) -> Subscription<T> {
+        let mut state = self.state.lock().unwrap();
+        // ids start over with the hub, an id it never assigned came from an earlier one
+        let after = after.filter(|after| *after < state.next_id).unwrap_or(0);
+        let matching = state
+            .recent
+            .iter()
+            .filter(|(id, item)| *id > after && filter(item))
+            .cloned()
+            .collect::<Vec<_>>();
+        let skip = matching.len().saturating_sub(replay);
+
+        let (sender, receiver) =
+            crossbeam::channel::bounded(SUBSCRIBER_CAPACITY + state.replay_capacity);
+        for item in matching.into_iter().skip(skip) {
+            // the channel has room for the whole replay
+            let _ = sender.try_send(item);
+        }
+        let alive = Arc::new(());
+        state.subscribers.push(Subscriber {
+            filter,
+            sender,
+            alive: Arc::downgrade(&alive),
+        });
+        Subscription {
+            receiver,
+            _alive: alive,
+        }
+    }
+
+    pub fn publish(&self, items: Vec<T>) {
+        let mut state = self.state.lock().unwrap();
+        for item in items {
+            let id = state.next_id;
+            state.next_id += 1;
+            let item = Arc::new(item);
+            // subscribers that hung up or fell too far behind are dropped
+            state.subscribers.retain(|s| {
+                if s.alive.strong_count() == 0 {
+                    return false;
+                }
+                if !(s.filter)(&item) {
+                    return true;
+                }
+                s.sender.try_send((id, item.clone())).is_ok()
+            });
+            if state.replay_capacity > 0 {
+                if state.recent.len() == state.replay_capacity {
+                    state.recent.pop_front();
+                }
+                state.recent.push_back((id, item));
+            }
+        }
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.state.lock().unwrap().subscribers.len()
+    }
+}
+
+// BroadcastSink publishes items to a hub once the pipeline has checkpointed them,
+// so subscribers only see items every other sink has stored. The filter should keep only
+// what those sinks store.
+pub struct BroadcastSink<T> {
+    hub: BroadcastHub<T>,
+    pending: Vec<T>,
+    filter: Option<SubscriberFilter<T>>,
+}
+
+impl<T> BroadcastSink<T> {
+    pub fn new(hub: BroadcastHub<T>) -> Self {
+        Self {
+            hub,
+            pending: Vec::new(),
+            filter: None,
+        }
+    }
+
+    // only items the filter returns true for are published
+    pub fn with_filter(mut self, filter: SubscriberFilter<T>) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+impl<T: Clone> Sink<T> for BroadcastSink<T> {
+    // items are only published with the checkpoint, a batch whose checkpoint failed is
+    // published with the next one, when the sinks before have stored it
+    fn write_batch(&mut self, items: &[T]) -> Result<(), Box<dyn Error>> {
+        let filter = &self.filter;
+        self.pending.extend(
+            items
+                .iter()
+                .filter(|item| filter.as_ref().is_none_or(|keep| keep(item)))
+                .cloned(),
+        );
+        Ok(())
+    }
+
+    fn checkpoint(&mut self, _checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+        self.hub.publish(std::mem::take(&mut self.pending));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            name: "p/0".to_string(),
+            sig: "sig".to_string(),
+            slot: 1,
+        }
+    }
+
+    fn received(subscription: &Subscription<u32>) -> Vec<u32> {
+        subscription
+            .receiver
+            .try_iter()
+            .map(|(_, item)| *item)
+            .collect()
+    }
+
+    #[test]
+    fn batches_wait_for_a_checkpoint() {
+        let hub = BroadcastHub::new(10);
+        let subscription = hub.subscribe(Box::new(|_| true), 0, None);
+        let mut sink = BroadcastSink::new(hub);
+
+        sink.write_batch(&[1, 2]).unwrap();
+        assert!(received(&subscription).is_empty());
+        // the checkpoint of the first batch failed, it goes out with the next one
+        sink.write_batch(&[3]).unwrap();
+        sink.checkpoint(&checkpoint()).unwrap();
+        assert_eq!(received(&subscription), vec![1, 2, 3]);
+
+        sink.checkpoint(&checkpoint()).unwrap();
+        assert!(received(&subscription).is_empty());
+    }
+
+    #[test]
+    fn publishes_only_what_the_filter_keeps() {
+        let hub = BroadcastHub::new(10);
+        let subscription = hub.subscribe(Box::new(|_| true), 0, None);
+        let mut sink =
+            BroadcastSink::new(hub).with_filter(Box::new(|item: &u32| item.is_multiple_of(2)));
+
+        sink.write_batch(&[1, 2, 3, 4]).unwrap();
+        sink.checkpoint(&checkpoint()).unwrap();
+        assert_eq!(received(&subscription), vec![2, 4]);
+    }
+
+    #[test]
+    fn reconnects_only_replay_items_after_their_last_event() {
+        let hub = BroadcastHub::new(10);
+        hub.publish(vec![1, 2, 3]);
+
+        let subscription = hub.subscribe(Box::new(|_| true), 10, Some(2));
+        assert_eq!(received(&subscription), vec![3]);
+        // an id from before a restart replays everything
+        let subscription = hub.subscribe(Box::new(|_| true), 10, Some(50));
+        assert_eq!(received(&subscription), vec![1, 2, 3]);
+    }
+}
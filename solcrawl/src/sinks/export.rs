@@ -0,0 +1,604 @@
+use crate::extractors::jupiter_swap::SwapEvent;
+use crate::sinks::{Checkpoint, Sink};
+use parquet::basic::Compression;
+use parquet::data_type::{
+    BoolType, ByteArray, ByteArrayType, DataType, DoubleType, Int32Type, Int64Type,
+};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 100_000;
+const FILE_PREFIX: &str = "swaps-";
+
+const SWAP_SCHEMA: &str = "
+message swap {
+    REQUIRED BYTE_ARRAY sig (STRING);
+    REQUIRED INT64 slot (INTEGER(64,false));
+    OPTIONAL INT64 block_time (TIMESTAMP(MILLIS,true));
+    REQUIRED BYTE_ARRAY trader (STRING);
+    REQUIRED BYTE_ARRAY input_token (STRING);
+    REQUIRED BYTE_ARRAY output_token (STRING);
+    OPTIONAL BYTE_ARRAY input_symbol (STRING);
+    OPTIONAL BYTE_ARRAY output_symbol (STRING);
+    REQUIRED INT64 input_amount (INTEGER(64,false));
+    REQUIRED INT64 output_amount (INTEGER(64,false));
+    REQUIRED INT32 input_decimals (INTEGER(8,false));
+    REQUIRED INT32 output_decimals (INTEGER(8,false));
+    REQUIRED DOUBLE input_ui_amount;
+    REQUIRED DOUBLE output_ui_amount;
+    REQUIRED BYTE_ARRAY input_token_program (STRING);
+    REQUIRED BYTE_ARRAY output_token_program (STRING);
+    REQUIRED INT64 input_transfer_fee (INTEGER(64,false));
+    REQUIRED INT64 output_transfer_fee (INTEGER(64,false));
+    REQUIRED DOUBLE price;
+    REQUIRED DOUBLE inverse_price;
+    OPTIONAL DOUBLE peg_deviation;
+    OPTIONAL INT64 quoted_out_amount (INTEGER(64,false));
+    OPTIONAL INT64 minimum_out_amount (INTEGER(64,false));
+    OPTIONAL DOUBLE price_impact_bps;
+    REQUIRED INT64 fee (INTEGER(64,false));
+    REQUIRED BOOLEAN success;
+    OPTIONAL INT32 program_version (INTEGER(8,false));
+    REQUIRED INT32 legs (INTEGER(32,false));
+}
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!("unknown export format - {}", s)),
+        }
+    }
+}
+
+// ExportFilter selects the swaps an ExportSink writes, times are unix seconds and the
+// end is exclusive. A pair matches swaps in either direction.
+#[derive(Clone, Debug, Default)]
+pub struct ExportFilter {
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub pair: Option<(String, String)>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, swap: &SwapEvent) -> bool {
+        if self.start_time.is_some() || self.end_time.is_some() {
+            let block_time = match swap.block_time {
+                Some(block_time) => block_time,
+                None => return false,
+            };
+            if matches!(self.start_time, Some(start) if block_time < start)
+                || matches!(self.end_time, Some(end) if block_time >= end)
+            {
+                return false;
+            }
+        }
+        match &self.pair {
+            Some((a, b)) => {
+                (&swap.input_token == a && &swap.output_token == b)
+                    || (&swap.input_token == b && &swap.output_token == a)
+            }
+            None => true,
+        }
+    }
+}
+
+// ExportSink writes swaps to CSV or Parquet files partitioned by the UTC day of their
+// block time, dir/date=2022-04-12/swaps-000001.parquet, which pandas and DuckDB read as
+// a hive partitioned dataset. Every sink writes new files next to existing ones.
+// A day's file is finished once a swap of a later day arrives. Parquet rows are buffered
+// until a row group is full and parquet files are only readable once finished, so they
+// are also finished at every checkpoint and the next batch starts new files.
+pub struct ExportSink {
+    dir: PathBuf,
+    format: ExportFormat,
+    row_group_size: usize,
+    filter: ExportFilter,
+    partitions: BTreeMap<String, PartitionWriter>,
+}
+
+enum PartitionWriter {
+    Csv(csv::Writer<File>),
+    Parquet {
+        writer: SerializedFileWriter<File>,
+        rows: Vec<ExportRow>,
+    },
+}
+
+// ExportRow is the flat, typed form of a swap that is written to the export files
+#[derive(Clone, Debug, Serialize)]
+struct ExportRow {
+    sig: String,
+    slot: u64,
+    block_time: Option<i64>,
+    trader: String,
+    input_token: String,
+    output_token: String,
+    input_symbol: Option<String>,
+    output_symbol: Option<String>,
+    input_amount: u64,
+    output_amount: u64,
+    input_decimals: u8,
+    output_decimals: u8,
+    input_ui_amount: f64,
+    output_ui_amount: f64,
+    input_token_program: String,
+    output_token_program: String,
+    input_transfer_fee: u64,
+    output_transfer_fee: u64,
+    price: f64,
+    inverse_price: f64,
+    peg_deviation: Option<f64>,
+    quoted_out_amount: Option<u64>,
+    minimum_out_amount: Option<u64>,
+    price_impact_bps: Option<f64>,
+    fee: u64,
+    success: bool,
+    program_version: Option<u8>,
+    legs: u32,
+}
+
+impl From<&SwapEvent> for ExportRow {
+    fn from(swap: &SwapEvent) -> Self {
+        Self {
+            sig: swap.sig.clone(),
+            slot: swap.slot,
+            block_time: swap.block_time,
+            trader: swap.trader.clone(),
+            input_token: swap.input_token.clone(),
+            output_token: swap.output_token.clone(),
+            input_symbol: swap.input_symbol.clone(),
+            output_symbol: swap.output_symbol.clone(),
+            input_amount: swap.input_amount,
+            output_amount: swap.output_amount,
+            input_decimals: swap.input_decimals,
+            output_decimals: swap.output_decimals,
+            input_ui_amount: swap.input_ui_amount,
+            output_ui_amount: swap.output_ui_amount,
+            input_token_program: swap.input_token_program.clone(),
+            output_token_program: swap.output_token_program.clone(),
+            input_transfer_fee: swap.input_transfer_fee,
+            output_transfer_fee: swap.output_transfer_fee,
+            price: swap.price,
+            inverse_price: swap.inverse_price,
+            peg_deviation: swap.peg_deviation,
+            quoted_out_amount: swap.quoted_out_amount,
+            minimum_out_amount: swap.minimum_out_amount,
+            price_impact_bps: swap.price_impact_bps,
+            fee: swap.fee,
+            success: swap.success,
+            program_version: swap.program_version,
+            legs: swap.legs.len() as u32,
+        }
+    }
+}
+
+impl ExportSink {
+    pub fn new(
+        dir: PathBuf,
+        format: ExportFormat,
+        row_group_size: usize,
+        filter: ExportFilter,
+    ) -> Result<Self, Box<dyn Error>> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            format,
+            row_group_size: row_group_size.max(1),
+            filter,
+            partitions: BTreeMap::new(),
+        })
+    }
+
+    // writes buffered rows and finishes every file, later swaps go to new files
+    pub fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        while let Some((_, partition)) = self.partitions.pop_first() {
+            partition.close()?;
+        }
+        Ok(())
+    }
+
+    // finishes the files of days before day, swaps arrive mostly in time order so those
+    // days are done. swaps without a block time sort after every day and stay open
+    fn close_before(&mut self, day: &str) -> Result<(), Box<dyn Error>> {
+        while let Some(entry) = self.partitions.first_entry() {
+            if entry.key().as_str() >= day {
+                break;
+            }
+            entry.remove().close()?;
+        }
+        Ok(())
+    }
+
+    fn partition(&mut self, day: &str) -> Result<&mut PartitionWriter, Box<dyn Error>> {
+        if !self.partitions.contains_key(day) {
+            let partition = self.open_partition(day)?;
+            self.partitions.insert(day.to_string(), partition);
+        }
+        Ok(self
+            .partitions
+            .get_mut(day)
+            .ok_or("partition is not open")?)
+    }
+
+    fn open_partition(&self, day: &str) -> Result<PartitionWriter, Box<dyn Error>> {
+        let dir = self.dir.join(format!("date={}", day));
+        std::fs::create_dir_all(&dir)?;
+
+        let mut number = 0;
+        for entry in std::fs::read_dir(&dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if let Some(n) = name
+                .strip_prefix(FILE_PREFIX)
+                .and_then(|n| n.split('.').next())
+                .and_then(|n| n.parse::<u64>().ok())
+            {
+                number = number.max(n);
+            }
+        }
+        let path = dir.join(format!(
+            "{}{:06}.{}",
+            FILE_PREFIX,
+            number + 1,
+            self.format.extension()
+        ));
+        let file = File::create(path)?;
+
+        Ok(match self.format {
+            ExportFormat::Csv => PartitionWriter::Csv(csv::Writer::from_writer(file)),
+            ExportFormat::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .set_max_row_group_size(self.row_group_size)
+                    .build();
+                PartitionWriter::Parquet {
+                    writer: SerializedFileWriter::new(
+                        file,
+                        Arc::new(parse_message_type(SWAP_SCHEMA)?),
+                        Arc::new(props),
+                    )?,
+                    rows: Vec::new(),
+                }
+            }
+        })
+    }
+}
+
+impl Sink<SwapEvent> for ExportSink {
+    fn write_batch(&mut self, items: &[SwapEvent]) -> Result<(), Box<dyn Error>> {
+        let row_group_size = self.row_group_size;
+        for swap in items {
+            if !self.filter.matches(swap) {
+                continue;
+            }
+            let day = match swap.block_time {
+                Some(block_time) => utc_date(block_time),
+                None => "unknown".to_string(),
+            };
+            self.close_before(&day)?;
+            match self.partition(&day)? {
+                PartitionWriter::Csv(writer) => writer.serialize(ExportRow::from(swap))?,
+                PartitionWriter::Parquet { writer, rows } => {
+                    rows.push(ExportRow::from(swap));
+                    if rows.len() >= row_group_size {
+                        write_row_group(writer, rows)?;
+                        rows.clear();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        for partition in self.partitions.values_mut() {
+            if let PartitionWriter::Csv(writer) = partition {
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // checkpointed swaps have to be readable from the files
+    fn checkpoint(&mut self, _checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+        if self.format == ExportFormat::Parquet {
+            self.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl PartitionWriter {
+    fn close(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            PartitionWriter::Csv(mut writer) => writer.flush()?,
+            PartitionWriter::Parquet { mut writer, rows } => {
+                if !rows.is_empty() {
+                    write_row_group(&mut writer, &rows)?;
+                }
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ExportSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            println!("export close err - {}", e);
+        }
+    }
+}
+
+// columns are written in the order of SWAP_SCHEMA
+fn write_row_group(
+    writer: &mut SerializedFileWriter<File>,
+    rows: &[ExportRow],
+) -> Result<(), Box<dyn Error>> {
+    let string = |s: &String| ByteArray::from(s.as_str());
+    let mut rg = writer.next_row_group()?;
+    write_required::<ByteArrayType>(&mut rg, rows.iter().map(|r| string(&r.sig)).collect())?;
+    write_required::<Int64Type>(&mut rg, rows.iter().map(|r| r.slot as i64).collect())?;
+    write_optional::<Int64Type>(
+        &mut rg,
+        rows.iter()
+            .map(|r| r.block_time.map(|t| t * 1000))
+            .collect(),
+    )?;
+    write_required::<ByteArrayType>(&mut rg, rows.iter().map(|r| string(&r.trader)).collect())?;
+    write_required::<ByteArrayType>(
+        &mut rg,
+        rows.iter().map(|r| string(&r.input_token)).collect(),
+    )?;
+    write_required::<ByteArrayType>(
+        &mut rg,
+        rows.iter().map(|r| string(&r.output_token)).collect(),
+    )?;
+    write_optional::<ByteArrayType>(
+        &mut rg,
+        rows.iter()
+            .map(|r| r.input_symbol.as_ref().map(string))
+            .collect(),
+    )?;
+    write_optional::<ByteArrayType>(
+        &mut rg,
+        rows.iter()
+            .map(|r| r.output_symbol.as_ref().map(string))
+            .collect(),
+    )?;
+    write_required::<Int64Type>(
+        &mut rg,
+        rows.iter().map(|r| r.input_amount as i64).collect(),
+    )?;
+    write_required::<Int64Type>(
+        &mut rg,
+        rows.iter().map(|r| r.output_amount as i64).collect(),
+    )?;
+    write_required::<Int32Type>(
+        &mut rg,
+        rows.iter().map(|r| r.input_decimals as i32).collect(),
+    )?;
+    write_required::<Int32Type>(
+        &mut rg,
+        rows.iter().map(|r| r.output_decimals as i32).collect(),
+    )?;
+    write_required::<DoubleType>(&mut rg, rows.iter().map(|r| r.input_ui_amount).collect())?;
+    write_required::<DoubleType>(&mut rg, rows.iter().map(|r| r.output_ui_amount).collect())?;
+    write_required::<ByteArrayType>(
+        &mut rg,
+        rows.iter()
+            .map(|r| string(&r.input_token_program))
+            .collect(),
+    )?;
+    write_required::<ByteArrayType>(
+        &mut rg,
+        rows.iter()
+            .map(|r| string(&r.output_token_program))
+            .collect(),
+    )?;
+    write_required::<Int64Type>(
+        &mut rg,
+        rows.iter().map(|r| r.input_transfer_fee as i64).collect(),
+    )?;
+    write_required::<Int64Type>(
+        &mut rg,
+        rows.iter().map(|r| r.output_transfer_fee as i64).collect(),
+    )?;
+    write_required::<DoubleType>(&mut rg, rows.iter().map(|r| r.price).collect())?;
+    write_required::<DoubleType>(&mut rg, rows.iter().map(|r| r.inverse_price).collect())?;
+    write_optional::<DoubleType>(&mut rg, rows.iter().map(|r| r.peg_deviation).collect())?;
+    write_optional::<Int64Type>(
+        &mut rg,
+        rows.iter()
+            .map(|r| r.quoted_out_amount.map(|a| a as i64))
+            .collect(),
+    )?;
+    write_optional::<Int64Type>(
+        &mut rg,
+        rows.iter()
+            .map(|r| r.minimum_out_amount.map(|a| a as i64))
+            .collect(),
+    )?;
+    write_optional::<DoubleType>(&mut rg, rows.iter().map(|r| r.price_impact_bps).collect())?;
+    write_required::<Int64Type>(&mut rg, rows.iter().map(|r| r.fee as i64).collect())?;
+    write_required::<BoolType>(&mut rg, rows.iter().map(|r| r.success).collect())?;
+    write_optional::<Int32Type>(
+        &mut rg,
+        rows.iter()
+            .map(|r| r.program_version.map(|v| v as i32))
+            .collect(),
+    )?;
+    write_required::<Int32Type>(&mut rg, rows.iter().map(|r| r.legs as i32).collect())?;
+    rg.close()?;
+    Ok(())
+}
+
+fn write_required<T: DataType>(
+    rg: &mut SerializedRowGroupWriter<File>,
+    values: Vec<T::T>,
+) -> Result<(), Box<dyn Error>> {
+    let mut col = rg
+        .next_column()?
+        .ok_or("more columns than the parquet schema")?;
+    col.typed::<T>().write_batch(&values, None, None)?;
+    col.close()?;
+    Ok(())
+}
+
+// null values are left out of the values and marked by a definition level of 0
+fn write_optional<T: DataType>(
+    rg: &mut SerializedRowGroupWriter<File>,
+    values: Vec<Option<T::T>>,
+) -> Result<(), Box<dyn Error>> {
+    let def_levels = values
+        .iter()
+        .map(|v| v.is_some() as i16)
+        .collect::<Vec<i16>>();
+    let values = values.into_iter().flatten().collect::<Vec<T::T>>();
+    let mut col = rg
+        .next_column()?
+        .ok_or("more columns than the parquet schema")?;
+    col.typed::<T>()
+        .write_batch(&values, Some(&def_levels), None)?;
+    col.close()?;
+    Ok(())
+}
+
+// YYYY-MM-DD of a unix timestamp, from Howard Hinnant's civil_from_days
+fn utc_date(unix_time: i64) -> String {
+    let z = unix_time.div_euclid(86_400) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::swap;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::path::Path;
+
+    const DAY: i64 = 86_400;
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            name: "p/0".to_string(),
+            sig: "sig".to_string(),
+            slot: 1,
+        }
+    }
+
+    // the files of a day partition, by name
+    fn files(dir: &Path, day: &str) -> Vec<PathBuf> {
+        let mut files = std::fs::read_dir(dir.join(format!("date={}", day)))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<PathBuf>>();
+        files.sort();
+        files
+    }
+
+    fn parquet_rows(path: &Path) -> i64 {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader.metadata().file_metadata().num_rows()
+    }
+
+    #[test]
+    fn checkpointed_parquet_files_are_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = ExportSink::new(
+            dir.path().to_path_buf(),
+            ExportFormat::Parquet,
+            DEFAULT_ROW_GROUP_SIZE,
+            ExportFilter::default(),
+        )
+        .unwrap();
+
+        sink.write_batch(&[swap("a", 10, 1, 1), swap("b", 20, 1, 1)])
+            .unwrap();
+        sink.flush().unwrap();
+        sink.checkpoint(&checkpoint()).unwrap();
+        let first = files(dir.path(), "1970-01-01");
+        assert_eq!(first.len(), 1);
+        assert_eq!(parquet_rows(&first[0]), 2);
+
+        // the next batch goes to a new file of the same day
+        sink.write_batch(&[swap("c", 30, 1, 1)]).unwrap();
+        sink.checkpoint(&checkpoint()).unwrap();
+        let files = files(dir.path(), "1970-01-01");
+        assert_eq!(files.len(), 2);
+        assert_eq!(parquet_rows(&files[1]), 1);
+    }
+
+    #[test]
+    fn a_later_day_finishes_earlier_days() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = ExportSink::new(
+            dir.path().to_path_buf(),
+            ExportFormat::Parquet,
+            DEFAULT_ROW_GROUP_SIZE,
+            ExportFilter::default(),
+        )
+        .unwrap();
+
+        sink.write_batch(&[swap("a", 10, 1, 1), swap("b", DAY + 10, 1, 1)])
+            .unwrap();
+        assert_eq!(sink.partitions.len(), 1);
+        assert_eq!(parquet_rows(&files(dir.path(), "1970-01-01")[0]), 1);
+        assert!(sink.partitions.contains_key("1970-01-02"));
+    }
+
+    #[test]
+    fn csv_rows_are_readable_after_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = ExportSink::new(
+            dir.path().to_path_buf(),
+            ExportFormat::Csv,
+            DEFAULT_ROW_GROUP_SIZE,
+            ExportFilter::default(),
+        )
+        .unwrap();
+
+        sink.write_batch(&[swap("a", 10, 1, 1), swap("b", 20, 1, 1)])
+            .unwrap();
+        sink.flush().unwrap();
+        let path = &files(dir.path(), "1970-01-01")[0];
+        let rows = csv::Reader::from_path(path).unwrap().records().count();
+        assert_eq!(rows, 2);
+    }
+}
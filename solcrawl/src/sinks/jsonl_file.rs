@@ -0,0 +1,35 @@
+use crate::sinks::Sink;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// JsonlFileSink appends every item to a file as a line of JSON
+pub struct JsonlFileSink {
+    writer: BufWriter<File>,
+}
+
+impl JsonlFileSink {
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+impl<T: Serialize> Sink<T> for JsonlFileSink {
+    fn write_batch(&mut self, items: &[T]) -> Result<(), Box<dyn Error>> {
+        for item in items {
+            serde_json::to_writer(&mut self.writer, item)?;
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
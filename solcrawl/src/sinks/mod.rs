@@ -0,0 +1,43 @@
+use std::error::Error;
+
+pub mod archive;
+pub mod broadcast;
+pub mod export;
+pub mod jsonl_file;
+pub mod stdout;
+pub mod webhook;
+
+// Checkpoint marks the last transaction one source of a pipeline published in a batch
+// every sink has written and flushed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    // pipeline and source, see checkpoint_name, so several pipelines can share a store
+    pub name: String,
+    pub sig: String,
+    pub slot: u64,
+}
+
+// every source of a pipeline is checkpointed on its own, since a batch mixes the
+// transactions of all of them and only a single crawler can resume from a signature
+pub fn checkpoint_name(pipeline: &str, source: &str) -> String {
+    format!("{}/{}", pipeline, source)
+}
+
+// a source that pages every watched address on its own, like the historical crawler,
+// resumes each address from its own checkpoint
+pub fn cursor_checkpoint_name(pipeline: &str, source: &str, cursor: &str) -> String {
+    format!("{}/{}/{}", pipeline, source, cursor)
+}
+
+pub trait Sink<T> {
+    fn write_batch(&mut self, items: &[T]) -> Result<(), Box<dyn Error>>;
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    // called after a batch has been written and flushed by every sink of the pipeline
+    fn checkpoint(&mut self, _checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use crate::sinks::Sink;
+use serde::Serialize;
+use std::error::Error;
+use std::io::Write;
+
+// StdoutSink prints every item as a line of JSON
+#[derive(Clone, Default)]
+pub struct StdoutSink {}
+
+impl<T: Serialize> Sink<T> for StdoutSink {
+    fn write_batch(&mut self, items: &[T]) -> Result<(), Box<dyn Error>> {
+        let mut out = std::io::stdout().lock();
+        for item in items {
+            serde_json::to_writer(&mut out, item)?;
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,401 @@
+use crate::sinks::Sink;
+use hmac::{Hmac, Mac};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const SIGNATURE_HEADER: &str = "X-Solcrawl-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Solcrawl-Timestamp";
+pub const DELIVERY_HEADER: &str = "X-Solcrawl-Delivery";
+
+// longest pause between two attempts, however many retries are configured
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// longest a batch waits on retries, they run on the pipelines thread
+const DEFAULT_MAX_RETRY_TIME: Duration = Duration::from_secs(60);
+
+const SPOOL_EXTENSION: &str = "json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookMode {
+    // one request per item, the body is the item
+    Single,
+    // one request per batch, the body is an array of the items
+    Batch,
+}
+
+// WebhookSink posts items as JSON to a url. With a secret every request carries
+// X-Solcrawl-Timestamp and X-Solcrawl-Signature: sha256=<hex hmac of "<timestamp>.<body>">,
+// and X-Solcrawl-Delivery is a hash of the body so receivers can drop redeliveries.
+// Failed requests are retried with exponential backoff for at most max_retry_time per batch,
+// payloads that still fail are written to the spool directory and delivered in order
+// before any newer payload.
+// TODO dont print to std out - use a logger
+pub struct WebhookSink {
+    url: String,
+    client: Client,
+    mode: WebhookMode,
+    secret: Option<Vec<u8>>,
+    max_retries: u32,
+    backoff: Duration,
+    max_retry_time: Duration,
+    spool_dir: Option<PathBuf>,
+    spool_seq: u64,
+}
+
+impl WebhookSink {
+    pub fn new(url: String, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        let client = Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            url,
+            client,
+            mode: WebhookMode::Batch,
+            secret: None,
+            max_retries: 0,
+            backoff: Duration::from_secs(1),
+            max_retry_time: DEFAULT_MAX_RETRY_TIME,
+            spool_dir: None,
+            spool_seq: 0,
+        })
+    }
+
+    pub fn with_mode(mut self, mode: WebhookMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_secret(mut self, secret: String) -> Self {
+        self.secret = Some(secret.into_bytes());
+        self
+    }
+
+    // the first retry waits backoff, every later one twice as long as the one before
+    pub fn with_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
+    // retries that would end past max_retry_time after a batch started are not made,
+    // the failed payload is spooled or the batch fails right away
+    pub fn with_max_retry_time(mut self, max_retry_time: Duration) -> Self {
+        self.max_retry_time = max_retry_time;
+        self
+    }
+
+    // payloads left in the directory by an earlier run are delivered first
+    pub fn with_spool(mut self, spool_dir: PathBuf) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(&spool_dir)?;
+        self.spool_dir = Some(spool_dir);
+        let pending = self.spooled()?.len();
+        if pending > 0 {
+            println!("webhook {} has {} spooled payloads", self.url, pending);
+        }
+        Ok(self)
+    }
+
+    fn post(&self, body: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header(DELIVERY_HEADER, hex(&Sha256::digest(body)))
+            .body(body.to_vec());
+        if let Some(secret) = &self.secret {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret)?;
+            mac.update(timestamp.to_string().as_bytes());
+            mac.update(b".");
+            mac.update(body);
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(
+                    SIGNATURE_HEADER,
+                    format!("sha256={}", hex(&mac.finalize().into_bytes())),
+                );
+        }
+        request.send()?.error_for_status()?;
+        Ok(())
+    }
+
+    fn post_with_retries(&self, body: &[u8], deadline: Instant) -> Result<(), Box<dyn Error>> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match self.post(body) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.max_retries && Instant::now() + backoff <= deadline => {
+                    attempt += 1;
+                    println!(
+                        "webhook {} err - {} - retry {}/{} in {:?}",
+                        self.url, e, attempt, self.max_retries, backoff
+                    );
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // spooled payload files, oldest first
+    fn spooled(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let dir = match &self.spool_dir {
+            Some(dir) => dir,
+            None => return Ok(vec![]),
+        };
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|p| p.extension().and_then(|e| e.to_str()) == Some(SPOOL_EXTENSION));
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn spool(&mut self, body: &[u8]) -> Result<(), Box<dyn Error>> {
+        let dir = self.spool_dir.as_ref().ok_or("webhook has no spool")?;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        self.spool_seq += 1;
+        let name = format!("{:024}-{:06}", nanos, self.spool_seq % 1_000_000);
+        // written under another extension first so a crash never leaves half a payload
+        let tmp = dir.join(format!("{}.tmp", name));
+        fs::write(&tmp, body)?;
+        fs::rename(&tmp, dir.join(format!("{}.{}", name, SPOOL_EXTENSION)))?;
+        Ok(())
+    }
+
+    // delivers spooled payloads in order with one attempt each, returns whether the
+    // spool is empty afterwards
+    fn drain_spool(&mut self) -> Result<bool, Box<dyn Error>> {
+        for path in self.spooled()? {
+            let body = fs::read(&path)?;
+            if let Err(e) = self.post(&body) {
+                println!("webhook {} spool err - {}", self.url, e);
+                return Ok(false);
+            }
+            fs::remove_file(&path)?;
+        }
+        Ok(true)
+    }
+
+    fn deliver(&mut self, bodies: Vec<Vec<u8>>) -> Result<(), Box<dyn Error>> {
+        let deadline = Instant::now() + self.max_retry_time;
+        if self.spool_dir.is_none() {
+            for body in bodies {
+                self.post_with_retries(&body, deadline)?;
+            }
+            return Ok(());
+        }
+
+        // while older payloads are stuck newer ones queue behind them
+        let mut delivering = self.drain_spool()?;
+        for body in bodies {
+            if delivering {
+                match self.post_with_retries(&body, deadline) {
+                    Ok(()) => continue,
+                    Err(e) => {
+                        println!("webhook {} err - {} - spooling", self.url, e);
+                        delivering = false;
+                    }
+                }
+            }
+            self.spool(&body)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Serialize> Sink<T> for WebhookSink {
+    fn write_batch(&mut self, items: &[T]) -> Result<(), Box<dyn Error>> {
+        if items.is_empty() {
+            return Ok(());
+        }
+        let bodies = match self.mode {
+            WebhookMode::Batch => vec![serde_json::to_vec(items)?],
+            WebhookMode::Single => items
+                .iter()
+                .map(serde_json::to_vec)
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        self.deliver(bodies)
+    }
+
+    // a flush also retries the spool, so stuck payloads go out once the endpoint is back
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.spool_dir.is_some() {
+            self.drain_spool()?;
+        }
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tiny_http::{Response, Server};
+
+    // a request the test endpoint received, with the headers the sink sets
+    struct Received {
+        body: String,
+        timestamp: Option<String>,
+        signature: Option<String>,
+        delivery: Option<String>,
+    }
+
+    // answers every request with the next status of statuses, then with 200
+    fn endpoint(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        std::thread::spawn(move || {
+            let mut statuses = statuses.into_iter();
+            for mut request in server.incoming_requests() {
+                let header = |name: &str| {
+                    request
+                        .headers()
+                        .iter()
+                        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+                        .map(|h| h.value.to_string())
+                };
+                let timestamp = header(TIMESTAMP_HEADER);
+                let signature = header(SIGNATURE_HEADER);
+                let delivery = header(DELIVERY_HEADER);
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                log.lock().unwrap().push(Received {
+                    body,
+                    timestamp,
+                    signature,
+                    delivery,
+                });
+                let status = statuses.next().unwrap_or(200);
+                let _ = request.respond(Response::empty(status));
+            }
+        });
+        (url, received)
+    }
+
+    fn bodies(received: &Arc<Mutex<Vec<Received>>>) -> Vec<String> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.body.clone())
+            .collect()
+    }
+
+    fn sink(url: String) -> WebhookSink {
+        WebhookSink::new(url, Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn signs_requests_with_the_secret() {
+        let (url, received) = endpoint(vec![]);
+        let mut webhook = sink(url).with_secret("secret".to_string());
+        webhook.write_batch(&[1, 2]).unwrap();
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(request.body, "[1,2]");
+        let timestamp = request.timestamp.clone().unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(format!("{}.{}", timestamp, request.body).as_bytes());
+        let expected = format!("sha256={}", hex(&mac.finalize().into_bytes()));
+        assert_eq!(request.signature.as_deref(), Some(expected.as_str()));
+        let delivery = hex(&Sha256::digest(request.body.as_bytes()));
+        assert_eq!(request.delivery.as_deref(), Some(delivery.as_str()));
+    }
+
+    #[test]
+    fn unsigned_without_a_secret() {
+        let (url, received) = endpoint(vec![]);
+        sink(url).write_batch(&[1]).unwrap();
+
+        let received = received.lock().unwrap();
+        assert!(received[0].signature.is_none());
+        assert!(received[0].timestamp.is_none());
+    }
+
+    #[test]
+    fn retries_with_backoff() {
+        let (url, received) = endpoint(vec![500, 503]);
+        let mut webhook = sink(url).with_retries(3, Duration::from_millis(50));
+        let started = Instant::now();
+        webhook.write_batch(&[1]).unwrap();
+
+        // 50ms before the first retry and 100ms before the second
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(bodies(&received), vec!["[1]", "[1]", "[1]"]);
+    }
+
+    #[test]
+    fn fails_once_retries_are_used_up() {
+        let (url, received) = endpoint(vec![500, 500, 500]);
+        let mut webhook = sink(url).with_retries(2, Duration::from_millis(10));
+
+        assert!(webhook.write_batch(&[1]).is_err());
+        assert_eq!(bodies(&received).len(), 3);
+    }
+
+    #[test]
+    fn retries_stop_at_the_batch_retry_time() {
+        let (url, received) = endpoint(vec![500; 100]);
+        let mut webhook = sink(url)
+            .with_mode(WebhookMode::Single)
+            .with_retries(10, Duration::from_millis(50))
+            .with_max_retry_time(Duration::from_millis(200));
+        let started = Instant::now();
+
+        assert!(webhook.write_batch(&[1, 2, 3]).is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        // retried after 50 and 100ms, the next 200ms wait would end past the retry time
+        assert_eq!(bodies(&received).len(), 3);
+    }
+
+    #[test]
+    fn spools_failed_payloads_and_drains_them_in_order() {
+        let spool = tempfile::tempdir().unwrap();
+        let (url, received) = endpoint(vec![500, 500]);
+        let mut webhook = sink(url)
+            .with_mode(WebhookMode::Single)
+            .with_retries(1, Duration::from_millis(10))
+            .with_spool(spool.path().to_path_buf())
+            .unwrap();
+
+        // the first item fails its retry too, the ones after it queue behind it
+        webhook.write_batch(&[1, 2, 3]).unwrap();
+        assert_eq!(bodies(&received), vec!["1", "1"]);
+        assert_eq!(webhook.spooled().unwrap().len(), 3);
+
+        webhook.write_batch(&[4]).unwrap();
+        assert_eq!(bodies(&received), vec!["1", "1", "1", "2", "3", "4"]);
+        assert!(webhook.spooled().unwrap().is_empty());
+    }
+
+    #[test]
+    fn flush_drains_the_spool_of_an_earlier_run() {
+        let spool = tempfile::tempdir().unwrap();
+        let (url, received) = endpoint(vec![500]);
+        let mut failing = sink(url.clone())
+            .with_mode(WebhookMode::Single)
+            .with_spool(spool.path().to_path_buf())
+            .unwrap();
+        failing.write_batch(&[1, 2]).unwrap();
+        drop(failing);
+
+        let mut webhook = sink(url).with_spool(spool.path().to_path_buf()).unwrap();
+        Sink::<u32>::flush(&mut webhook).unwrap();
+        assert_eq!(bodies(&received), vec!["1", "1", "2"]);
+        assert!(webhook.spooled().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::extractors::jupiter_swap::SwapEvent;
+use serde_json::Value;
+use solana_account_decoder::parse_token::token_amount_to_ui_amount;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::v0::{LoadedAddresses, MessageAddressTableLookup};
+use solana_sdk::message::{v0, MessageHeader, VersionedMessage};
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::{
+    ConfirmedTransactionWithStatusMeta, EncodedConfirmedTransactionWithStatusMeta,
+    InnerInstruction, InnerInstructions, TransactionStatusMeta, TransactionTokenBalance,
+    TransactionWithStatusMeta, UiTransactionEncoding, VersionedTransactionWithStatusMeta,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tiny_http::{Response, Server};
+
+pub const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+pub const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+pub fn key(n: u8) -> Pubkey {
+    Pubkey::new_from_array([n; 32])
+}
+
+pub fn signature(n: u8) -> Signature {
+    Signature::from([n; 64])
+}
+
+// a swap of input usdc for output usdt, amounts in raw units of 6 decimals
+pub fn swap(sig: &str, block_time: i64, input_amount: u64, output_amount: u64) -> SwapEvent {
+    serde_json::from_value(serde_json::json!({
+        "sig": sig,
+        "slot": block_time as u64,
+        "block_time": block_time,
+        "trader": key(1).to_string(),
+        "fee": 5000,
+        "success": true,
+        "input_token": USDC,
+        "output_token": USDT,
+        "input_amount": input_amount,
+        "output_amount": output_amount,
+        "input_token_program": crate::tokens::TOKEN_PROGRAM,
+        "output_token_program": crate::tokens::TOKEN_PROGRAM,
+        "input_transfer_fee": 0,
+        "output_transfer_fee": 0,
+        "input_decimals": 6,
+        "output_decimals": 6,
+        "input_ui_amount": input_amount as f64 / 1e6,
+        "output_ui_amount": output_amount as f64 / 1e6,
+        "price": output_amount as f64 / input_amount as f64,
+        "inverse_price": input_amount as f64 / output_amount as f64,
+        "legs": [],
+    }))
+    .unwrap()
+}
+
+// a v0 transaction of the signer key(1) calling program key(3), which moves 1 usdc from
+// the signer's token account to a token account loaded from a lookup table.
+// account keys are key(1), key(2), key(3), the token program, then the loaded key(4)
+// and the usdc mint
+pub fn transaction(
+    sig: &Signature,
+    slot: u64,
+    encoding: UiTransactionEncoding,
+) -> EncodedConfirmedTransactionWithStatusMeta {
+    let token_program = Pubkey::new_from_array(spl_token::id().to_bytes());
+    let usdc = USDC.parse::<Pubkey>().unwrap();
+    let message = v0::Message {
+        header: MessageHeader {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 2,
+        },
+        account_keys: vec![key(1), key(2), key(3), token_program],
+        recent_blockhash: Hash::default(),
+        instructions: vec![CompiledInstruction::new_from_raw_parts(
+            2,
+            vec![7, 1, 2, 3],
+            vec![0, 1, 4, 5],
+        )],
+        address_table_lookups: vec![MessageAddressTableLookup {
+            account_key: key(9),
+            writable_indexes: vec![0],
+            readonly_indexes: vec![0],
+        }],
+    };
+    let transfer = spl_token::instruction::TokenInstruction::Transfer { amount: 1_000_000 };
+    let balance = |account_index: u8, amount: u64| TransactionTokenBalance {
+        account_index,
+        mint: USDC.to_string(),
+        ui_token_amount: token_amount_to_ui_amount(amount, 6),
+        owner: key(1).to_string(),
+        program_id: token_program.to_string(),
+    };
+    let meta = TransactionStatusMeta {
+        fee: 5000,
+        pre_balances: vec![10_000, 0, 0, 0, 0, 0],
+        post_balances: vec![5_000, 0, 0, 0, 0, 0],
+        inner_instructions: Some(vec![InnerInstructions {
+            index: 0,
+            instructions: vec![InnerInstruction {
+                instruction: CompiledInstruction::new_from_raw_parts(
+                    3,
+                    transfer.pack(),
+                    vec![1, 4, 0],
+                ),
+                stack_height: Some(2),
+            }],
+        }]),
+        log_messages: Some(vec![format!("Program {} invoke [1]", key(3))]),
+        pre_token_balances: Some(vec![balance(1, 3_000_000), balance(4, 0)]),
+        post_token_balances: Some(vec![balance(1, 2_000_000), balance(4, 1_000_000)]),
+        loaded_addresses: LoadedAddresses {
+            writable: vec![key(4)],
+            readonly: vec![usdc],
+        },
+        ..TransactionStatusMeta::default()
+    };
+    ConfirmedTransactionWithStatusMeta {
+        slot,
+        tx_with_meta: TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+            transaction: VersionedTransaction {
+                signatures: vec![*sig],
+                message: VersionedMessage::V0(message),
+            },
+            meta,
+        }),
+        block_time: Some(slot as i64),
+    }
+    .encode(encoding, Some(0))
+    .unwrap()
+}
+
+// a json rpc endpoint answering every call with the result handle returns for its method
+// and params, besides the version query the client makes first. once the returned flag is
+// set every call fails
+pub fn rpc_server<F>(handle: F) -> (String, Arc<AtomicBool>)
+where
+    F: Fn(&str, &Value) -> Value + Send + 'static,
+{
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", server.server_addr());
+    let stopped = Arc::new(AtomicBool::new(false));
+    let stop = stopped.clone();
+    std::thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            if stop.load(Ordering::SeqCst) {
+                let _ = request.respond(Response::empty(500));
+                continue;
+            }
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let call: Value = serde_json::from_str(&body).unwrap();
+            let result = match call["method"].as_str().unwrap_or("") {
+                "getVersion" => serde_json::json!({"solana-core": "1.18.26", "feature-set": 0}),
+                method => handle(method, &call["params"]),
+            };
+            let response = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": call["id"],
+                "result": result,
+            });
+            let _ = request.respond(Response::from_string(response.to_string()));
+        }
+    });
+    (url, stopped)
+}
@@ -0,0 +1,138 @@
+use crate::transaction_view::TokenBalance;
+use serde::Deserialize;
+use solana_client::rpc_client::RpcClient;
+use solana_program::program_pack::Pack;
+use solana_program::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MintInfo {
+    pub mint: String,
+    pub decimals: u8,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+}
+
+// MintRegistry caches mint decimals and token list metadata.
+// Decimals are learnt from the token balances of crawled transactions and otherwise
+// fetched from the mint account, symbol and name only come from a local token list.
+pub struct MintRegistry {
+    rpc_url: Option<String>,
+    decimals: RwLock<HashMap<String, u8>>,
+    metadata: RwLock<HashMap<String, TokenListEntry>>,
+}
+
+// subset of the solana token list format - {"tokens": [{"address", "symbol", "name", "decimals"}]}
+#[derive(Clone, Debug, Deserialize)]
+struct TokenList {
+    tokens: Vec<TokenListEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct TokenListEntry {
+    address: String,
+    symbol: Option<String>,
+    name: Option<String>,
+    decimals: Option<u8>,
+}
+
+impl MintRegistry {
+    // rpc_url is used to look up mints that have not been seen yet, pass None to stay offline
+    pub fn new(rpc_url: Option<String>) -> Self {
+        Self {
+            rpc_url,
+            decimals: RwLock::new(HashMap::new()),
+            metadata: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn load_token_list(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let list: TokenList = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let count = list.tokens.len();
+
+        let mut decimals = self
+            .decimals
+            .write()
+            .map_err(|_| "mint registry lock poisoned")?;
+        let mut metadata = self
+            .metadata
+            .write()
+            .map_err(|_| "mint registry lock poisoned")?;
+        for token in list.tokens {
+            if let Some(d) = token.decimals {
+                decimals.entry(token.address.clone()).or_insert(d);
+            }
+            metadata.insert(token.address.clone(), token);
+        }
+
+        Ok(count)
+    }
+
+    pub fn record_balances(&self, balances: &[TokenBalance]) {
+        if let Ok(mut decimals) = self.decimals.write() {
+            for balance in balances {
+                decimals.insert(balance.mint.clone(), balance.decimals);
+            }
+        }
+    }
+
+    pub fn get(&self, mint: &str) -> Result<MintInfo, Box<dyn Error>> {
+        let decimals = self.decimals(mint)?;
+        let metadata = self
+            .metadata
+            .read()
+            .map_err(|_| "mint registry lock poisoned")?
+            .get(mint)
+            .cloned();
+
+        Ok(MintInfo {
+            mint: mint.to_string(),
+            decimals,
+            symbol: metadata.as_ref().and_then(|m| m.symbol.clone()),
+            name: metadata.and_then(|m| m.name),
+        })
+    }
+
+    pub fn decimals(&self, mint: &str) -> Result<u8, Box<dyn Error>> {
+        let cached = self
+            .decimals
+            .read()
+            .map_err(|_| "mint registry lock poisoned")?
+            .get(mint)
+            .copied();
+        if let Some(decimals) = cached {
+            return Ok(decimals);
+        }
+
+        let decimals = self.fetch_decimals(mint)?;
+        self.decimals
+            .write()
+            .map_err(|_| "mint registry lock poisoned")?
+            .insert(mint.to_string(), decimals);
+        Ok(decimals)
+    }
+
+    // token 2022 mints share the classic mint layout, extensions are appended after it
+    fn fetch_decimals(&self, mint: &str) -> Result<u8, Box<dyn Error>> {
+        let rpc_url = self
+            .rpc_url
+            .clone()
+            .ok_or(format!("decimals for mint {} are unknown", mint))?;
+        let client = RpcClient::new(rpc_url);
+        let data = client.get_account_data(&Pubkey::from_str(mint)?)?;
+        if data.len() < spl_token::state::Mint::LEN {
+            return Err(format!("account {} is not a mint", mint).into());
+        }
+
+        let state =
+            spl_token::state::Mint::unpack_from_slice(&data[..spl_token::state::Mint::LEN])?;
+        Ok(state.decimals)
+    }
+}
+
+pub fn ui_amount(amount: u64, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
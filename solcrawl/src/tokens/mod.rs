@@ -0,0 +1,174 @@
+use crate::transaction_view::{InstructionView, TransactionView};
+use std::error::Error;
+
+pub mod mint_registry;
+
+pub const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_2022_PROGRAM: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+// spl token instruction tags, shared by both token programs
+const TRANSFER_TAG: u8 = 3;
+const TRANSFER_CHECKED_TAG: u8 = 12;
+// token 2022 instruction tags for TransferFeeExtension::TransferCheckedWithFee
+const TRANSFER_FEE_EXTENSION_TAG: u8 = 26;
+const TRANSFER_CHECKED_WITH_FEE_TAG: u8 = 1;
+
+// TransferFee is a fee withheld by a token 2022 mint with the transfer fee extension
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransferFee {
+    pub source: String,
+    pub mint: String,
+    pub destination: String,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+// TokenTransfer is a transfer between two token accounts, mint is only known when the
+// instruction names it, otherwise it has to be looked up from the token balances
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenTransfer {
+    pub source: String,
+    pub destination: String,
+    pub mint: Option<String>,
+    pub amount: u64,
+}
+
+// balances reported without a program id predate token 2022 and belong to the classic program
+pub fn token_program_or_default(program_id: &Option<String>) -> String {
+    program_id
+        .clone()
+        .unwrap_or_else(|| TOKEN_PROGRAM.to_string())
+}
+
+// collects the transfer fees of all top level and inner TransferCheckedWithFee instructions
+pub fn transfer_fees(view: &TransactionView) -> Result<Vec<TransferFee>, Box<dyn Error>> {
+    let mut fees = Vec::new();
+    let inner = view
+        .inner_instructions()
+        .iter()
+        .flat_map(|i| i.instructions.iter());
+    for ix in view.instructions().iter().chain(inner) {
+        if ix.program_id != TOKEN_2022_PROGRAM {
+            continue;
+        }
+        if let Some(fee) = transfer_fee(ix)? {
+            fees.push(fee);
+        }
+    }
+    Ok(fees)
+}
+
+fn transfer_fee(ix: &InstructionView) -> Result<Option<TransferFee>, Box<dyn Error>> {
+    if let Some(parsed) = &ix.parsed {
+        if parsed["type"] != "transferCheckedWithFee" {
+            return Ok(None);
+        }
+        let info = &parsed["info"];
+        let field = |name: &str| -> Result<String, Box<dyn Error>> {
+            Ok(info[name]
+                .as_str()
+                .ok_or(format!("transfer with fee is missing {}", name))?
+                .to_string())
+        };
+        let amount = |name: &str| -> Result<u64, Box<dyn Error>> {
+            Ok(info[name]["amount"]
+                .as_str()
+                .ok_or(format!("transfer with fee is missing {}", name))?
+                .parse::<u64>()?)
+        };
+        return Ok(Some(TransferFee {
+            source: field("source")?,
+            mint: field("mint")?,
+            destination: field("destination")?,
+            amount: amount("tokenAmount")?,
+            fee: amount("feeAmount")?,
+        }));
+    }
+
+    let data = match &ix.data {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    if data.len() < 19
+        || data[0] != TRANSFER_FEE_EXTENSION_TAG
+        || data[1] != TRANSFER_CHECKED_WITH_FEE_TAG
+    {
+        return Ok(None);
+    }
+    if ix.accounts.len() < 3 {
+        return Err("unexpected transfer with fee accounts".into());
+    }
+
+    // amount: u64, decimals: u8, fee: u64
+    let amount = u64::from_le_bytes(data[2..10].try_into()?);
+    let fee = u64::from_le_bytes(data[11..19].try_into()?);
+    Ok(Some(TransferFee {
+        source: ix.accounts[0].clone(),
+        mint: ix.accounts[1].clone(),
+        destination: ix.accounts[2].clone(),
+        amount,
+        fee,
+    }))
+}
+
+// decodes Transfer, TransferChecked and TransferCheckedWithFee instructions of either token program
+pub fn token_transfer(ix: &InstructionView) -> Result<Option<TokenTransfer>, Box<dyn Error>> {
+    if ix.program_id != TOKEN_PROGRAM && ix.program_id != TOKEN_2022_PROGRAM {
+        return Ok(None);
+    }
+    if let Some(fee) = transfer_fee(ix)? {
+        return Ok(Some(TokenTransfer {
+            source: fee.source,
+            destination: fee.destination,
+            mint: Some(fee.mint),
+            amount: fee.amount,
+        }));
+    }
+
+    if let Some(parsed) = &ix.parsed {
+        let info = &parsed["info"];
+        let field = |name: &str| -> Result<String, Box<dyn Error>> {
+            Ok(info[name]
+                .as_str()
+                .ok_or(format!("transfer is missing {}", name))?
+                .to_string())
+        };
+        let amount = match parsed["type"].as_str() {
+            Some("transfer") => info["amount"].as_str(),
+            Some("transferChecked") => info["tokenAmount"]["amount"].as_str(),
+            _ => return Ok(None),
+        };
+        return Ok(Some(TokenTransfer {
+            source: field("source")?,
+            destination: field("destination")?,
+            mint: info["mint"].as_str().map(|m| m.to_string()),
+            amount: amount.ok_or("transfer is missing amount")?.parse::<u64>()?,
+        }));
+    }
+
+    let data = match &ix.data {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    match data.first() {
+        // amount: u64
+        Some(&TRANSFER_TAG) if data.len() >= 9 && ix.accounts.len() >= 2 => {
+            Ok(Some(TokenTransfer {
+                source: ix.accounts[0].clone(),
+                destination: ix.accounts[1].clone(),
+                mint: None,
+                amount: u64::from_le_bytes(data[1..9].try_into()?),
+            }))
+        }
+        // amount: u64, decimals: u8
+        Some(&TRANSFER_CHECKED_TAG) if data.len() >= 10 && ix.accounts.len() >= 3 => {
+            Ok(Some(TokenTransfer {
+                source: ix.accounts[0].clone(),
+                destination: ix.accounts[2].clone(),
+                mint: Some(ix.accounts[1].clone()),
+                amount: u64::from_le_bytes(data[1..9].try_into()?),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,463 @@
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiInstruction, UiMessage,
+    UiParsedInstruction, UiTransactionStatusMeta, UiTransactionTokenBalance,
+};
+use std::error::Error;
+
+// TransactionView exposes the parts of a transaction the rest of solcrawl cares about,
+// independent of the encoding the transaction was fetched with.
+#[derive(Clone, Debug)]
+pub struct TransactionView {
+    slot: u64,
+    block_time: Option<i64>,
+    signatures: Vec<String>,
+    account_keys: Vec<String>,
+    instructions: Vec<InstructionView>,
+    inner_instructions: Vec<InnerInstructionsView>,
+    meta: Option<UiTransactionStatusMeta>,
+}
+
+#[derive(Clone, Debug)]
+pub struct InstructionView {
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    // raw instruction data, not available for instructions the rpc node parsed for us
+    pub data: Option<Vec<u8>>,
+    // only set for instructions returned with JsonParsed encoding
+    pub parsed: Option<serde_json::Value>,
+    pub stack_height: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct InnerInstructionsView {
+    // index of the top level instruction these were invoked from
+    pub index: u8,
+    pub instructions: Vec<InstructionView>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TokenBalance {
+    pub account_index: u8,
+    pub account: String,
+    pub mint: String,
+    pub owner: Option<String>,
+    pub program_id: Option<String>,
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+impl TransactionView {
+    pub fn new(tx: &EncodedConfirmedTransactionWithStatusMeta) -> Result<Self, Box<dyn Error>> {
+        let meta = tx.transaction.meta.clone();
+        let (signatures, account_keys, instructions) = match &tx.transaction.transaction {
+            EncodedTransaction::Json(ui_tx) => match &ui_tx.message {
+                UiMessage::Raw(msg) => {
+                    let account_keys = with_loaded_addresses(msg.account_keys.clone(), &meta);
+                    let instructions = msg
+                        .instructions
+                        .iter()
+                        .map(|ix| {
+                            compiled_instruction(
+                                ix.program_id_index,
+                                &ix.accounts,
+                                Some(bs58::decode(&ix.data).into_vec()?),
+                                ix.stack_height,
+                                &account_keys,
+                            )
+                        })
+                        .collect::<Result<Vec<InstructionView>, Box<dyn Error>>>()?;
+                    (ui_tx.signatures.clone(), account_keys, instructions)
+                }
+                UiMessage::Parsed(msg) => {
+                    let account_keys = msg
+                        .account_keys
+                        .iter()
+                        .map(|a| a.pubkey.clone())
+                        .collect::<Vec<String>>();
+                    let instructions = msg
+                        .instructions
+                        .iter()
+                        .map(|ix| ui_instruction(ix, &account_keys))
+                        .collect::<Result<Vec<InstructionView>, Box<dyn Error>>>()?;
+                    (ui_tx.signatures.clone(), account_keys, instructions)
+                }
+            },
+            EncodedTransaction::Accounts(list) => (
+                list.signatures.clone(),
+                list.account_keys.iter().map(|a| a.pubkey.clone()).collect(),
+                Vec::new(),
+            ),
+            EncodedTransaction::LegacyBinary(_) | EncodedTransaction::Binary(_, _) => {
+                let versioned = tx
+                    .transaction
+                    .transaction
+                    .decode()
+                    .ok_or("could not decode binary transaction")?;
+                versioned_parts(&versioned, &meta)?
+            }
+        };
+
+        let mut inner_instructions = Vec::new();
+        if let Some(meta) = &meta {
+            if let OptionSerializer::Some(inner) = &meta.inner_instructions {
+                for inner in inner {
+                    inner_instructions.push(InnerInstructionsView {
+                        index: inner.index,
+                        instructions: inner
+                            .instructions
+                            .iter()
+                            .map(|ix| ui_instruction(ix, &account_keys))
+                            .collect::<Result<Vec<InstructionView>, Box<dyn Error>>>()?,
+                    });
+                }
+            }
+        }
+
+        Ok(Self {
+            slot: tx.slot,
+            block_time: tx.block_time,
+            signatures,
+            account_keys,
+            instructions,
+            inner_instructions,
+            meta,
+        })
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    pub fn block_time(&self) -> Option<i64> {
+        self.block_time
+    }
+
+    pub fn signature(&self) -> Option<&str> {
+        self.signatures.first().map(|s| s.as_str())
+    }
+
+    // fee payer and first signer of the transaction
+    pub fn signer(&self) -> Option<&str> {
+        self.account_keys.first().map(|s| s.as_str())
+    }
+
+    // static account keys followed by any keys loaded from address lookup tables
+    pub fn account_keys(&self) -> &[String] {
+        &self.account_keys
+    }
+
+    pub fn instructions(&self) -> &[InstructionView] {
+        &self.instructions
+    }
+
+    pub fn inner_instructions(&self) -> &[InnerInstructionsView] {
+        &self.inner_instructions
+    }
+
+    pub fn meta(&self) -> Option<&UiTransactionStatusMeta> {
+        self.meta.as_ref()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.meta.as_ref().map(|m| m.err.is_none()).unwrap_or(false)
+    }
+
+    pub fn fee(&self) -> Option<u64> {
+        self.meta.as_ref().map(|m| m.fee)
+    }
+
+    pub fn log_messages(&self) -> Vec<String> {
+        match self.meta.as_ref().map(|m| &m.log_messages) {
+            Some(OptionSerializer::Some(logs)) => logs.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn pre_balances(&self) -> Vec<u64> {
+        self.meta
+            .as_ref()
+            .map(|m| m.pre_balances.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn post_balances(&self) -> Vec<u64> {
+        self.meta
+            .as_ref()
+            .map(|m| m.post_balances.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn pre_token_balances(&self) -> Result<Vec<TokenBalance>, Box<dyn Error>> {
+        let meta = self.meta.as_ref().ok_or("tx does not contain metadata")?;
+        match &meta.pre_token_balances {
+            OptionSerializer::Some(balances) => self.token_balances(balances),
+            _ => Err("does not have pre token balances".into()),
+        }
+    }
+
+    pub fn post_token_balances(&self) -> Result<Vec<TokenBalance>, Box<dyn Error>> {
+        let meta = self.meta.as_ref().ok_or("tx does not contain metadata")?;
+        match &meta.post_token_balances {
+            OptionSerializer::Some(balances) => self.token_balances(balances),
+            _ => Err("does not have post token balances".into()),
+        }
+    }
+
+    fn token_balances(
+        &self,
+        balances: &[UiTransactionTokenBalance],
+    ) -> Result<Vec<TokenBalance>, Box<dyn Error>> {
+        balances
+            .iter()
+            .map(|b| {
+                Ok(TokenBalance {
+                    account_index: b.account_index,
+                    account: self
+                        .account_keys
+                        .get(b.account_index as usize)
+                        .cloned()
+                        .ok_or("token balance account index out of range")?,
+                    mint: b.mint.clone(),
+                    owner: b.owner.clone().into(),
+                    program_id: b.program_id.clone().into(),
+                    amount: b.ui_token_amount.amount.parse::<u64>()?,
+                    decimals: b.ui_token_amount.decimals,
+                })
+            })
+            .collect()
+    }
+}
+
+type TransactionParts = (Vec<String>, Vec<String>, Vec<InstructionView>);
+
+fn versioned_parts(
+    tx: &VersionedTransaction,
+    meta: &Option<UiTransactionStatusMeta>,
+) -> Result<TransactionParts, Box<dyn Error>> {
+    let account_keys = with_loaded_addresses(
+        tx.message
+            .static_account_keys()
+            .iter()
+            .map(|k| k.to_string())
+            .collect(),
+        meta,
+    );
+    let instructions = tx
+        .message
+        .instructions()
+        .iter()
+        .map(|ix| {
+            compiled_instruction(
+                ix.program_id_index,
+                &ix.accounts,
+                Some(ix.data.clone()),
+                None,
+                &account_keys,
+            )
+        })
+        .collect::<Result<Vec<InstructionView>, Box<dyn Error>>>()?;
+    Ok((
+        tx.signatures.iter().map(|s| s.to_string()).collect(),
+        account_keys,
+        instructions,
+    ))
+}
+
+// versioned transactions only carry their static keys, the rest come from the metadata
+fn with_loaded_addresses(
+    mut account_keys: Vec<String>,
+    meta: &Option<UiTransactionStatusMeta>,
+) -> Vec<String> {
+    if let Some(meta) = meta {
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            account_keys.extend(loaded.writable.iter().cloned());
+            account_keys.extend(loaded.readonly.iter().cloned());
+        }
+    }
+    account_keys
+}
+
+fn compiled_instruction(
+    program_id_index: u8,
+    accounts: &[u8],
+    data: Option<Vec<u8>>,
+    stack_height: Option<u32>,
+    account_keys: &[String],
+) -> Result<InstructionView, Box<dyn Error>> {
+    let key = |i: u8| -> Result<String, Box<dyn Error>> {
+        Ok(account_keys
+            .get(i as usize)
+            .ok_or("instruction account index out of range")?
+            .clone())
+    };
+    Ok(InstructionView {
+        program_id: key(program_id_index)?,
+        accounts: accounts
+            .iter()
+            .map(|i| key(*i))
+            .collect::<Result<Vec<String>, Box<dyn Error>>>()?,
+        data,
+        parsed: None,
+        stack_height,
+    })
+}
+
+fn ui_instruction(
+    ix: &UiInstruction,
+    account_keys: &[String],
+) -> Result<InstructionView, Box<dyn Error>> {
+    match ix {
+        UiInstruction::Compiled(ix) => compiled_instruction(
+            ix.program_id_index,
+            &ix.accounts,
+            Some(bs58::decode(&ix.data).into_vec()?),
+            ix.stack_height,
+            account_keys,
+        ),
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) => Ok(InstructionView {
+            program_id: ix.program_id.clone(),
+            accounts: Vec::new(),
+            data: None,
+            parsed: Some(ix.parsed.clone()),
+            stack_height: ix.stack_height,
+        }),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => Ok(InstructionView {
+            program_id: ix.program_id.clone(),
+            accounts: ix.accounts.clone(),
+            data: Some(bs58::decode(&ix.data).into_vec()?),
+            parsed: None,
+            stack_height: ix.stack_height,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{key, signature, transaction, USDC};
+    use solana_transaction_status::UiTransactionEncoding;
+
+    const ENCODINGS: [UiTransactionEncoding; 3] = [
+        UiTransactionEncoding::Json,
+        UiTransactionEncoding::JsonParsed,
+        UiTransactionEncoding::Base64,
+    ];
+
+    fn view(encoding: UiTransactionEncoding) -> TransactionView {
+        TransactionView::new(&transaction(&signature(1), 100, encoding)).unwrap()
+    }
+
+    type Instruction = (String, Vec<String>, Option<Vec<u8>>, Option<u32>);
+
+    fn instruction(ix: &InstructionView) -> Instruction {
+        (
+            ix.program_id.clone(),
+            ix.accounts.clone(),
+            ix.data.clone(),
+            ix.stack_height,
+        )
+    }
+
+    fn balances(balances: Vec<TokenBalance>) -> Vec<(u8, String, String, u64, u8)> {
+        balances
+            .into_iter()
+            .map(|b| (b.account_index, b.account, b.mint, b.amount, b.decimals))
+            .collect()
+    }
+
+    #[test]
+    fn encodings_give_the_same_view() {
+        let token_program = spl_token::id().to_string();
+        let keys = vec![
+            key(1).to_string(),
+            key(2).to_string(),
+            key(3).to_string(),
+            token_program.clone(),
+            key(4).to_string(),
+            USDC.to_string(),
+        ];
+        for encoding in ENCODINGS {
+            let view = view(encoding);
+            assert_eq!(view.slot(), 100, "{:?}", encoding);
+            assert_eq!(view.block_time(), Some(100), "{:?}", encoding);
+            assert_eq!(
+                view.signature(),
+                Some(signature(1).to_string().as_str()),
+                "{:?}",
+                encoding
+            );
+            assert_eq!(view.signer(), Some(keys[0].as_str()), "{:?}", encoding);
+            // loaded addresses follow the static keys
+            assert_eq!(view.account_keys(), keys.as_slice(), "{:?}", encoding);
+            assert_eq!(
+                view.instructions()
+                    .iter()
+                    .map(instruction)
+                    .collect::<Vec<_>>(),
+                vec![(
+                    key(3).to_string(),
+                    vec![
+                        keys[0].clone(),
+                        keys[1].clone(),
+                        keys[4].clone(),
+                        USDC.to_string()
+                    ],
+                    Some(vec![7, 1, 2, 3]),
+                    None,
+                )],
+                "{:?}",
+                encoding
+            );
+
+            let inner = view.inner_instructions();
+            assert_eq!(inner.len(), 1, "{:?}", encoding);
+            assert_eq!(inner[0].index, 0, "{:?}", encoding);
+            assert_eq!(inner[0].instructions.len(), 1, "{:?}", encoding);
+            assert_eq!(inner[0].instructions[0].program_id, token_program);
+            assert_eq!(inner[0].instructions[0].stack_height, Some(2));
+
+            assert_eq!(
+                balances(view.pre_token_balances().unwrap()),
+                vec![
+                    (1, keys[1].clone(), USDC.to_string(), 3_000_000, 6),
+                    (4, keys[4].clone(), USDC.to_string(), 0, 6),
+                ],
+                "{:?}",
+                encoding
+            );
+            assert_eq!(
+                balances(view.post_token_balances().unwrap()),
+                vec![
+                    (1, keys[1].clone(), USDC.to_string(), 2_000_000, 6),
+                    (4, keys[4].clone(), USDC.to_string(), 1_000_000, 6),
+                ],
+                "{:?}",
+                encoding
+            );
+            assert_eq!(view.fee(), Some(5000));
+            assert!(view.is_success());
+        }
+    }
+
+    #[test]
+    fn parsed_inner_instructions_keep_the_parsed_form() {
+        let parsed = view(UiTransactionEncoding::JsonParsed);
+        let ix = &parsed.inner_instructions()[0].instructions[0];
+        assert_eq!(ix.parsed.as_ref().unwrap()["type"], "transfer");
+        assert!(ix.data.is_none());
+
+        // the raw encodings decode the same transfer
+        let json = view(UiTransactionEncoding::Json);
+        let base64 = view(UiTransactionEncoding::Base64);
+        assert_eq!(
+            instruction(&json.inner_instructions()[0].instructions[0]),
+            instruction(&base64.inner_instructions()[0].instructions[0])
+        );
+        assert_eq!(
+            json.inner_instructions()[0].instructions[0].accounts,
+            vec![key(2).to_string(), key(4).to_string(), key(1).to_string()]
+        );
+    }
+}